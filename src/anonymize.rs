@@ -0,0 +1,39 @@
+use std::collections::HashMap;
+
+use crate::Value;
+use crate::Value::{BinaryOperation, Int, Name, UnaryOperation, Variable};
+
+// Renames every name to `x0`, `x1`, ... in order of first appearance, so
+// that the same name keeps standing for the same value. Formatting is
+// normalized by the canonical display of the result.
+pub fn anonymize(value: &Value) -> Value {
+    rename(value, &mut HashMap::new())
+}
+
+fn rename(value: &Value, names: &mut HashMap<String, String>) -> Value {
+    match value {
+        BinaryOperation { operator, left, right } => {
+            let left = rename(left, names);
+            BinaryOperation { operator: *operator, left: Box::new(left), right: Box::new(rename(right, names)) }
+        }
+        UnaryOperation { operator, arg } => UnaryOperation { operator: *operator, arg: Box::new(rename(arg, names)) },
+        Name(name) => {
+            let count = names.len();
+            Name(names.entry(name.clone()).or_insert_with(|| format!("x{}", count)).clone())
+        }
+        Int(_) | Variable(_) => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::anonymize::anonymize;
+    use crate::parse_line;
+
+    #[test]
+    fn names() {
+        let anonymized = |line: &str| anonymize(&parse_line(line.split_whitespace()).unwrap()).to_string();
+        assert_eq!(anonymized("+ *  salary   bonus_rate ! salary"), "+ * x0 x1 fact x0");
+        assert_eq!(anonymized("- $3 42"), "- $3 42");
+    }
+}
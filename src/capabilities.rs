@@ -0,0 +1,128 @@
+use std::fmt;
+
+use crate::json;
+use crate::registry::{Operator, OPERATORS};
+use crate::{grid, repl, session, store};
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// What this build supports, for tools wrapping cwh to adapt to it rather
+// than guess from the version.
+#[derive(Debug, PartialEq)]
+pub struct Features {
+    pub version: &'static str,
+    // Canonical symbol, arity and aliases of every operator.
+    pub operators: Vec<(&'static str, usize, &'static [&'static str])>,
+    // Integer types results are computed in, with their width in bits.
+    pub numbers: Vec<(&'static str, u32)>,
+    // Input syntaxes with the command line flag selecting them.
+    pub syntaxes: Vec<(&'static str, &'static str)>,
+    pub protocols: Vec<&'static str>,
+    // Cargo features the build was compiled with.
+    pub cargo_features: Vec<&'static str>,
+    pub limits: Vec<(&'static str, usize)>,
+}
+
+pub fn features() -> Features {
+    Features {
+        version: VERSION,
+        operators: OPERATORS.iter()
+            .map(|doc| {
+                let arity = match doc.operator {
+                    Operator::Binary(_) => 2,
+                    Operator::Unary(_) => 1,
+                };
+                (doc.symbol(), arity, &doc.symbols[1..])
+            })
+            .collect(),
+        numbers: vec![("isize", isize::BITS)],
+        syntaxes: vec![("prefix", ""), ("prefix-multiple", "--quick"), ("bc", "--compat bc")],
+        protocols: vec!["simple-json", "events"],
+        cargo_features: [("clipboard", cfg!(feature = "clipboard"))].into_iter()
+            .filter_map(|(name, enabled)| enabled.then_some(name))
+            .collect(),
+        limits: vec![
+            ("grid_columns", grid::COLUMNS),
+            ("grid_rows", grid::ROWS),
+            ("macro_repeat", repl::MAX_REPEAT),
+            ("min_reference", store::MIN_REFERENCE),
+            ("session_version", session::VERSION as usize),
+            ("binary_session_version", session::BINARY_VERSION as usize),
+            ("goalseek_values", repl::MAX_SEARCH),
+            ("whatif_values", repl::MAX_SWEEP),
+        ],
+    }
+}
+
+impl Features {
+    pub fn to_json(&self) -> String {
+        let operators = self.operators.iter().map(|(symbol, arity, aliases)| {
+            json::object([
+                ("symbol", json::string(symbol)),
+                ("arity", arity.to_string()),
+                ("aliases", json::array(aliases.iter().map(|alias| json::string(alias)))),
+            ])
+        });
+        let numbers = self.numbers.iter().map(|(name, bits)| {
+            json::object([("type", json::string(name)), ("bits", bits.to_string())])
+        });
+        let syntaxes = self.syntaxes.iter().map(|(name, flag)| {
+            json::object([("name", json::string(name)), ("flag", json::string(flag))])
+        });
+        json::object([
+            ("version", json::string(self.version)),
+            ("operators", json::array(operators)),
+            ("numbers", json::array(numbers)),
+            ("syntaxes", json::array(syntaxes)),
+            ("protocols", json::array(self.protocols.iter().map(|protocol| json::string(protocol)))),
+            ("cargo_features", json::array(self.cargo_features.iter().map(|feature| json::string(feature)))),
+            ("limits", json::object(self.limits.iter().copied())),
+        ])
+    }
+}
+
+impl fmt::Display for Features {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Version: {}", self.version)?;
+        let operators: Vec<_> = self.operators.iter().map(|(symbol, _, _)| *symbol).collect();
+        writeln!(f, "Operators: {}", operators.join(" "))?;
+        let numbers: Vec<_> = self.numbers.iter().map(|(name, bits)| format!("{} ({} bits)", name, bits)).collect();
+        writeln!(f, "Numbers: {}", numbers.join(", "))?;
+        let syntaxes: Vec<_> = self.syntaxes.iter()
+            .map(|(name, flag)| match flag.is_empty() {
+                true => String::from(*name),
+                false => format!("{} ({})", name, flag),
+            })
+            .collect();
+        writeln!(f, "Syntaxes: {}", syntaxes.join(", "))?;
+        writeln!(f, "Protocols: {}", self.protocols.join(", "))?;
+        writeln!(f, "Cargo features: {}", match self.cargo_features.is_empty() {
+            true => String::from("(none)"),
+            false => self.cargo_features.join(", "),
+        })?;
+        write!(f, "Limits:")?;
+        for (name, limit) in &self.limits {
+            write!(f, "\n  {} {}", name, limit)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::capabilities::features;
+    use crate::json::{self, Json};
+
+    #[test]
+    fn json() {
+        let features = features();
+        let parsed = json::parse(&features.to_json()).unwrap();
+        assert_eq!(parsed.get("version"), Some(&Json::String(String::from(env!("CARGO_PKG_VERSION")))));
+        let Some(Json::Array(operators)) = parsed.get("operators") else { panic!("no operators") };
+        assert_eq!(operators.len(), 10);
+        let factorial = operators.iter().find(|operator| operator.get("symbol") == Some(&Json::String(String::from("fact"))));
+        assert_eq!(factorial.unwrap().get("aliases"), Some(&Json::Array(vec![Json::String(String::from("!"))])));
+        assert_eq!(parsed.get("limits").unwrap().get("grid_rows"), Some(&Json::Int(99)));
+        assert!(features.to_string().contains("\nOperators: / - * + abs fact neg pred sgn succ\n"));
+    }
+}
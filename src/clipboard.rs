@@ -0,0 +1,65 @@
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+use crate::{Error, ErrorCode};
+
+type Program = (&'static str, &'static [&'static str]);
+
+// Tried in order, the first one installed is used.
+const COPY: [Program; 3] = [("wl-copy", &[]), ("xclip", &["-selection", "clipboard"]), ("pbcopy", &[])];
+const PASTE: [Program; 3] = [
+    ("wl-paste", &["--no-newline"]),
+    ("xclip", &["-selection", "clipboard", "-o"]),
+    ("pbpaste", &[]),
+];
+
+pub fn copy(text: &str) -> Result<(), Error> {
+    run_first(&COPY, Some(text)).map(|_| ())
+}
+
+pub fn paste() -> Result<String, Error> {
+    run_first(&PASTE, None)
+}
+
+fn run_first(programs: &[Program], input: Option<&str>) -> Result<String, Error> {
+    for (program, args) in programs {
+        let child = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Err(Error::new(ErrorCode::Unavailable, format!("Cannot run {}: {}.", program, err))),
+        };
+        let mut stdin = child.stdin.take().unwrap();
+        if let Some(input) = input {
+            stdin.write_all(input.as_bytes())
+                .map_err(|err| Error::new(ErrorCode::Unavailable, format!("Cannot write to {}: {}.", program, err)))?;
+        }
+        drop(stdin);
+        let output = child.wait_with_output()
+            .map_err(|err| Error::new(ErrorCode::Unavailable, format!("Cannot run {}: {}.", program, err)))?;
+        if !output.status.success() {
+            return Err(Error::new(ErrorCode::Unavailable, format!("{} exited with {}.", program, output.status)));
+        }
+        return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+    }
+    let names: Vec<_> = programs.iter().map(|(program, _)| *program).collect();
+    Err(Error::new(ErrorCode::Unavailable, format!("No clipboard program found, install one of {}.", names.join(", "))))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clipboard::run_first;
+    use crate::ErrorCode;
+
+    #[test]
+    fn programs() {
+        assert_eq!(run_first(&[("cwh-missing", &[]), ("cat", &[])], Some("42")), Ok(String::from("42")));
+        assert_eq!(run_first(&[("cwh-missing", &[])], None).unwrap_err().code, ErrorCode::Unavailable);
+        assert_eq!(run_first(&[("false", &[])], None).unwrap_err().code, ErrorCode::Unavailable);
+    }
+}
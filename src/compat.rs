@@ -0,0 +1,153 @@
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use crate::BinaryOperator::{Division, Minus, Multiplication, Plus};
+use crate::UnaryOperator::Negative;
+use crate::Value::{BinaryOperation, Int, UnaryOperation, Variable};
+use crate::{evaluate_value, BinaryOperator, Error, ErrorCode, Value};
+
+// Parses the infix subset of bc shared with cwh: integers, `+ - * /`,
+// unary minus, parentheses and `last` (or `.`) for the previous result.
+// The result is a regular expression tree, evaluated by the usual rules.
+struct Infix<'a> {
+    line: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    history: usize,
+}
+
+impl Infix<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, char)| char.is_whitespace()).is_some() {}
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.peek().map(|(_, char)| *char)
+    }
+
+    fn binary(
+        &mut self,
+        operators: &[(char, BinaryOperator)],
+        operand: fn(&mut Self) -> Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        let mut left = operand(self)?;
+        while let Some(operator) = self.peek().and_then(|next| {
+            operators.iter().find(|(char, _)| *char == next).map(|(_, operator)| *operator)
+        }) {
+            self.chars.next();
+            let right = operand(self)?;
+            left = BinaryOperation { operator, left: Box::new(left), right: Box::new(right) };
+        }
+        Ok(left)
+    }
+
+    fn expression(&mut self) -> Result<Value, Error> {
+        self.binary(&[('+', Plus), ('-', Minus)], Self::term)
+    }
+
+    fn term(&mut self) -> Result<Value, Error> {
+        self.binary(&[('*', Multiplication), ('/', Division)], Self::factor)
+    }
+
+    fn factor(&mut self) -> Result<Value, Error> {
+        match self.peek() {
+            Some('-') => {
+                self.chars.next();
+                Ok(UnaryOperation { operator: Negative, arg: Box::new(self.factor()?) })
+            }
+            Some('+') => {
+                self.chars.next();
+                self.factor()
+            }
+            Some('(') => {
+                self.chars.next();
+                let value = self.expression()?;
+                match self.chars.next() {
+                    Some((_, ')')) => Ok(value),
+                    Some((idx, _)) => Err(self.unexpected(idx)),
+                    None => Err(Error::new(ErrorCode::EndOfInput, "Expected ')' at the end of input.")),
+                }
+            }
+            Some(_) => self.atom(),
+            None => Err(Error::new(ErrorCode::EndOfInput, "Expected a value at the end of input.")),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Value, Error> {
+        let start = self.chars.peek().map_or(self.line.len(), |(idx, _)| *idx);
+        while self.chars.next_if(|(_, char)| char.is_alphanumeric() || *char == '.').is_some() {}
+        let end = self.chars.peek().map_or(self.line.len(), |(idx, _)| *idx);
+        match &self.line[start..end] {
+            "" => Err(self.unexpected(start)),
+            "last" | "." => match self.history.checked_sub(1) {
+                Some(idx) => Ok(Variable(idx)),
+                None => Err(Error::new(ErrorCode::InvalidVariableIndex, "There is no last result yet.")),
+            },
+            token => token.parse().map(Int)
+                .map_err(|_| Error::new(ErrorCode::UnexpectedInput, format!("Unexpected input '{}'.", token))),
+        }
+    }
+
+    fn unexpected(&self, idx: usize) -> Error {
+        let token = self.line[idx..].chars().next().unwrap_or_default();
+        Error::new(ErrorCode::UnexpectedInput, format!("Unexpected input '{}'.", token))
+    }
+}
+
+pub fn parse_infix(line: &str, history: usize) -> Result<Value, Error> {
+    let mut infix = Infix { line, chars: line.char_indices().peekable(), history };
+    let value = infix.expression()?;
+    match infix.peek() {
+        None => Ok(value),
+        Some(_) => {
+            let rest = &line[infix.chars.peek().unwrap().0..];
+            Err(Error::new(ErrorCode::TrailingInput, format!("Expected end of statement, instead found '{}'.", rest)))
+        }
+    }
+}
+
+// Evaluates a line of bc input, made of statements separated by `;`, with
+// `#` starting a comment. Every result is pushed to history in turn.
+pub fn bc_line(line: &str, history: &mut Vec<isize>) -> Vec<Result<isize, Error>> {
+    let line = line.split_once('#').map_or(line, |(code, _)| code);
+    line.split(';')
+        .filter(|statement| !statement.trim().is_empty())
+        .map(|statement| {
+            let result = evaluate_value(&parse_infix(statement, history.len())?, history)?;
+            history.push(result);
+            Ok(result)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compat::{bc_line, parse_infix};
+    use crate::ErrorCode;
+
+    #[test]
+    fn infix() {
+        let show = |line: &str| parse_infix(line, 2).map(|value| value.to_string());
+        assert_eq!(show("1 + 2 * 3"), Ok(String::from("+ 1 * 2 3")));
+        assert_eq!(show("(1 + 2) * -3"), Ok(String::from("* + 1 2 neg 3")));
+        assert_eq!(show("10 - 4 - 3"), Ok(String::from("- - 10 4 3")));
+        assert_eq!(show("last / 2 + ."), Ok(String::from("+ / $1 2 $1")));
+        let code = |line: &str| parse_infix(line, 0).unwrap_err().code;
+        assert_eq!(code("(1 + 2"), ErrorCode::EndOfInput);
+        assert_eq!(code("1 +"), ErrorCode::EndOfInput);
+        assert_eq!(code("1 2"), ErrorCode::TrailingInput);
+        assert_eq!(code("x + 1"), ErrorCode::UnexpectedInput);
+        assert_eq!(code("1 % 2"), ErrorCode::TrailingInput);
+        assert_eq!(code("last"), ErrorCode::InvalidVariableIndex);
+    }
+
+    #[test]
+    fn statements() {
+        let mut history = Vec::new();
+        assert_eq!(bc_line("2^1", &mut history)[0].as_ref().unwrap_err().code, ErrorCode::TrailingInput);
+        assert_eq!(bc_line("7 / 2; last * 3 # comment", &mut history), [Ok(3), Ok(9)]);
+        assert_eq!(bc_line("", &mut history), []);
+        assert_eq!(bc_line("1 / 0", &mut history)[0].as_ref().unwrap_err().code, ErrorCode::DivisionByZero);
+        assert_eq!(history, [3, 9]);
+    }
+}
@@ -0,0 +1,125 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::script::{parse_string, strip_comment};
+use crate::settings::Settings;
+use crate::{Error, ErrorCode};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Filter {
+    // Replaces every occurrence of a text, like a currency symbol.
+    Replace { from: String, to: String },
+    // Replaces whole tokens only, like `x` for `*`.
+    Token { from: String, to: String },
+}
+
+// Rewrites input before it is parsed, filters applying in order.
+pub fn apply_filters(filters: &[Filter], line: &str) -> String {
+    filters.iter().fold(String::from(line), |line, filter| match filter {
+        Filter::Replace { from, to } => line.replace(from.as_str(), to),
+        Filter::Token { from, to } => line.split_whitespace()
+            .map(|token| if token == from { to.as_str() } else { token })
+            .collect::<Vec<_>>()
+            .join(" "),
+    })
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    pub settings: Settings,
+    pub filters: Vec<Filter>,
+}
+
+// Splits `"<from>" "<to>"`.
+fn parse_strings(rest: &str) -> Option<(&str, &str)> {
+    let inner = rest.strip_prefix('"')?;
+    let end = inner.find('"')?;
+    Some((&inner[..end], parse_string(inner[end + 1..].trim())?))
+}
+
+impl Config {
+    // $CWH_CONFIG, else the XDG configuration directory.
+    pub fn path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os("CWH_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+        let dir = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(dir.join("cwh").join("config"))
+    }
+
+    pub fn parse(text: &str) -> Result<Config, Error> {
+        let mut config = Config::default();
+        for (idx, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            let invalid = |message: String| {
+                Error::new(ErrorCode::InvalidConfig, format!("Line {}: {}", idx + 1, message))
+            };
+            let (directive, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+            match directive {
+                "" => {}
+                "set" => {
+                    let (name, value) = rest.split_once(char::is_whitespace)
+                        .ok_or_else(|| invalid(String::from("Expected 'set <name> <value>'.")))?;
+                    config.settings.set(name, value.trim()).map_err(|err| invalid(err.message))?;
+                }
+                "filter" => {
+                    let (from, to) = parse_strings(rest)
+                        .filter(|(from, _)| !from.is_empty())
+                        .ok_or_else(|| invalid(String::from("Expected 'filter \"<from>\" \"<to>\"'.")))?;
+                    config.filters.push(Filter::Replace { from: String::from(from), to: String::from(to) });
+                }
+                "token" => {
+                    let [from, to] = rest.split_whitespace().collect::<Vec<_>>()[..] else {
+                        return Err(invalid(String::from("Expected 'token <from> <to>'.")));
+                    };
+                    config.filters.push(Filter::Token { from: String::from(from), to: String::from(to) });
+                }
+                directive => return Err(invalid(format!("Unknown directive '{}'.", directive))),
+            }
+        }
+        Ok(config)
+    }
+
+    // A missing file is the default configuration.
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        match fs::read_to_string(path) {
+            Ok(text) => Config::parse(&text)
+                .map_err(|err| Error::new(err.code, format!("{}: {}", path.display(), err.message))),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(err) => Err(Error::new(ErrorCode::ReadFailed, format!("Cannot read '{}': {}.", path.display(), err))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{apply_filters, Config, Filter};
+    use crate::ErrorCode;
+
+    #[test]
+    fn parse() {
+        let config = Config::parse("; pasted prices\nset grouping ,\nfilter \"€\" \"\" ; euros\n\ntoken x *\n").unwrap();
+        assert_eq!(config.settings.grouping, Some(','));
+        assert_eq!(config.filters, [
+            Filter::Replace { from: String::from("€"), to: String::new() },
+            Filter::Token { from: String::from("x"), to: String::from("*") },
+        ]);
+        assert_eq!(apply_filters(&config.filters, "x 3€  €2 x2"), "* 3 2 x2");
+
+        for text in ["color on", "set pager", "set pager maybe", "filter € \"\"", "filter \"\" \"x\"", "token x"] {
+            let err = Config::parse(text).unwrap_err();
+            assert_eq!(err.code, ErrorCode::InvalidConfig);
+            assert!(err.message.starts_with("Line 1: "));
+        }
+    }
+
+    #[test]
+    fn missing() {
+        let path = std::env::temp_dir().join(format!("cwh-missing-{}", std::process::id()));
+        assert_eq!(Config::load(&path), Ok(Config::default()));
+    }
+}
@@ -0,0 +1,156 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::{evaluate_with_cancel, parse_line, CancelToken, Context, Error, ErrorCode};
+
+// Stack the worker thread gets for every token allowed on a line, on top of
+// the default, as parsing, evaluating and dropping an expression recurse once
+// per level of nesting.
+const STACK_PER_TOKEN: usize = 16 * 1024;
+const BASE_STACK: usize = 2 * 1024 * 1024;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Limits {
+    // Bounds the nesting depth as well, the worker's stack being sized for it.
+    pub max_tokens: usize,
+    pub timeout: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits { max_tokens: 10_000, timeout: Duration::from_secs(5) }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Completed { results: usize, errors: usize },
+    Panicked(String),
+    TimedOut,
+}
+
+impl Outcome {
+    pub fn is_failure(&self) -> bool {
+        !matches!(self, Outcome::Completed { .. })
+    }
+}
+
+fn evaluate_lines(text: &str, limits: &Limits, token: &CancelToken) -> Outcome {
+    let mut history = Vec::new();
+    let names = Default::default();
+    let mut errors = 0;
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let result = if line.split_whitespace().nth(limits.max_tokens).is_some() {
+            Err(Error::new(
+                ErrorCode::InputTooLong,
+                format!("Expected at most {} tokens on a line.", limits.max_tokens),
+            ))
+        } else {
+            parse_line(line.split_whitespace())
+                .and_then(|value| evaluate_with_cancel(&value, &Context::new(&history, &names), token))
+        };
+        match result {
+            Ok(int) => history.push(int),
+            Err(err) if err.code == ErrorCode::Cancelled => return Outcome::TimedOut,
+            Err(_) => errors += 1,
+        }
+    }
+    Outcome::Completed { results: history.len(), errors }
+}
+
+// Replays the lines of a corpus entry on a separate thread, so that a panic
+// or a runaway evaluation is reported instead of taking the caller down.
+pub fn replay(text: String, limits: Limits) -> Outcome {
+    replay_with(text, limits, evaluate_lines)
+}
+
+fn replay_with(text: String, limits: Limits, evaluate: fn(&str, &Limits, &CancelToken) -> Outcome) -> Outcome {
+    let token = CancelToken::new();
+    let (sender, receiver) = mpsc::channel();
+    let worker = {
+        let token = token.clone();
+        let stack_size = limits.max_tokens.saturating_mul(STACK_PER_TOKEN).saturating_add(BASE_STACK);
+        thread::Builder::new().stack_size(stack_size).spawn(move || {
+            let outcome = evaluate(&text, &limits, &token);
+            let _ = sender.send(());
+            outcome
+        })
+    };
+    let worker = match worker {
+        Ok(worker) => worker,
+        Err(err) => return Outcome::Panicked(format!("Cannot start the worker: {}.", err)),
+    };
+    match receiver.recv_timeout(limits.timeout) {
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            token.cancel();
+            Outcome::TimedOut
+        }
+        Ok(()) | Err(mpsc::RecvTimeoutError::Disconnected) => match worker.join() {
+            Ok(outcome) => outcome,
+            Err(payload) => Outcome::Panicked(
+                payload.downcast_ref::<&str>().map(|str| String::from(*str))
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| String::from("unknown panic")),
+            ),
+        },
+    }
+}
+
+pub fn entries(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let read_failed = |err| Error::new(ErrorCode::ReadFailed, format!("Cannot read '{}': {}.", dir.display(), err));
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir).map_err(read_failed)? {
+        let path = entry.map_err(read_failed)?.path();
+        if path.is_file() {
+            entries.push(path);
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::corpus::{replay, replay_with, Limits, Outcome};
+
+    #[test]
+    fn completed() {
+        let outcome = replay(String::from("+ 3 2\n\n/ $0 0\n* $0 2\n"), Limits::default());
+        assert_eq!(outcome, Outcome::Completed { results: 2, errors: 1 });
+    }
+
+    #[test]
+    fn panicked() {
+        let outcome = replay_with(String::from("fact 30\n"), Limits::default(), |_, _, _| panic!("overflow"));
+        assert_eq!(outcome, Outcome::Panicked(String::from("overflow")));
+        assert_eq!(replay(String::from("fact 30\n"), Limits::default()), Outcome::Completed { results: 0, errors: 1 });
+    }
+
+    #[test]
+    fn too_long() {
+        let text = format!("{}0\n", "succ ".repeat(1_000_000));
+        let limits = Limits { max_tokens: 1000, ..Limits::default() };
+        assert_eq!(replay(text, limits), Outcome::Completed { results: 0, errors: 1 });
+    }
+
+    #[test]
+    fn deeply_nested() {
+        let limits = Limits::default();
+        let text = format!("{}0\n", "succ ".repeat(limits.max_tokens - 1));
+        assert_eq!(replay(text, limits), Outcome::Completed { results: 1, errors: 0 });
+        let text = format!("{}0\n", "neg ".repeat(limits.max_tokens - 1));
+        assert_eq!(replay(text, limits), Outcome::Completed { results: 1, errors: 0 });
+    }
+
+    #[test]
+    fn timed_out() {
+        let text = "+ 1 1\n".repeat(1_000_000);
+        let limits = Limits { timeout: Duration::ZERO, ..Limits::default() };
+        assert_eq!(replay(text, limits), Outcome::TimedOut);
+    }
+}
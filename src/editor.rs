@@ -0,0 +1,49 @@
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{Error, ErrorCode};
+
+// $VISUAL takes precedence over $EDITOR, as in most Unix tools.
+pub fn editor() -> String {
+    env::var("VISUAL").or_else(|_| env::var("EDITOR")).unwrap_or_else(|_| String::from("vi"))
+}
+
+pub fn edit(initial: &str) -> Result<String, Error> {
+    edit_with(&editor(), initial)
+}
+
+// The editor goes through the shell so that it may carry arguments, like
+// `code --wait`.
+pub fn edit_with(editor: &str, initial: &str) -> Result<String, Error> {
+    static EDITS: AtomicUsize = AtomicUsize::new(0);
+    let edit = EDITS.fetch_add(1, Ordering::Relaxed);
+    let path = env::temp_dir().join(format!("cwh-edit-{}-{}.cwh", std::process::id(), edit));
+    fs::write(&path, format!("{}\n", initial))
+        .map_err(|err| Error::new(ErrorCode::WriteFailed, format!("Cannot write '{}': {}.", path.display(), err)))?;
+    let status = Command::new("sh")
+        .args(["-c", &format!("{} \"$1\"", editor), "sh"])
+        .arg(&path)
+        .status();
+    let text = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+    let status = status
+        .map_err(|err| Error::new(ErrorCode::Unavailable, format!("Cannot run editor '{}': {}.", editor, err)))?;
+    if !status.success() {
+        return Err(Error::new(ErrorCode::Unavailable, format!("Editor '{}' exited with {}.", editor, status)));
+    }
+    text.map_err(|err| Error::new(ErrorCode::ReadFailed, format!("Cannot read '{}': {}.", path.display(), err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::editor::edit_with;
+    use crate::ErrorCode;
+
+    #[test]
+    fn edit() {
+        assert_eq!(edit_with("sed -i s/3/4/", "+ 3\n2"), Ok(String::from("+ 4\n2\n")));
+        assert_eq!(edit_with("false", "").unwrap_err().code, ErrorCode::Unavailable);
+    }
+}
@@ -0,0 +1,573 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::ErrorCode::{
+    AssertionFailed, BinaryArity, Cancelled, CannotRefactor, CaptureFailed, CircularReference,
+    DivisionByZero, EndOfInput, IncludeCycle, InputTooLong, InvalidCommand, InvalidConfig,
+    InvalidDirective, InvalidExercises, InvalidLiteral, InvalidParameters, InvalidRequest,
+    InvalidSession, InvalidSessionName, InvalidSetting, InvalidTemplate, InvalidVariableIndex,
+    InvalidVariableName, NegativeFactorial, Overflow, Raised, ReadFailed, StackUnderflow,
+    TrailingInput, UnaryArity, Unavailable, UnexpectedInput, UnknownName, UnknownReference,
+    UnsupportedVersion, WriteFailed,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    UnexpectedInput,
+    EndOfInput,
+    InvalidVariableName,
+    BinaryArity,
+    UnaryArity,
+    TrailingInput,
+    InvalidVariableIndex,
+    DivisionByZero,
+    NegativeFactorial,
+    Cancelled,
+    ReadFailed,
+    IncludeCycle,
+    InvalidDirective,
+    Raised,
+    UnknownName,
+    InvalidParameters,
+    InvalidTemplate,
+    CaptureFailed,
+    InvalidCommand,
+    InputTooLong,
+    InvalidSession,
+    UnsupportedVersion,
+    WriteFailed,
+    UnknownReference,
+    InvalidSessionName,
+    InvalidSetting,
+    StackUnderflow,
+    Unavailable,
+    InvalidConfig,
+    Overflow,
+    CannotRefactor,
+    InvalidRequest,
+    CircularReference,
+    AssertionFailed,
+    InvalidExercises,
+    InvalidLiteral,
+}
+
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 36] = [
+        UnexpectedInput,
+        EndOfInput,
+        InvalidVariableName,
+        BinaryArity,
+        UnaryArity,
+        TrailingInput,
+        InvalidVariableIndex,
+        DivisionByZero,
+        NegativeFactorial,
+        Cancelled,
+        ReadFailed,
+        IncludeCycle,
+        InvalidDirective,
+        Raised,
+        UnknownName,
+        InvalidParameters,
+        InvalidTemplate,
+        CaptureFailed,
+        InvalidCommand,
+        InputTooLong,
+        InvalidSession,
+        UnsupportedVersion,
+        WriteFailed,
+        UnknownReference,
+        InvalidSessionName,
+        InvalidSetting,
+        StackUnderflow,
+        Unavailable,
+        InvalidConfig,
+        Overflow,
+        CannotRefactor,
+        InvalidRequest,
+        CircularReference,
+        AssertionFailed,
+        InvalidExercises,
+        InvalidLiteral,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnexpectedInput => "E0001",
+            EndOfInput => "E0002",
+            InvalidVariableName => "E0003",
+            BinaryArity => "E0004",
+            UnaryArity => "E0005",
+            TrailingInput => "E0006",
+            InvalidVariableIndex => "E0101",
+            DivisionByZero => "E0102",
+            NegativeFactorial => "E0103",
+            Cancelled => "E0104",
+            ReadFailed => "E0201",
+            IncludeCycle => "E0202",
+            InvalidDirective => "E0203",
+            Raised => "E0204",
+            UnknownName => "E0105",
+            InvalidParameters => "E0205",
+            InvalidTemplate => "E0206",
+            CaptureFailed => "E0207",
+            InvalidCommand => "E0301",
+            InputTooLong => "E0007",
+            InvalidSession => "E0208",
+            UnsupportedVersion => "E0209",
+            WriteFailed => "E0210",
+            UnknownReference => "E0008",
+            InvalidSessionName => "E0302",
+            InvalidSetting => "E0303",
+            StackUnderflow => "E0304",
+            Unavailable => "E0305",
+            InvalidConfig => "E0211",
+            Overflow => "E0106",
+            CannotRefactor => "E0212",
+            InvalidRequest => "E0306",
+            CircularReference => "E0107",
+            AssertionFailed => "E0213",
+            InvalidExercises => "E0214",
+            InvalidLiteral => "E0009",
+        }
+    }
+
+    pub fn explanation(&self) -> &'static str {
+        match self {
+            UnexpectedInput => r#"An unknown token was found where a value was expected.
+
+Values are integers like `42` or `-7`, history variables like `$0`,
+or an operator followed by its arguments. Anything else is rejected:
+
+    # + 3 two%
+    Error[E0001]: Unexpected input 'two%'.
+
+Check the spelling of operators; `:help` lists all of them."#,
+            EndOfInput => r#"The input ended while a value was still expected.
+
+This happens when a line contains nothing but whitespace, or when
+an empty expression is passed to the library:
+
+    #
+    Error[E0002]: Expected arguments at the end of input.
+
+Operators missing some of their arguments report E0004 or E0005."#,
+            InvalidVariableName => r#"A history variable does not have a numeric name.
+
+History variables are written as `$` followed by the index of
+a previous result, counting from zero:
+
+    # $first
+    Error[E0003]: Expected valid number as a variable name, instead got '$first'.
+    # $0
+    13"#,
+            BinaryArity => r#"A binary operator did not get two arguments.
+
+The calculator uses prefix notation, so both arguments follow the
+operator:
+
+    # * 6
+    Error[E0004]: Binary operator '*' expected two arguments.
+    # * 6 9
+    54"#,
+            UnaryArity => r#"A unary operator did not get its argument.
+
+Unary operators like `abs`, `neg` or `fact` take exactly one
+argument written after them:
+
+    # neg
+    Error[E0005]: Unary operator 'neg' expected an argument.
+    # neg 5
+    -5"#,
+            TrailingInput => r#"The line continues after a complete expression.
+
+Only one expression is evaluated per line. Extra tokens usually mean
+an operator is missing at the start:
+
+    # 3 2
+    Error[E0006]: Expected end of line, instead found '2'.
+    # + 3 2
+    5
+
+In quick-calc mode, started with `cwh --quick`, the remaining tokens
+are evaluated as the next expressions instead."#,
+            InvalidVariableIndex => r#"A history variable refers to a result that does not exist.
+
+History is indexed from zero, so after two results only `$0` and
+`$1` are valid:
+
+    # 1
+    1
+    # 2
+    2
+    # $2
+    Error[E0101]: Invalid variable index '2'."#,
+            DivisionByZero => r#"The divisor of `/` evaluated to zero.
+
+Integer division by zero has no result. This is often caused by
+a history variable holding zero, or by integer division truncating
+an intermediate result to zero:
+
+    # / 10 - 3 3
+    Error[E0102]: Division by zero.
+    # / 10 / 1 2
+    Error[E0102]: Division by zero."#,
+            NegativeFactorial => r#"The factorial was asked for a negative number.
+
+`fact` (or `!`) is only defined for non-negative integers, 0! being 1:
+
+    # fact -3
+    Error[E0103]: Expected a non-negative number as an argument to factorial.
+    # fact 0
+    1"#,
+            Cancelled => r#"The evaluation was cancelled before it finished.
+
+Embedders of the library can cancel a running evaluation through
+a cancellation token, for example when a user presses a stop button.
+No result is recorded in history."#,
+            ReadFailed => r#"A script or one of its includes could not be read.
+
+Included files are resolved relative to the directory of the file
+containing the `include` line:
+
+    ; sheets/main.cwh
+    include "lib.cwh"        ; reads sheets/lib.cwh
+
+Check that the file exists and is readable."#,
+            IncludeCycle => r#"A script includes itself, directly or through other files.
+
+Includes are expanded in place, so a cycle would never end:
+
+    ; a.cwh
+    include "b.cwh"
+    ; b.cwh
+    include "a.cwh"
+
+Move the shared lines to a separate file included by both."#,
+            InvalidDirective => r#"A script directive is malformed.
+
+Directives are lines of a script that are not expressions, like
+`include` or `@if`. Their arguments have to follow the documented
+form and blocks have to be closed:
+
+    include lib.cwh          ; error, the path has to be quoted
+    include "lib.cwh"
+    @if $0
+    @error "Unfinished."     ; error, missing @end"#,
+            Raised => r#"A script reached an `@error` directive.
+
+Scripts use `@error` to stop with a message of their own, usually
+inside an `@if` checking that inputs make sense:
+
+    @if - sgn $0 1           ; non-zero unless $0 is positive
+    @error "The rate has to be positive."
+    @end
+
+The message after the code is the one written in the script."#,
+            UnknownName => r#"An expression refers to a name that is not bound.
+
+Names are bound by scripts, for example by the loop variable of
+`@for`. Outside of their block, or in the REPL, they are unknown:
+
+    @for i in 1..3
+    * i i
+    @end
+    + i 1                    ; error, `i` is only bound inside the loop"#,
+            InvalidParameters => r#"The parameters given to a script do not match the ones it declares.
+
+Scripts declare their inputs with `@param`, optionally with a default
+value. Parameters without a default have to be given on the command
+line, and only declared parameters can be given:
+
+    ; sheet.cwh
+    @param rate default 20
+    @param amount
+    / * amount rate 100
+
+    $ cwh run sheet.cwh --param rate=25
+    Error[E0205]: Missing parameter 'amount'. Expected parameters: rate (default 20), amount.
+    $ cwh run sheet.cwh --param amount=300
+    60"#,
+            InvalidTemplate => r#"A template for printing values is malformed.
+
+Templates, like the one of `@print`, contain expressions in braces,
+optionally followed by a colon and a format specification:
+
+    @print "Total: {+ $0 $1} EUR"
+    @print "Mask: {$2:>10x}"         ; right aligned hexadecimal
+    @print "{$3:,}"                  ; digits grouped by thousands
+
+The specification consists of an optional alignment (`<`, `^`, `>`),
+a `0` for zero padding, a width, a grouping separator (`,` or `_`)
+and a radix (`b`, `o`, `d`, `x`, `X`), in this order. Literal braces
+are written doubled as `{{` and `}}`."#,
+            CaptureFailed => r#"A value for `@capture` could not be obtained.
+
+`@capture` freezes a value at the start of a run. Environment
+variables have to be set and hold an integer:
+
+    @capture year env YEAR
+    $ cwh run sheet.cwh
+    Error[E0207]: Environment variable 'YEAR' is not set.
+    $ YEAR=2024 cwh run sheet.cwh"#,
+            InvalidCommand => r#"A meta-command of the REPL is unknown or malformed.
+
+Lines starting with a colon are commands to the calculator itself
+rather than expressions:
+
+    # :help
+    Operators, written before their arguments:
+    ...
+    # :hlep
+    Error[E0301]: Unknown command ':hlep'.
+
+Run `:help` to list operators and commands."#,
+            InputTooLong => r#"An input has more tokens than the configured limit allows.
+
+Limits protect tools replaying untrusted inputs, like
+`cwh replay-corpus`, from inputs nested so deeply that parsing them
+would exhaust the stack:
+
+    succ succ succ ... succ 0    ; a hundred thousand times
+
+Split the computation over several lines using history variables."#,
+            InvalidSession => r#"A session file is malformed.
+
+Session files written by `:save` start with a header naming the
+format version, followed by one entry per line, holding the input
+and its result separated by a tab:
+
+    cwh-session 1
+    + 3 2	5
+    * $0 2	10
+
+Files without a header are read as plain lists of inputs, which are
+evaluated again when loaded. Editing saved files by hand can break
+this layout; the error names the offending line."#,
+            UnsupportedVersion => r#"A session file was written by a newer version of cwh.
+
+The format version in the header of the file is higher than the one
+this build understands, so loading it could silently misread it:
+
+    cwh-session 7
+    ...
+    Error[E0209]: Session format version 7 is newer than the supported version 2.
+
+Upgrade cwh to load the file. Older versions are migrated on load."#,
+            WriteFailed => r#"A file could not be written.
+
+Commands like `:save` write files relative to the working directory
+of the calculator. Check that the directory exists and is writable."#,
+            UnknownReference => r#"An expression refers to a stored expression that does not exist.
+
+Expressions saved by `:store` are referred to by the hash it prints,
+or by any prefix of it at least six digits long:
+
+    :store + $0 1
+    #697274
+    * #697274 2
+
+The reference must match exactly one stored expression. Store the
+expression first, or use a longer prefix when several match."#,
+            InvalidSessionName => r#"A session name is unknown or already taken.
+
+The REPL starts in a session named `main`. `:branch` creates a new
+session under a name no other session uses, and `:switch` only goes
+to an existing one:
+
+    :branch 5 main
+    :switch scenario-b
+
+List the sessions with `:sessions`."#,
+            InvalidSetting => r#"A setting of the REPL is unknown or given an invalid value.
+
+`:set <name> <value>` changes a setting and `:set` alone lists them
+with their current values:
+
+    :set sigfigs 3
+    :set sigfigs off
+
+Every setting documents its accepted values in `:help`."#,
+            StackUnderflow => r#"A stack command needs more values than the stack holds.
+
+`:pop` and `:dup` need one value on the stack and `:swap` needs two:
+
+    :push 3
+    :swap
+
+Push values with `:push <expr>`, or `:push` alone for the last result."#,
+            Unavailable => r#"A command is not available in this build or environment.
+
+Some commands depend on optional features chosen when building cwh,
+or on external programs. `:copy` and `:paste-eval` need the
+`clipboard` feature and one of `wl-copy`, `xclip` or `pbcopy`:
+
+    cargo build --features clipboard
+
+Rebuild with the feature, or install one of the programs."#,
+            InvalidConfig => r#"The configuration file is malformed.
+
+The configuration, read from `$CWH_CONFIG` or else from
+`~/.config/cwh/config`, holds one directive per line, `;` starting
+comments:
+
+    set grouping ,
+    filter "€" ""
+    token x *
+
+`set` takes a setting as `:set` does, `filter` replaces text and
+`token` replaces whole tokens of every input before it is parsed."#,
+            Overflow => r#"The result of an operation does not fit the integer range.
+
+Integers are 64 bits wide on most machines, ranging from
+-9223372036854775808 to 9223372036854775807. Operations whose exact
+result falls outside are rejected rather than wrapped around:
+
+    fact 21
+    * 9223372036854775807 2
+
+Split the computation so that intermediate results stay in range."#,
+            CannotRefactor => r#"The script cannot be refactored automatically.
+
+`cwh refactor renumber` numbers history entries by counting the
+expression lines of a single file, so it refuses scripts whose
+expressions are inside `@if` or `@for` blocks, which may produce
+any number of entries, and scripts including other files:
+
+    $ cwh refactor renumber sheet.cwh --from old.cwh
+    Error[E0212]: Line 4 is inside a block, its entries cannot be numbered.
+
+Such scripts have to be renumbered by hand."#,
+            InvalidRequest => r#"A request of the JSON protocol is malformed.
+
+With `cwh --protocol simple-json`, every line of standard input has
+to be a JSON object with the line to evaluate as a string under
+`input`, and optionally an `id` echoed in the response:
+
+    {"id": 1, "input": "+ 3 2"}
+
+Lines which are not valid JSON, or objects without an `input` string,
+are answered with this error and an `id` of `null` when none could be
+read."#,
+            CircularReference => r#"A grid cell depends on itself.
+
+Cells of the grid refer to other cells by name, like `A1`, and are
+evaluated after the cells they refer to. A cell referring to itself,
+directly or through other cells, has no value:
+
+    # :grid set A1 + B1 1
+    # :grid set B1 * A1 2
+    Error[E0107]: Cell 'B1' refers to itself through B1 -> A1 -> B1.
+
+Replace one of the references by a value to break the cycle."#,
+            AssertionFailed => r#"An `@assert-eq` directive found two different values.
+
+`@assert-eq <expr> <expr>` evaluates both expressions, written one
+after the other, and stops the script when their values differ. The
+scripts written by `:export-tests` check every entry of a session
+this way:
+
+    ; tests.cwh
+    + 3 2
+    @assert-eq $0 5
+    * $0 2
+    @assert-eq $1 11
+
+    $ cwh run tests.cwh
+    5
+    10
+    tests.cwh:5: Error[E0213]: Expected '$1' to be 11, instead got 10."#,
+            InvalidExercises => r#"The exercises file given to `cwh grade` is malformed.
+
+Exercises are written in a subset of TOML, one table per exercise
+with integer or quoted string values, `#` starting comments:
+
+    [discount]
+    expected = 850           # or an expression = "- 1000 150"
+    tolerance = "2%"         # or an absolute tolerance like 5
+    points = 2
+
+Every exercise needs either `expected` or `expression`. `tolerance`
+defaults to 0 and `points` to 1."#,
+            InvalidLiteral => r#"A token starting like an integer is not a valid one.
+
+Integers are written in decimal with an optional sign, `+5` being the
+same as `5`, and an optional `u` suffix asking for an unsigned value.
+Other forms are rejected rather than guessed at:
+
+    # + 3 2x
+    Error[E0009]: Invalid integer literal '2x': expected only decimal digits.
+    # + 3 +-2
+    Error[E0009]: Invalid integer literal '+-2': expected at most one sign.
+    # + 3 -2u
+    Error[E0009]: Invalid integer literal '-2u': unsigned literals cannot be negative.
+
+Integers too large for 64 bits are reported as overflows."#,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ErrorCode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ErrorCode::ALL.into_iter()
+            .find(|code| code.as_str().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl Error {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Error { code, message: message.into() }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error[{}]: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::str::FromStr;
+
+    use crate::*;
+
+    #[test]
+    fn codes_are_unique() {
+        let codes: HashSet<_> = ErrorCode::ALL.iter().map(ErrorCode::as_str).collect();
+        assert_eq!(codes.len(), ErrorCode::ALL.len());
+    }
+
+    #[test]
+    fn from_str() {
+        for code in ErrorCode::ALL {
+            assert_eq!(ErrorCode::from_str(code.as_str()), Ok(code));
+        }
+        assert_eq!(ErrorCode::from_str("e0102"), Ok(ErrorCode::DivisionByZero));
+        assert_eq!(ErrorCode::from_str("E9999"), Err(()));
+    }
+
+    #[test]
+    fn display() {
+        let error = Error::new(ErrorCode::DivisionByZero, "Division by zero.");
+        assert_eq!(error.to_string(), "Error[E0102]: Division by zero.");
+    }
+}
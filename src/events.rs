@@ -0,0 +1,170 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::json;
+use crate::repl::Repl;
+use crate::trace::Trace;
+use crate::Error;
+
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Prompt,
+    // A result pushed to history, along with how the settings display it.
+    Result { index: usize, value: isize, display: String },
+    // Any other line the REPL prints.
+    Output(String),
+    Error(Error),
+    // A node of `:trace`, in depth-first order.
+    TraceStep { depth: usize, label: String, result: String, elapsed: Duration },
+    // Sent while an evaluation is still running.
+    Progress { elapsed: Duration },
+}
+
+impl Event {
+    pub fn to_json(&self) -> String {
+        let event = |name: &str| ("event", json::string(name));
+        match self {
+            Event::Prompt => json::object([event("prompt")]),
+            Event::Result { index, value, display } => json::object([
+                event("result"),
+                ("index", index.to_string()),
+                ("value", value.to_string()),
+                ("display", json::string(display)),
+            ]),
+            Event::Output(line) => json::object([event("output"), ("line", json::string(line))]),
+            Event::Error(err) => json::object([
+                event("error"),
+                ("code", json::string(err.code.as_str())),
+                ("message", json::string(&err.message)),
+            ]),
+            Event::TraceStep { depth, label, result, elapsed } => json::object([
+                event("trace-step"),
+                ("depth", depth.to_string()),
+                ("label", json::string(label)),
+                ("result", json::string(result)),
+                ("elapsed_ns", elapsed.as_nanos().to_string()),
+            ]),
+            Event::Progress { elapsed } => {
+                json::object([event("progress"), ("elapsed_ms", elapsed.as_millis().to_string())])
+            }
+        }
+    }
+}
+
+fn trace_steps(trace: &Trace, depth: usize, emit: &mut dyn FnMut(Event)) {
+    let result = match trace.result {
+        Ok(int) => int.to_string(),
+        Err(code) => code.as_str().to_string(),
+    };
+    emit(Event::TraceStep { depth, label: trace.label.clone(), result, elapsed: trace.elapsed });
+    for child in &trace.children {
+        trace_steps(child, depth + 1, emit);
+    }
+}
+
+// Handles a line like the REPL does, emitting what it prints as events. The
+// line is evaluated on another thread so that progress is reported while it
+// runs.
+pub fn handle(repl: &mut Repl, line: &str, emit: &mut dyn FnMut(Event)) {
+    if let Some(expr) = line.trim_start().strip_prefix(":trace ") {
+        match repl.trace(expr) {
+            Ok(trace) => trace_steps(&trace, 0, emit),
+            Err(err) => emit(Event::Error(err)),
+        }
+        return;
+    }
+    let started = Instant::now();
+    let response = thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        let repl = &mut *repl;
+        scope.spawn(move || sender.send(repl.handle(line)));
+        loop {
+            match receiver.recv_timeout(PROGRESS_INTERVAL) {
+                Ok(response) => return response,
+                Err(mpsc::RecvTimeoutError::Timeout) => emit(Event::Progress { elapsed: started.elapsed() }),
+                Err(mpsc::RecvTimeoutError::Disconnected) => panic!("the evaluation thread panicked"),
+            }
+        }
+    });
+    let mut results = response.results.into_iter().peekable();
+    for (idx, line) in response.lines.into_iter().enumerate() {
+        match results.next_if(|pushed| pushed.line == idx) {
+            Some(pushed) => emit(Event::Result { index: pushed.index, value: pushed.value, display: line }),
+            None => emit(Event::Output(line)),
+        }
+    }
+    if let Some(err) = response.error {
+        emit(Event::Error(err));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::events::{handle, Event};
+    use crate::repl::Repl;
+    use crate::teach::Rules;
+    use crate::{ErrorCode, TrailingPolicy};
+
+    fn events(repl: &mut Repl, line: &str) -> Vec<Event> {
+        let mut events = Vec::new();
+        handle(repl, line, &mut |event| events.push(event));
+        events
+    }
+
+    #[test]
+    fn results() {
+        let mut repl = Repl::new(TrailingPolicy::NextExpression);
+        repl.settings.set("grouping", ",").unwrap();
+        assert_eq!(events(&mut repl, "1000 + $0 1"), [
+            Event::Result { index: 0, value: 1000, display: String::from("1,000") },
+            Event::Result { index: 1, value: 1001, display: String::from("1,001") },
+        ]);
+        assert_eq!(events(&mut repl, ":sessions"), [Event::Output(String::from("* main (2 entries)"))]);
+        let events = events(&mut repl, "/ 1 0");
+        assert!(matches!(&events[..], [Event::Error(err)] if err.code == ErrorCode::DivisionByZero));
+    }
+
+    #[test]
+    fn shrinking_sessions() {
+        let mut repl = Repl::new(TrailingPolicy::NextExpression);
+        events(&mut repl, "1 2 3");
+        assert_eq!(events(&mut repl, ":branch 0 first"), [
+            Event::Output(String::from("Switched to new session 'first' with 1 entries.")),
+        ]);
+        assert_eq!(events(&mut repl, ":switch main").len(), 1);
+        events(&mut repl, ":switch first");
+        assert_eq!(events(&mut repl, "+ $0 1"), [Event::Result { index: 1, value: 2, display: String::from("2") }]);
+    }
+
+    #[test]
+    fn explanations() {
+        let mut repl = Repl::new(TrailingPolicy::Reject);
+        repl.rules = Rules::parse("* \"{a} times {b}\"").unwrap();
+        assert_eq!(events(&mut repl, "* 6 7"), [
+            Event::Output(String::from("6 times 7")),
+            Event::Result { index: 0, value: 42, display: String::from("42") },
+        ]);
+    }
+
+    #[test]
+    fn trace_steps() {
+        let mut repl = Repl::new(TrailingPolicy::Reject);
+        let steps: Vec<_> = events(&mut repl, ":trace + 1 / 2 0").into_iter()
+            .map(|event| match event {
+                Event::TraceStep { depth, label, result, .. } => (depth, label, result),
+                event => panic!("unexpected {:?}", event),
+            })
+            .collect();
+        let step = |depth, label: &str, result: &str| (depth, String::from(label), String::from(result));
+        assert_eq!(steps, [step(0, "+", "E0102"), step(1, "1", "1"), step(1, "/", "E0102"), step(2, "2", "2"), step(2, "0", "0")]);
+        assert_eq!(
+            Event::Progress { elapsed: Duration::from_millis(1500) }.to_json(),
+            r#"{"event":"progress","elapsed_ms":1500}"#
+        );
+    }
+}
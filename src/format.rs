@@ -0,0 +1,269 @@
+use crate::script::strip_comment;
+use crate::Value::{self, BinaryOperation, Int, Name, UnaryOperation, Variable};
+use crate::parse_line;
+
+const INDENT: &str = "    ";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Layout {
+    #[default]
+    Prefix,
+    // Every operation in parentheses, for reading only as scripts have to be
+    // in prefix layout to run.
+    Sexpr,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Style {
+    pub layout: Layout,
+    // Expressions wider than this are wrapped, one operand per line.
+    pub width: Option<usize>,
+}
+
+// A line joined with its continuations, with the text of its comments.
+struct Logical {
+    code: String,
+    comment: Option<String>,
+}
+
+fn logical_lines(text: &str) -> Vec<Logical> {
+    let mut lines: Vec<Logical> = Vec::new();
+    let mut continued = false;
+    for line in text.lines() {
+        let code = strip_comment(line);
+        let comment = line[code.len()..].strip_prefix(';').map(|comment| String::from(comment.trim()));
+        let code = code.trim();
+        let (code, continues) = match code.strip_suffix('\\') {
+            Some(code) => (code.trim_end(), true),
+            None => (code, false),
+        };
+        match lines.last_mut() {
+            Some(last) if continued => {
+                last.code = format!("{} {}", last.code, code).trim().to_string();
+                last.comment = match (last.comment.take(), comment) {
+                    (Some(last), Some(comment)) => Some(format!("{} {}", last, comment)),
+                    (last, comment) => last.or(comment),
+                };
+            }
+            _ => lines.push(Logical { code: String::from(code), comment }),
+        }
+        continued = continues;
+    }
+    lines
+}
+
+// Parses an expression in either layout, parentheses being redundant in
+// prefix notation.
+fn parse_expression(code: &str) -> Option<Value> {
+    let spaced = code.replace('(', " ( ").replace(')', " ) ");
+    let mut depth = 0usize;
+    for token in spaced.split_whitespace() {
+        match token {
+            "(" => depth += 1,
+            ")" => depth = depth.checked_sub(1)?,
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return None;
+    }
+    parse_line(spaced.split_whitespace().filter(|token| *token != "(" && *token != ")")).ok()
+}
+
+fn render(value: &Value, layout: Layout) -> String {
+    match (value, layout) {
+        (_, Layout::Prefix) => value.to_string(),
+        (BinaryOperation { operator, left, right }, Layout::Sexpr) => {
+            format!("({} {} {})", operator, render(left, layout), render(right, layout))
+        }
+        (UnaryOperation { operator, arg }, Layout::Sexpr) => format!("({} {})", operator, render(arg, layout)),
+        (Int(_) | Name(_) | Variable(_), Layout::Sexpr) => value.to_string(),
+    }
+}
+
+// Lays the expression out on one line when it fits, else puts its operator
+// on the first line and every operand indented on the following ones.
+fn wrap(value: &Value, layout: Layout, indent: &str, width: usize) -> Vec<String> {
+    let line = format!("{}{}", indent, render(value, layout));
+    let (operator, operands) = match value {
+        BinaryOperation { operator, left, right } => (operator.to_string(), vec![left, right]),
+        UnaryOperation { operator, arg } => (operator.to_string(), vec![arg]),
+        Int(_) | Name(_) | Variable(_) => return vec![line],
+    };
+    if line.len() <= width {
+        return vec![line];
+    }
+    let nested = format!("{}{}", indent, INDENT);
+    let mut lines = vec![match layout {
+        Layout::Prefix => format!("{}{}", indent, operator),
+        Layout::Sexpr => format!("{}({}", indent, operator),
+    }];
+    lines.extend(operands.into_iter().flat_map(|operand| wrap(operand, layout, &nested, width)));
+    if layout == Layout::Sexpr {
+        lines.last_mut().unwrap().push(')');
+    }
+    lines
+}
+
+fn expression(code: &str, style: &Style) -> String {
+    match parse_expression(code) {
+        Some(value) => render(&value, style.layout),
+        None => code.split_whitespace().collect::<Vec<_>>().join(" "),
+    }
+}
+
+// Directives with expressions have them formatted, the others only have
+// their whitespace normalized outside of strings.
+fn directive(name: &str, rest: &str, style: &Style) -> String {
+    match name {
+        "if" => format!("@if {}", expression(rest, style)),
+        "for" => {
+            let range = rest.split_once(char::is_whitespace).and_then(|(name, rest)| {
+                let (from, to) = rest.trim_start().strip_prefix("in ")?.split_once("..")?;
+                Some(format!("@for {} in {}..{}", name, expression(from, style), expression(to, style)))
+            });
+            range.unwrap_or_else(|| format!("@for {}", rest))
+        }
+        "print" | "error" => format!("@{} {}", name, rest),
+        _ => format!("@{} {}", name, rest.split_whitespace().collect::<Vec<_>>().join(" ")),
+    }
+}
+
+// Normalizes a script: single spaces between tokens, blocks indented, runs
+// of empty lines collapsed and comments of neighbouring lines aligned.
+pub fn format(text: &str, style: &Style) -> String {
+    // Every output line as its indented code, possibly wrapped over several
+    // lines, and its comment. `None` stands for an empty line.
+    let mut formatted: Vec<Option<(Vec<String>, Option<String>)>> = Vec::new();
+    let mut depth = 0usize;
+    for Logical { code, comment } in logical_lines(text) {
+        if code.is_empty() && comment.is_none() {
+            if formatted.last().is_some_and(Option::is_some) {
+                formatted.push(None);
+            }
+            continue;
+        }
+        let (name, rest) = match code.strip_prefix('@') {
+            Some(directive) => match directive.split_once(char::is_whitespace) {
+                Some((name, rest)) => (Some(name), rest.trim()),
+                None => (Some(directive), ""),
+            },
+            None => (None, code.as_str()),
+        };
+        if let Some("else" | "end") = name {
+            depth = depth.saturating_sub(1);
+        }
+        let indent = INDENT.repeat(depth);
+        let lines = match name {
+            _ if code.is_empty() => vec![indent],
+            Some(name) if rest.is_empty() => vec![format!("{}@{}", indent, name)],
+            Some(name) => vec![format!("{}{}", indent, directive(name, rest, style))],
+            None if code.starts_with("include ") => vec![format!("{}include {}", indent, rest[7..].trim())],
+            None => match (parse_expression(&code), style.width) {
+                (Some(value), Some(width)) => wrap(&value, style.layout, &indent, width),
+                _ => vec![format!("{}{}", indent, expression(&code, style))],
+            },
+        };
+        if let Some("if" | "for" | "else") = name {
+            depth += 1;
+        }
+        formatted.push(Some((lines, comment)));
+    }
+    if formatted.last().is_some_and(Option::is_none) {
+        formatted.pop();
+    }
+
+    let mut output = String::new();
+    for group in formatted.split(Option::is_none) {
+        let group: Vec<_> = group.iter().flatten().collect();
+        let column = group.iter()
+            .filter(|(lines, comment)| comment.is_some() && !lines[0].trim().is_empty())
+            .map(|(lines, _)| lines.last().unwrap().len())
+            .max()
+            .unwrap_or(0);
+        for (lines, comment) in group {
+            for (idx, line) in lines.iter().enumerate() {
+                let last = idx + 1 == lines.len();
+                output.push_str(line);
+                if !last {
+                    output.push_str(" \\");
+                } else if let Some(comment) = comment {
+                    let padding = match line.trim().is_empty() {
+                        true => 0,
+                        false => column - line.len() + 1,
+                    };
+                    output.push_str(&" ".repeat(padding));
+                    output.push(';');
+                    if !comment.is_empty() {
+                        output.push(' ');
+                        output.push_str(comment);
+                    }
+                }
+                output.push('\n');
+            }
+        }
+        output.push('\n');
+    }
+    output.pop();
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::{format, Layout, Style};
+
+    const SCRIPT: &str = "\
+@param  rate   default  20 ;percent
+;   totals
+@for i in 1 ..  3
+@if -  i 2
+*   i  rate  ; scaled
+@else
+( + i 1 )
+@end
+@end
+
+
+@print \"{i:>4}  |\"
++ $0 \\
+  $1 ; last
+";
+
+    #[test]
+    fn prefix() {
+        let formatted = format(SCRIPT, &Style::default());
+        assert_eq!(formatted, "\
+@param rate default 20 ; percent
+; totals
+@for i in 1..3
+    @if - i 2
+        * i rate       ; scaled
+    @else
+        + i 1
+    @end
+@end
+
+@print \"{i:>4}  |\"
++ $0 $1 ; last
+");
+        assert_eq!(format(&formatted, &Style::default()), formatted);
+    }
+
+    #[test]
+    fn sexpr() {
+        let sexpr = Style { layout: Layout::Sexpr, width: None };
+        assert_eq!(format("+ 1 * 2 abs $0\n", &sexpr), "(+ 1 (* 2 (abs $0)))\n");
+        assert_eq!(format("(+ 1 (* 2 (abs $0)))\n", &Style::default()), "+ 1 * 2 abs $0\n");
+        assert_eq!(format("(+ 1 2\n", &Style::default()), "(+ 1 2\n");
+    }
+
+    #[test]
+    fn wrapping() {
+        let style = Style { layout: Layout::Prefix, width: Some(16) };
+        let wrapped = "+ \\\n    * 1000 2000 \\\n    30000 ; sum\n1\n";
+        assert_eq!(format("+ * 1000 2000 30000 ; sum\n1\n", &style), wrapped);
+        assert_eq!(format(wrapped, &style), wrapped);
+        let sexpr = Style { layout: Layout::Sexpr, width: Some(12) };
+        assert_eq!(format("+ * 1000 2000 3\n", &sexpr), "(+ \\\n    (* \\\n        1000 \\\n        2000) \\\n    3)\n");
+    }
+}
@@ -0,0 +1,327 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::json;
+use crate::script::{self, parse_string, Runner, ScriptError};
+use crate::{evaluate_value, parse_line, Error, ErrorCode};
+
+pub const EXTENSION: &str = "cwh";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tolerance {
+    Absolute(isize),
+    Percent(isize),
+}
+
+impl Tolerance {
+    fn accepts(&self, answer: isize, expected: isize) -> bool {
+        let difference = (answer as i128 - expected as i128).abs();
+        match self {
+            Tolerance::Absolute(tolerance) => difference <= *tolerance as i128,
+            Tolerance::Percent(percent) => difference * 100 <= *percent as i128 * (expected as i128).abs(),
+        }
+    }
+}
+
+impl fmt::Display for Tolerance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tolerance::Absolute(tolerance) => write!(f, "{}", tolerance),
+            Tolerance::Percent(percent) => write!(f, "{}%", percent),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exercise {
+    pub name: String,
+    pub expected: isize,
+    pub tolerance: Tolerance,
+    pub points: usize,
+}
+
+enum Toml<'a> {
+    Int(isize),
+    String(&'a str),
+}
+
+// Strips a `#` comment, ignoring hashes inside double quotes.
+fn strip_toml_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (idx, char) in line.char_indices() {
+        match char {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..idx],
+            _ => {}
+        }
+    }
+    line
+}
+
+// Reads the subset of TOML made of tables holding integers and strings
+// without escapes, a table per exercise named after it.
+pub fn parse_exercises(text: &str) -> Result<Vec<Exercise>, Error> {
+    let mut tables: Vec<(&str, Vec<(&str, Toml)>)> = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let invalid = |message: String| Error::new(ErrorCode::InvalidExercises, format!("Line {}: {}", idx + 1, message));
+        let line = strip_toml_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            let name = name.trim();
+            if name.is_empty() || !name.chars().all(|char| char.is_alphanumeric() || "-_".contains(char)) {
+                return Err(invalid(format!("Expected an exercise name made of letters, digits, '-' and '_', instead got '{}'.", name)));
+            }
+            if tables.iter().any(|(other, _)| *other == name) {
+                return Err(invalid(format!("Exercise '{}' is defined twice.", name)));
+            }
+            tables.push((name, Vec::new()));
+            continue;
+        }
+        let (key, value) = line.split_once('=')
+            .ok_or_else(|| invalid(format!("Expected '[<exercise>]' or '<key> = <value>', instead got '{}'.", line)))?;
+        let (key, value) = (key.trim(), value.trim());
+        let value = match (value.parse(), parse_string(value)) {
+            (Ok(int), _) => Toml::Int(int),
+            (_, Some(string)) => Toml::String(string),
+            _ => return Err(invalid(format!("Expected an integer or a quoted string, instead got '{}'.", value))),
+        };
+        let (_, entries) = tables.last_mut().ok_or_else(|| invalid(format!("Key '{}' is outside of any exercise.", key)))?;
+        entries.push((key, value));
+    }
+
+    let mut exercises = Vec::new();
+    for (name, entries) in tables {
+        let invalid = |message: String| Error::new(ErrorCode::InvalidExercises, format!("Exercise '{}': {}", name, message));
+        let mut exercise = Exercise { name: String::from(name), expected: 0, tolerance: Tolerance::Absolute(0), points: 1 };
+        let mut expected = false;
+        for (key, value) in entries {
+            match (key, value) {
+                ("expected", Toml::Int(int)) if !expected => exercise.expected = int,
+                ("expression", Toml::String(expr)) if !expected => {
+                    exercise.expected = parse_line(expr.split_whitespace())
+                        .and_then(|value| evaluate_value(&value, &[]))
+                        .map_err(|err| invalid(format!("Cannot evaluate '{}': {}", expr, err)))?;
+                }
+                ("expected" | "expression", _) if expected => {
+                    return Err(invalid(String::from("Expected either 'expected' or 'expression', only once.")));
+                }
+                ("tolerance", Toml::Int(int)) if int >= 0 => exercise.tolerance = Tolerance::Absolute(int),
+                ("tolerance", Toml::String(percent)) => {
+                    let percent = percent.strip_suffix('%').and_then(|percent| percent.trim().parse().ok())
+                        .filter(|percent| *percent >= 0)
+                        .ok_or_else(|| invalid(format!("Expected a tolerance like 5 or \"2%\", instead got '{}'.", percent)))?;
+                    exercise.tolerance = Tolerance::Percent(percent);
+                }
+                ("points", Toml::Int(int)) if int >= 0 => exercise.points = int as usize,
+                (key, _) => return Err(invalid(format!("Unexpected value for '{}'.", key))),
+            }
+            expected |= key == "expected" || key == "expression";
+        }
+        if !expected {
+            return Err(invalid(String::from("Expected an 'expected' value or an 'expression'.")));
+        }
+        exercises.push(exercise);
+    }
+    Ok(exercises)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Correct(isize),
+    Wrong(isize),
+    // The script ran without producing any result.
+    NoResult,
+    Failed(ScriptError),
+    Missing,
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Correct(_) => "correct",
+            Outcome::Wrong(_) => "wrong",
+            Outcome::NoResult => "no-result",
+            Outcome::Failed(_) => "error",
+            Outcome::Missing => "missing",
+        }
+    }
+}
+
+// The last result of a script is its answer.
+pub fn check(exercise: &Exercise, path: &Path) -> Outcome {
+    if !path.exists() {
+        return Outcome::Missing;
+    }
+    let mut runner = Runner::new();
+    let run = script::load(path).and_then(|lines| runner.run(&lines, &mut |_| {}));
+    match (run, runner.history.last()) {
+        (Err(err), _) => Outcome::Failed(err),
+        (Ok(()), None) => Outcome::NoResult,
+        (Ok(()), Some(answer)) if exercise.tolerance.accepts(*answer, exercise.expected) => Outcome::Correct(*answer),
+        (Ok(()), Some(answer)) => Outcome::Wrong(*answer),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Grade<'a> {
+    pub student: String,
+    pub outcomes: Vec<(&'a Exercise, Outcome)>,
+}
+
+impl Grade<'_> {
+    pub fn score(&self) -> usize {
+        self.outcomes.iter()
+            .filter(|(_, outcome)| matches!(outcome, Outcome::Correct(_)))
+            .map(|(exercise, _)| exercise.points)
+            .sum()
+    }
+
+    pub fn total(&self) -> usize {
+        self.outcomes.iter().map(|(exercise, _)| exercise.points).sum()
+    }
+
+    pub fn to_json(&self) -> String {
+        let outcomes = self.outcomes.iter().map(|(exercise, outcome)| {
+            let mut entries = vec![
+                ("exercise", json::string(&exercise.name)),
+                ("outcome", json::string(outcome.as_str())),
+            ];
+            match outcome {
+                Outcome::Correct(answer) | Outcome::Wrong(answer) => entries.push(("answer", answer.to_string())),
+                Outcome::Failed(err) => entries.push(("error", json::object([
+                    ("code", json::string(err.error.code.as_str())),
+                    ("message", json::string(&err.error.message)),
+                    ("line", err.location.as_ref().map_or(String::from("null"), |location| location.line.to_string())),
+                ]))),
+                Outcome::NoResult | Outcome::Missing => {}
+            }
+            json::object(entries)
+        });
+        json::object([
+            ("student", json::string(&self.student)),
+            ("score", self.score().to_string()),
+            ("total", self.total().to_string()),
+            ("exercises", json::array(outcomes)),
+        ])
+    }
+}
+
+impl fmt::Display for Grade<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}/{}", self.student, self.score(), self.total())?;
+        let width = self.outcomes.iter().map(|(exercise, _)| exercise.name.len()).max().unwrap_or_default();
+        for (exercise, outcome) in &self.outcomes {
+            let details = match outcome {
+                Outcome::Correct(answer) => answer.to_string(),
+                Outcome::Wrong(answer) => format!("{}, expected {} ± {}", answer, exercise.expected, exercise.tolerance),
+                Outcome::Failed(err) => err.to_string(),
+                Outcome::NoResult | Outcome::Missing => String::new(),
+            };
+            let line = format!("  {:<width$} {:<9} {}", exercise.name, outcome.as_str(), details);
+            write!(f, "\n{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+// Grades every student, a subdirectory of `dir` holding a script per
+// exercise named after it, students being sorted by name.
+pub fn grade<'a>(exercises: &'a [Exercise], dir: &Path) -> Result<Vec<Grade<'a>>, Error> {
+    let read_failed = |err: std::io::Error| {
+        Error::new(ErrorCode::ReadFailed, format!("Cannot read '{}': {}.", dir.display(), err))
+    };
+    let mut students = Vec::new();
+    for entry in fs::read_dir(dir).map_err(read_failed)? {
+        let path = entry.map_err(read_failed)?.path();
+        if path.is_dir() {
+            students.push(path);
+        }
+    }
+    students.sort();
+    Ok(students.iter()
+        .map(|path| Grade {
+            student: path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            outcomes: exercises.iter()
+                .map(|exercise| (exercise, check(exercise, &path.join(&exercise.name).with_extension(EXTENSION))))
+                .collect(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::grade::{grade, parse_exercises, Exercise, Outcome, Tolerance};
+    use crate::ErrorCode;
+
+    const EXERCISES: &str = "\
+# week 3
+[discount]
+expected = 850
+tolerance = \"2%\"   # rounding is fine
+points = 2
+
+[fact]
+expression = \"fact 5\"
+
+[tax]
+expected = -12
+tolerance = 1
+";
+
+    #[test]
+    fn exercises() {
+        let exercises = parse_exercises(EXERCISES).unwrap();
+        assert_eq!(exercises[0], Exercise {
+            name: String::from("discount"),
+            expected: 850,
+            tolerance: Tolerance::Percent(2),
+            points: 2,
+        });
+        assert_eq!(exercises[1].expected, 120);
+        assert_eq!(exercises[2].tolerance, Tolerance::Absolute(1));
+        assert!(Tolerance::Percent(2).accepts(867, 850));
+        assert!(!Tolerance::Percent(2).accepts(868, 850));
+
+        for text in ["expected = 1", "[a]\n", "[a]\nexpected = 1\nexpression = \"1\"", "[a]\nexpected = x",
+            "[a]\nexpected = 1\ntolerance = \"2\"", "[a]\nexpected = 1\n[a]\nexpected = 2", "[a b]", "[a]\nexpression = \"/ 1 0\""]
+        {
+            assert_eq!(parse_exercises(text).unwrap_err().code, ErrorCode::InvalidExercises, "{}", text);
+        }
+    }
+
+    #[test]
+    fn submissions() {
+        let dir = std::env::temp_dir().join(format!("cwh-grade-{}", std::process::id()));
+        for (path, text) in [
+            ("alice/discount.cwh", "1000\n* $0 85\n/ $1 100 ; rounded\n"),
+            ("alice/fact.cwh", "fact 5\n"),
+            ("alice/tax.cwh", "/ 1 0\n"),
+            ("bob/discount.cwh", "800\n"),
+            ("bob/tax.cwh", "@print \"no idea\"\n"),
+        ] {
+            fs::create_dir_all(dir.join(path).parent().unwrap()).unwrap();
+            fs::write(dir.join(path), text).unwrap();
+        }
+        let exercises = parse_exercises(EXERCISES).unwrap();
+        let grades = grade(&exercises, &dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let outcomes: Vec<Vec<_>> = grades.iter()
+            .map(|grade| grade.outcomes.iter().map(|(_, outcome)| outcome.as_str()).collect())
+            .collect();
+        assert_eq!(outcomes, [["correct", "correct", "error"], ["wrong", "missing", "no-result"]]);
+        assert_eq!((grades[0].score(), grades[0].total()), (3, 4));
+        let Outcome::Failed(err) = &grades[0].outcomes[2].1 else { panic!("alice's tax should fail") };
+        assert_eq!(err.error.code, ErrorCode::DivisionByZero);
+        assert_eq!(grades[1].to_string(), "bob 0/4\n  discount wrong     800, expected 850 ± 2%\n  fact     missing\n  tax      no-result");
+        assert_eq!(
+            grades[1].to_json(),
+            r#"{"student":"bob","score":0,"total":4,"exercises":[{"exercise":"discount","outcome":"wrong","answer":800},{"exercise":"fact","outcome":"missing"},{"exercise":"tax","outcome":"no-result"}]}"#,
+        );
+    }
+}
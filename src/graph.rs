@@ -0,0 +1,148 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+use crate::script::{Line, Runner, ScriptError};
+
+// Indices of every `$n` in the text, including those in templates.
+pub fn references(text: &str) -> Vec<usize> {
+    text.split('$')
+        .skip(1)
+        .filter_map(|rest| {
+            let digits = rest.find(|char: char| !char.is_ascii_digit()).unwrap_or(rest.len());
+            rest[..digits].parse().ok()
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Edge {
+    // Indices of the referencing and the producing line, `None` when the
+    // entry is never produced.
+    pub from: usize,
+    pub index: usize,
+    pub to: Option<usize>,
+}
+
+impl Edge {
+    // References to an entry produced by the same or a later line, which
+    // only happens in loops.
+    pub fn is_cyclic(&self) -> bool {
+        self.to.is_some_and(|to| to >= self.from)
+    }
+}
+
+#[derive(Debug)]
+pub struct Graph<'a> {
+    pub lines: &'a [Line],
+    // Index of the line producing every entry of history.
+    pub producers: Vec<usize>,
+    pub edges: BTreeSet<Edge>,
+    // Entries are known only up to a failing line.
+    pub error: Option<ScriptError>,
+}
+
+// Runs the script to learn which line produces which entry, loops and
+// conditions making it impossible to tell from the text alone.
+pub fn graph<'a>(lines: &'a [Line], runner: &mut Runner) -> Graph<'a> {
+    let error = runner.run(lines, &mut |_| {}).err();
+    let positions: HashMap<_, _> = lines.iter().enumerate().map(|(idx, line)| (&line.location, idx)).collect();
+    let producers: Vec<_> = runner.sources.iter().map(|location| positions[location]).collect();
+    let edges = lines.iter()
+        .enumerate()
+        .flat_map(|(from, line)| {
+            let producers = &producers;
+            references(&line.text).into_iter().map(move |index| Edge { from, index, to: producers.get(index).copied() })
+        })
+        .collect();
+    Graph { lines, producers, edges, error }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl Graph<'_> {
+    pub fn to_dot(&self) -> String {
+        let mut nodes: BTreeSet<_> = self.producers.iter().copied().collect();
+        nodes.extend(self.edges.iter().map(|edge| edge.from));
+        let mut dot = String::from("digraph worksheet {\n");
+        for idx in nodes {
+            let line = &self.lines[idx];
+            writeln!(dot, "    line{} [label=\"{}\\n{}\"];", idx, line.location, escape(&line.text)).unwrap();
+        }
+        for edge in &self.edges {
+            match edge.to {
+                Some(to) => {
+                    let style = if edge.is_cyclic() { ", color=orange" } else { "" };
+                    writeln!(dot, "    line{} -> line{} [label=\"${}\"{}];", edge.from, to, edge.index, style).unwrap();
+                }
+                None => {
+                    writeln!(dot, "    missing{} [label=\"${} is never produced\", color=red];", edge.index, edge.index).unwrap();
+                    writeln!(dot, "    line{} -> missing{} [label=\"${}\", color=red];", edge.from, edge.index, edge.index)
+                        .unwrap();
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for edge in &self.edges {
+            let from = &self.lines[edge.from].location;
+            match edge.to {
+                Some(to) if edge.is_cyclic() => {
+                    writeln!(text, "{} ${} -> {} (cyclic)", from, edge.index, self.lines[to].location).unwrap()
+                }
+                Some(to) => writeln!(text, "{} ${} -> {}", from, edge.index, self.lines[to].location).unwrap(),
+                None => writeln!(text, "{} ${} -> never produced", from, edge.index).unwrap(),
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::graph::{graph, references, Edge};
+    use crate::script::{split_lines, Line, Runner};
+    use crate::ErrorCode;
+
+    fn lines(text: &str) -> Vec<Line> {
+        split_lines(Path::new("sheet.cwh"), text, 1)
+    }
+
+    #[test]
+    fn references_in_text() {
+        assert_eq!(references("+ $0 * $12 $"), [0, 12]);
+        assert_eq!(references("@print \"{$3:x} costs $\""), [3]);
+    }
+
+    #[test]
+    fn edges() {
+        let lines = lines("3\n@for i in 1..2\n+ $0 i\n@end\n@print \"{$2}\"\n* $1 $7");
+        let graph = graph(&lines, &mut Runner::new());
+        assert_eq!(graph.producers, [0, 2, 2]);
+        assert_eq!(graph.error.unwrap().error.code, ErrorCode::InvalidVariableIndex);
+        let edge = |from, index, to| Edge { from, index, to };
+        assert_eq!(graph.edges.into_iter().collect::<Vec<_>>(), [
+            edge(2, 0, Some(0)),
+            edge(4, 2, Some(2)),
+            edge(5, 1, Some(2)),
+            edge(5, 7, None),
+        ]);
+        assert!(edge(4, 2, Some(4)).is_cyclic());
+    }
+
+    #[test]
+    fn dot() {
+        let lines = lines("3\n* $0 \"2\"\n- $1 $4");
+        let dot = graph(&lines, &mut Runner::new()).to_dot();
+        assert!(dot.starts_with("digraph worksheet {\n    line0 [label=\"sheet.cwh:1\\n3\"];\n"));
+        assert!(dot.contains("    line1 -> line0 [label=\"$0\"];\n"));
+        assert!(dot.contains("    line2 -> missing4 [label=\"$4\", color=red];\n"));
+    }
+}
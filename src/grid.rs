@@ -0,0 +1,275 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::script::{parse_string, strip_comment};
+use crate::{evaluate_in, parse_line, Context, Error, ErrorCode};
+
+pub const COLUMNS: usize = 26;
+pub const ROWS: usize = 99;
+
+// Zero-based position of a cell, named like `B3` for row 2 of column 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Cell {
+    pub row: usize,
+    pub column: usize,
+}
+
+impl Cell {
+    pub fn column_name(column: usize) -> char {
+        (b'A' + column as u8) as char
+    }
+
+    pub fn parse(name: &str) -> Option<Cell> {
+        let mut chars = name.chars();
+        let column = chars.next().filter(char::is_ascii_uppercase)? as usize - 'A' as usize;
+        let digits = chars.as_str();
+        if digits.starts_with('0') || !digits.chars().all(|char| char.is_ascii_digit()) {
+            return None;
+        }
+        let row: usize = digits.parse().ok().filter(|row| (1..=ROWS).contains(row))?;
+        Some(Cell { row: row - 1, column })
+    }
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", Cell::column_name(self.column), self.row + 1)
+    }
+}
+
+// Cells holding expressions which refer to other cells by name, every cell
+// being evaluated again whenever one changes.
+#[derive(Debug, Default)]
+pub struct Grid {
+    inputs: BTreeMap<Cell, String>,
+    values: BTreeMap<Cell, Result<isize, Error>>,
+}
+
+enum Visit {
+    Started,
+    Done,
+}
+
+impl Grid {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    pub fn input(&self, cell: Cell) -> Option<&str> {
+        self.inputs.get(&cell).map(String::as_str)
+    }
+
+    pub fn value(&self, cell: Cell) -> Option<&Result<isize, Error>> {
+        self.values.get(&cell)
+    }
+
+    // The last row and column holding a cell.
+    pub fn extent(&self) -> Option<Cell> {
+        let rows = self.inputs.keys().map(|cell| cell.row).max()?;
+        let columns = self.inputs.keys().map(|cell| cell.column).max()?;
+        Some(Cell { row: rows, column: columns })
+    }
+
+    // Sets the expression of a cell, an empty one clearing it, and returns
+    // the new value of the cell.
+    pub fn set(&mut self, cell: Cell, input: &str) -> Result<Option<isize>, Error> {
+        let input = input.trim();
+        if input.is_empty() {
+            self.inputs.remove(&cell);
+            self.recompute();
+            return Ok(None);
+        }
+        parse_line(input.split_whitespace())?;
+        self.inputs.insert(cell, String::from(input));
+        self.recompute();
+        self.values[&cell].clone().map(Some)
+    }
+
+    fn recompute(&mut self) {
+        let mut visits = HashMap::new();
+        self.values.clear();
+        for cell in self.inputs.keys().copied().collect::<Vec<_>>() {
+            self.evaluate(cell, &mut visits, &mut Vec::new());
+        }
+    }
+
+    // Evaluates the cells referred to first, `path` being the cells whose
+    // evaluation is under way.
+    fn evaluate(&mut self, cell: Cell, visits: &mut HashMap<Cell, Visit>, path: &mut Vec<Cell>) {
+        match visits.get(&cell) {
+            Some(Visit::Done) => return,
+            Some(Visit::Started) => {
+                let start = path.iter().position(|other| *other == cell).unwrap();
+                let cycle: Vec<_> = path[start..].iter().chain([&cell]).map(Cell::to_string).collect();
+                let err = Error::new(
+                    ErrorCode::CircularReference,
+                    format!("Cell '{}' refers to itself through {}.", cell, cycle.join(" -> ")),
+                );
+                for other in &path[start..] {
+                    self.values.insert(*other, Err(err.clone()));
+                }
+                return;
+            }
+            None => {}
+        }
+        visits.insert(cell, Visit::Started);
+        path.push(cell);
+        let value = parse_line(self.inputs[&cell].split_whitespace()).unwrap();
+        let mut names = HashMap::new();
+        let mut failed = None;
+        for name in value.names() {
+            let Some(other) = Cell::parse(name).filter(|other| self.inputs.contains_key(other)) else { continue };
+            self.evaluate(other, visits, path);
+            match &self.values[&other] {
+                Ok(int) => {
+                    names.insert(String::from(name), *int);
+                }
+                Err(err) => failed = failed.or(Some(err.clone())),
+            }
+        }
+        path.pop();
+        visits.insert(cell, Visit::Done);
+        if self.values.contains_key(&cell) {
+            return;
+        }
+        let result = match failed {
+            Some(err) => Err(err),
+            None => evaluate_in(&value, &Context::new(&[], &names)),
+        };
+        self.values.insert(cell, result);
+    }
+
+    // A script capturing every cell in the order of their references and
+    // printing them all, runnable with `cwh run`.
+    pub fn to_script(&self) -> Result<String, Error> {
+        let mut order = Vec::new();
+        let mut visits = HashMap::new();
+        for cell in self.inputs.keys() {
+            self.order(*cell, &mut visits, &mut order)?;
+        }
+        let mut script = String::new();
+        for cell in &order {
+            script.push_str(&format!("@capture {} {}\n", cell, self.inputs[cell]));
+        }
+        for cell in self.inputs.keys() {
+            script.push_str(&format!("@print \"{} = {{{}}}\"\n", cell, cell));
+        }
+        Ok(script)
+    }
+
+    fn order(&self, cell: Cell, visits: &mut HashMap<Cell, Visit>, order: &mut Vec<Cell>) -> Result<(), Error> {
+        match visits.get(&cell) {
+            Some(Visit::Done) => return Ok(()),
+            Some(Visit::Started) => return Err(self.values[&cell].clone().unwrap_err()),
+            None => {}
+        }
+        visits.insert(cell, Visit::Started);
+        let value = parse_line(self.inputs[&cell].split_whitespace()).unwrap();
+        for other in value.names().into_iter().filter_map(Cell::parse) {
+            if self.inputs.contains_key(&other) {
+                self.order(other, visits, order)?;
+            }
+        }
+        visits.insert(cell, Visit::Done);
+        order.push(cell);
+        Ok(())
+    }
+
+    // Reads the cells back from a script written by `to_script`, ignoring
+    // its `@print` lines.
+    pub fn from_script(text: &str) -> Result<Grid, Error> {
+        let mut grid = Grid::new();
+        for (idx, line) in text.lines().enumerate() {
+            let invalid = || Error::new(
+                ErrorCode::InvalidDirective,
+                format!("Expected '@capture <cell> <expr>' or '@print' on line {}.", idx + 1),
+            );
+            let line = strip_comment(line).trim();
+            if line.is_empty() || line.strip_prefix("@print").and_then(|rest| parse_string(rest.trim())).is_some() {
+                continue;
+            }
+            let (name, input) = line.strip_prefix("@capture ")
+                .and_then(|rest| rest.trim_start().split_once(char::is_whitespace))
+                .ok_or_else(invalid)?;
+            let cell = Cell::parse(name).ok_or_else(invalid)?;
+            parse_line(input.split_whitespace())?;
+            grid.inputs.insert(cell, String::from(input.trim()));
+        }
+        grid.recompute();
+        Ok(grid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grid::{Cell, Grid};
+    use crate::script::{Line, Location, Runner};
+    use crate::ErrorCode;
+
+    fn cell(name: &str) -> Cell {
+        Cell::parse(name).unwrap()
+    }
+
+    #[test]
+    fn cells() {
+        assert_eq!(Cell::parse("B3"), Some(Cell { row: 2, column: 1 }));
+        assert_eq!(cell("Z99").to_string(), "Z99");
+        for invalid in ["b3", "A0", "A100", "A", "A01", "AA1", "A1x"] {
+            assert_eq!(Cell::parse(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn recompute() {
+        let mut grid = Grid::new();
+        assert_eq!(grid.set(cell("B1"), "* A1 A2").unwrap_err().code, ErrorCode::UnknownName);
+        assert_eq!(grid.value(cell("B1")).unwrap().as_ref().unwrap_err().code, ErrorCode::UnknownName);
+        assert_eq!(grid.set(cell("A1"), "3"), Ok(Some(3)));
+        assert_eq!(grid.set(cell("A2"), "+ A1 1"), Ok(Some(4)));
+        assert_eq!(grid.value(cell("B1")), Some(&Ok(12)));
+        assert_eq!(grid.set(cell("A1"), "5"), Ok(Some(5)));
+        assert_eq!(grid.value(cell("B1")), Some(&Ok(30)));
+        assert_eq!(grid.set(cell("A2"), ""), Ok(None));
+        assert_eq!(grid.value(cell("A2")), None);
+        assert_eq!(grid.set(cell("A2"), "+ 1").unwrap_err().code, ErrorCode::BinaryArity);
+        assert_eq!(grid.extent(), Some(cell("B1")));
+    }
+
+    #[test]
+    fn cycles() {
+        let mut grid = Grid::new();
+        grid.set(cell("A1"), "+ B1 1").unwrap_err();
+        let err = grid.set(cell("B1"), "* A1 2").unwrap_err();
+        assert_eq!(err.code, ErrorCode::CircularReference);
+        assert_eq!(grid.value(cell("A1")).unwrap().as_ref().unwrap_err().code, ErrorCode::CircularReference);
+        assert_eq!(grid.set(cell("C1"), "A1").unwrap_err().code, ErrorCode::CircularReference);
+        assert_eq!(grid.to_script().unwrap_err().code, ErrorCode::CircularReference);
+    }
+
+    #[test]
+    fn scripts() {
+        let mut grid = Grid::new();
+        grid.set(cell("A2"), "* A1 2").unwrap_err();
+        grid.set(cell("A1"), "7").unwrap();
+        let script = grid.to_script().unwrap();
+        assert_eq!(script, "@capture A1 7\n@capture A2 * A1 2\n@print \"A1 = {A1}\"\n@print \"A2 = {A2}\"\n");
+        let lines: Vec<_> = script.lines().enumerate()
+            .map(|(idx, text)| Line { location: Location { path: "grid.cwh".into(), line: idx + 1 }, text: text.into() })
+            .collect();
+        let mut output = Vec::new();
+        Runner::new().run(&lines, &mut |line| output.push(line)).unwrap();
+        assert_eq!(output, ["A1 = 7", "A2 = 14"]);
+
+        let imported = Grid::from_script(&script).unwrap();
+        assert_eq!(imported.value(cell("A2")), Some(&Ok(14)));
+        assert_eq!(Grid::from_script("+ 1 2\n").unwrap_err().code, ErrorCode::InvalidDirective);
+    }
+}
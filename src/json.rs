@@ -0,0 +1,209 @@
+use std::fmt::{Display, Write};
+
+pub fn string(str: &str) -> String {
+    let mut out = String::with_capacity(str.len() + 2);
+    out.push('"');
+    for char in str.chars() {
+        match char {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            char if char.is_control() => write!(out, "\\u{:04x}", char as u32).unwrap(),
+            char => out.push(char),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// Values are written verbatim, so they have to be valid JSON already.
+pub fn object<K: AsRef<str>, V: Display>(entries: impl IntoIterator<Item = (K, V)>) -> String {
+    let entries: Vec<_> = entries.into_iter()
+        .map(|(key, value)| format!("{}:{}", string(key.as_ref()), value))
+        .collect();
+    format!("{{{}}}", entries.join(","))
+}
+
+pub fn array<V: Display>(values: impl IntoIterator<Item = V>) -> String {
+    let values: Vec<_> = values.into_iter().map(|value| value.to_string()).collect();
+    format!("[{}]", values.join(","))
+}
+
+// Parsed JSON, numbers being restricted to integers.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(isize),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(bool) => write!(f, "{}", bool),
+            Json::Int(int) => write!(f, "{}", int),
+            Json::String(str) => f.write_str(&string(str)),
+            Json::Array(values) => f.write_str(&array(values)),
+            Json::Object(entries) => f.write_str(&object(entries.iter().map(|(key, value)| (key, value)))),
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Option<Json> {
+    let mut parser = Parser { chars: text.chars().collect(), position: 0 };
+    let value = parser.value()?;
+    parser.whitespace();
+    (parser.position == parser.chars.len()).then_some(value)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn whitespace(&mut self) {
+        while self.chars.get(self.position).is_some_and(|char| char.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let char = *self.chars.get(self.position)?;
+        self.position += 1;
+        Some(char)
+    }
+
+    fn expect(&mut self, word: &str) -> Option<()> {
+        word.chars().try_for_each(|expected| (self.next()? == expected).then_some(()))
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        self.whitespace();
+        match *self.chars.get(self.position)? {
+            'n' => self.expect("null").map(|_| Json::Null),
+            't' => self.expect("true").map(|_| Json::Bool(true)),
+            'f' => self.expect("false").map(|_| Json::Bool(false)),
+            '"' => self.string().map(Json::String),
+            '[' => {
+                self.position += 1;
+                let mut values = Vec::new();
+                self.whitespace();
+                if self.chars.get(self.position) == Some(&']') {
+                    self.position += 1;
+                    return Some(Json::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.whitespace();
+                    match self.next()? {
+                        ',' => continue,
+                        ']' => return Some(Json::Array(values)),
+                        _ => return None,
+                    }
+                }
+            }
+            '{' => {
+                self.position += 1;
+                let mut entries = Vec::new();
+                self.whitespace();
+                if self.chars.get(self.position) == Some(&'}') {
+                    self.position += 1;
+                    return Some(Json::Object(entries));
+                }
+                loop {
+                    self.whitespace();
+                    let key = self.string()?;
+                    self.whitespace();
+                    self.expect(":")?;
+                    entries.push((key, self.value()?));
+                    self.whitespace();
+                    match self.next()? {
+                        ',' => continue,
+                        '}' => return Some(Json::Object(entries)),
+                        _ => return None,
+                    }
+                }
+            }
+            _ => {
+                let start = self.position;
+                while self.chars.get(self.position).is_some_and(|char| *char == '-' || char.is_ascii_digit()) {
+                    self.position += 1;
+                }
+                self.chars[start..self.position].iter().collect::<String>().parse().ok().map(Json::Int)
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.expect("\"")?;
+        let mut string = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Some(string),
+                '\\' => string.push(match self.next()? {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'u' => {
+                        let hex: String = (0..4).map(|_| self.next()).collect::<Option<_>>()?;
+                        char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                    }
+                    char @ ('"' | '\\' | '/') => char,
+                    _ => return None,
+                }),
+                char => string.push(char),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json::{self, Json};
+
+    #[test]
+    fn string() {
+        assert_eq!(json::string("+ 3 2"), r#""+ 3 2""#);
+        assert_eq!(json::string("say \"hi\"\n"), r#""say \"hi\"\n""#);
+        assert_eq!(json::string("\\\u{1}"), r#""\\\u0001""#);
+    }
+
+    #[test]
+    fn object() {
+        assert_eq!(json::object([("a", 1), ("b", 2)]), r#"{"a":1,"b":2}"#);
+        assert_eq!(json::object(Vec::<(&str, usize)>::new()), "{}");
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(json::parse(r#" {"id": -7, "input": "+ 1 \"2\"\u0041", "tags": [true, null, {}, []]} "#), Some(Json::Object(vec![
+            (String::from("id"), Json::Int(-7)),
+            (String::from("input"), Json::String(String::from("+ 1 \"2\"A"))),
+            (String::from("tags"), Json::Array(vec![Json::Bool(true), Json::Null, Json::Object(vec![]), Json::Array(vec![])])),
+        ])));
+        for invalid in ["", "{", "[1,]", "{\"a\" 1}", "1.5", "\"\\x\"", "1 2"] {
+            assert_eq!(json::parse(invalid), None, "{}", invalid);
+        }
+        let value = json::parse(r#"{"a":[1,"b\n"],"c":null}"#).unwrap();
+        assert_eq!(value.to_string(), r#"{"a":[1,"b\n"],"c":null}"#);
+        assert_eq!(value.get("c"), Some(&Json::Null));
+    }
+}
@@ -0,0 +1,120 @@
+use std::ops::Range;
+
+use crate::{parse_line, Error, Value};
+
+// Keeps the tokens of the previously lexed line so that an edit only re-lexes
+// the tokens following the first changed byte.
+#[derive(Debug, Default)]
+pub struct IncrementalLexer {
+    line: String,
+    tokens: Vec<Range<usize>>,
+    reused: usize,
+}
+
+impl IncrementalLexer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, line: &str) {
+        let prefix = self.line.bytes()
+            .zip(line.bytes())
+            .take_while(|(old, new)| old == new)
+            .count();
+        // A token ending right at the change might have been extended by it.
+        self.reused = self.tokens.iter().take_while(|token| token.end < prefix).count();
+        self.tokens.truncate(self.reused);
+        self.line.clear();
+        self.line.push_str(line);
+
+        let offset = self.tokens.last().map_or(0, |token| token.end);
+        let mut start = None;
+        for (idx, char) in self.line[offset..].char_indices() {
+            let idx = offset + idx;
+            match (char.is_whitespace(), start) {
+                (true, Some(begin)) => {
+                    self.tokens.push(begin..idx);
+                    start = None;
+                }
+                (false, None) => start = Some(idx),
+                _ => {}
+            }
+        }
+        if let Some(begin) = start {
+            self.tokens.push(begin..self.line.len());
+        }
+    }
+
+    pub fn tokens(&self) -> impl Iterator<Item = &str> {
+        self.tokens.iter().map(|token| &self.line[token.clone()])
+    }
+
+    pub fn spans(&self) -> &[Range<usize>] {
+        &self.tokens
+    }
+
+    pub fn reused(&self) -> usize {
+        self.reused
+    }
+
+    pub fn parse(&self) -> Result<Value, Error> {
+        parse_line(self.tokens())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::IncrementalLexer;
+    use crate::*;
+
+    fn tokens(lexer: &IncrementalLexer) -> Vec<&str> {
+        lexer.tokens().collect()
+    }
+
+    #[test]
+    fn matches_split_whitespace() {
+        let mut lexer = IncrementalLexer::new();
+        for line in ["", "   ", "+ 3 2", "  * $0\t succ  7  ", "fact ünï 5"] {
+            lexer.update(line);
+            assert_eq!(tokens(&lexer), line.split_whitespace().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn reuses_unchanged_prefix() {
+        let mut lexer = IncrementalLexer::new();
+        lexer.update("+ 3 * 2");
+        assert_eq!(lexer.reused(), 0);
+
+        lexer.update("+ 3 * 2 5");
+        assert_eq!(lexer.reused(), 3);
+        assert_eq!(tokens(&lexer), ["+", "3", "*", "2", "5"]);
+
+        lexer.update("+ 3 * 2 56");
+        assert_eq!(lexer.reused(), 4);
+        assert_eq!(tokens(&lexer), ["+", "3", "*", "2", "56"]);
+
+        lexer.update("+ 4 * 2 56");
+        assert_eq!(lexer.reused(), 1);
+        assert_eq!(tokens(&lexer), ["+", "4", "*", "2", "56"]);
+
+        lexer.update("+ 4");
+        assert_eq!(lexer.reused(), 1);
+        assert_eq!(tokens(&lexer), ["+", "4"]);
+    }
+
+    #[test]
+    fn parse() {
+        let mut lexer = IncrementalLexer::new();
+        lexer.update("+ 3");
+        assert_eq!(lexer.parse(), Err(Error::new(ErrorCode::BinaryArity, "Binary operator '+' expected two arguments.")));
+        lexer.update("+ 3 2");
+        assert_eq!(lexer.parse(), Ok(BinaryOperation {
+            operator: Plus,
+            left: Box::new(Int(3)),
+            right: Box::new(Int(2)),
+        }));
+        lexer.update("+ 3 2 1");
+        assert_eq!(lexer.parse(), Err(Error::new(ErrorCode::TrailingInput, "Expected end of line, instead found '1'.")));
+    }
+}
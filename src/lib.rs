@@ -0,0 +1,758 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::registry::Operator;
+use crate::BinaryOperator::{Division, Minus, Multiplication, Plus};
+use crate::UnaryOperator::{Abs, Factorial, Negative, Predecessor, Signum, Successor};
+use crate::Value::{BinaryOperation, Int, Name, UnaryOperation, Variable};
+
+pub use crate::error::{Error, ErrorCode};
+
+#[cfg(feature = "clipboard")]
+pub mod clipboard;
+pub mod anonymize;
+pub mod capabilities;
+pub mod compat;
+pub mod config;
+pub mod corpus;
+pub mod editor;
+pub mod events;
+pub mod graph;
+pub mod grid;
+mod error;
+pub mod format;
+pub mod grade;
+mod json;
+pub mod lexer;
+pub mod lint;
+pub mod literate;
+pub mod minimize;
+pub mod notify;
+pub mod pager;
+pub mod protocol;
+pub mod refactor;
+pub mod registry;
+pub mod repl;
+pub mod report;
+pub mod script;
+pub mod session;
+pub mod settings;
+pub mod store;
+pub mod summary;
+pub mod teach;
+pub mod template;
+pub mod trace;
+pub mod tui;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BinaryOperator {
+    Division,
+    Minus,
+    Multiplication,
+    Plus,
+}
+
+impl FromStr for BinaryOperator {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match registry::lookup(s).map(|doc| doc.operator) {
+            Some(Operator::Binary(operator)) => Ok(operator),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(registry::doc(Operator::Binary(*self)).symbol())
+    }
+}
+
+fn factorial(n: isize) -> Option<isize> {
+    (1..=n).try_fold(1_isize, |product, k| product.checked_mul(k))
+}
+
+fn overflow(operator: impl fmt::Display) -> Error {
+    Error::new(ErrorCode::Overflow, format!("Result of '{}' overflows the integer range.", operator))
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum UnaryOperator {
+    Abs,
+    Factorial,
+    Negative,
+    Predecessor,
+    Signum,
+    Successor,
+}
+
+impl FromStr for UnaryOperator {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match registry::lookup(s).map(|doc| doc.operator) {
+            Some(Operator::Unary(operator)) => Ok(operator),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for UnaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(registry::doc(Operator::Unary(*self)).symbol())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    BinaryOperation {
+        operator: BinaryOperator,
+        left: Box<Value>,
+        right: Box<Value>,
+    },
+    Int(isize),
+    Name(String),
+    UnaryOperation {
+        operator: UnaryOperator,
+        arg: Box<Value>,
+    },
+    Variable(usize),
+}
+
+impl Value {
+    pub fn depth(&self) -> usize {
+        match self {
+            BinaryOperation { left, right, .. } => 1 + left.depth().max(right.depth()),
+            Int(_) | Name(_) | Variable(_) => 1,
+            UnaryOperation { arg, .. } => 1 + arg.depth(),
+        }
+    }
+
+    // Names in order of appearance, repeated as often as they appear.
+    pub fn names(&self) -> Vec<&str> {
+        match self {
+            BinaryOperation { left, right, .. } => [left.names(), right.names()].concat(),
+            Name(name) => vec![name],
+            Int(_) | Variable(_) => Vec::new(),
+            UnaryOperation { arg, .. } => arg.names(),
+        }
+    }
+
+    // History indices in order of appearance.
+    pub fn variables(&self) -> Vec<usize> {
+        match self {
+            BinaryOperation { left, right, .. } => [left.variables(), right.variables()].concat(),
+            Variable(idx) => vec![*idx],
+            Int(_) | Name(_) => Vec::new(),
+            UnaryOperation { arg, .. } => arg.variables(),
+        }
+    }
+
+    // The same expression with every history index replaced.
+    pub fn map_variables(&self, map: &impl Fn(usize) -> usize) -> Value {
+        match self {
+            BinaryOperation { operator, left, right } => BinaryOperation {
+                operator: *operator,
+                left: Box::new(left.map_variables(map)),
+                right: Box::new(right.map_variables(map)),
+            },
+            Variable(idx) => Variable(map(*idx)),
+            Int(_) | Name(_) => self.clone(),
+            UnaryOperation { operator, arg } => UnaryOperation { operator: *operator, arg: Box::new(arg.map_variables(map)) },
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            BinaryOperation { left, right, .. } => 1 + left.size() + right.size(),
+            Int(_) | Name(_) | Variable(_) => 1,
+            UnaryOperation { arg, .. } => 1 + arg.size(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BinaryOperation { operator, left, right } => write!(f, "{} {} {}", operator, left, right),
+            Int(int) => write!(f, "{}", int),
+            Name(name) => write!(f, "{}", name),
+            UnaryOperation { operator, arg } => write!(f, "{} {}", operator, arg),
+            Variable(idx) => write!(f, "${}", idx),
+        }
+    }
+}
+
+pub fn is_name(str: &str) -> bool {
+    let mut chars = str.chars();
+    chars.next().is_some_and(|char| char.is_alphabetic() || char == '_')
+        && chars.all(|char| char.is_alphanumeric() || char == '_')
+        && BinaryOperator::from_str(str).is_err()
+        && UnaryOperator::from_str(str).is_err()
+}
+
+// Integers are decimal with an optional sign, `+5` being 5, and an optional
+// `u` suffix for a literal that must not be negative. Returns `None` when the
+// token does not start like an integer, so that it can be something else.
+pub fn parse_int(str: &str) -> Option<Result<isize, Error>> {
+    let unsigned = str.strip_suffix('u');
+    let literal = unsigned.unwrap_or(str);
+    let magnitude = literal.strip_prefix(['+', '-']).unwrap_or(literal);
+    if !magnitude.starts_with(|char: char| char.is_ascii_digit() || char == '+' || char == '-') {
+        return None;
+    }
+    let invalid = |reason: &str| {
+        Some(Err(Error::new(ErrorCode::InvalidLiteral, format!("Invalid integer literal '{}': {}.", str, reason))))
+    };
+    if magnitude.starts_with(['+', '-']) {
+        return invalid("expected at most one sign");
+    }
+    if !magnitude.chars().all(|char| char.is_ascii_digit()) {
+        return invalid("expected only decimal digits");
+    }
+    if unsigned.is_some() && literal.starts_with('-') {
+        return invalid("unsigned literals cannot be negative");
+    }
+    Some(literal.parse().map_err(|_| {
+        Error::new(ErrorCode::Overflow, format!("Integer literal '{}' does not fit in {} bits.", str, isize::BITS))
+    }))
+}
+
+// Malformed literals are reported as such rather than as a missing operand.
+fn is_literal_error(err: &Error) -> bool {
+    matches!(err.code, ErrorCode::InvalidLiteral | ErrorCode::Overflow)
+}
+
+pub fn parse_value<'a, I: Iterator<Item = &'a str>>(iter: &mut I) -> Result<Value, Error> {
+    let Some(str) = iter.next() else {
+        return Err(Error::new(ErrorCode::EndOfInput, "Expected arguments at the end of input."));
+    };
+
+    if str.chars().next().unwrap_or_default() == '$' {
+        match str[1..].parse::<usize>() {
+            Ok(idx) => Ok(Variable(idx)),
+            Err(_) => Err(Error::new(ErrorCode::InvalidVariableName, format!(
+                "Expected valid number as a variable name, instead got '{}'.",
+                str
+            ))),
+        }
+    } else if let Some(int) = parse_int(str) {
+        int.map(Int)
+    } else if let Ok(op) = BinaryOperator::from_str(str) {
+        match (parse_value(iter), parse_value(iter)) {
+            (Ok(left), Ok(right)) => Ok(BinaryOperation {
+                operator: op,
+                left: Box::new(left),
+                right: Box::new(right),
+            }),
+            (Err(err), _) | (_, Err(err)) if is_literal_error(&err) => Err(err),
+            (Err(_), _) | (_, Err(_)) => {
+                Err(Error::new(ErrorCode::BinaryArity, format!("Binary operator '{}' expected two arguments.", str)))
+            }
+        }
+    } else if let Ok(op) = UnaryOperator::from_str(str) {
+        match parse_value(iter) {
+            Ok(value) => Ok(UnaryOperation {
+                operator: op,
+                arg: Box::new(value),
+            }),
+            Err(err) if is_literal_error(&err) => Err(err),
+            Err(_) => Err(Error::new(ErrorCode::UnaryArity, format!("Unary operator '{}' expected an argument.", str)))
+        }
+    } else if is_name(str) {
+        Ok(Name(String::from(str)))
+    } else {
+        Err(Error::new(ErrorCode::UnexpectedInput, format!("Unexpected input '{}'.", str)))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Context<'a> {
+    pub history: &'a [isize],
+    pub names: &'a HashMap<String, isize>,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(history: &'a [isize], names: &'a HashMap<String, isize>) -> Self {
+        Context { history, names }
+    }
+}
+
+pub fn evaluate_value(value: &Value, variables: &[isize]) -> Result<isize, Error> {
+    evaluate_in(value, &Context::new(variables, &HashMap::new()))
+}
+
+// Notified around the evaluation of every node, children being evaluated
+// in between, left to right.
+pub trait Observer {
+    fn enter(&mut self, _value: &Value) {}
+    fn leave(&mut self, _value: &Value, _result: &Result<isize, Error>) {}
+}
+
+impl Observer for () {}
+
+pub fn evaluate_in(value: &Value, ctx: &Context) -> Result<isize, Error> {
+    evaluate(value, ctx, None, &mut ())
+}
+
+pub fn evaluate_with_cancel(value: &Value, ctx: &Context, token: &CancelToken) -> Result<isize, Error> {
+    evaluate(value, ctx, Some(token), &mut ())
+}
+
+pub fn evaluate_observed(value: &Value, ctx: &Context, observer: &mut dyn Observer) -> Result<isize, Error> {
+    evaluate(value, ctx, None, observer)
+}
+
+fn evaluate(
+    value: &Value,
+    ctx: &Context,
+    token: Option<&CancelToken>,
+    observer: &mut dyn Observer,
+) -> Result<isize, Error> {
+    observer.enter(value);
+    let result = evaluate_node(value, ctx, token, observer);
+    observer.leave(value, &result);
+    result
+}
+
+fn evaluate_node(
+    value: &Value,
+    ctx: &Context,
+    token: Option<&CancelToken>,
+    observer: &mut dyn Observer,
+) -> Result<isize, Error> {
+    if token.is_some_and(CancelToken::is_cancelled) {
+        return Err(Error::new(ErrorCode::Cancelled, "Evaluation cancelled."));
+    }
+    match value {
+        BinaryOperation { operator, left, right } => {
+            match (evaluate(left, ctx, token, observer), evaluate(right, ctx, token, observer)) {
+                (Ok(lhs), Ok(rhs)) => {
+                    match operator {
+                        Division => {
+                            if rhs == 0 {
+                                Err(Error::new(ErrorCode::DivisionByZero, "Division by zero."))
+                            } else { lhs.checked_div(rhs).ok_or_else(|| overflow(operator)) }
+                        }
+                        Minus => lhs.checked_sub(rhs).ok_or_else(|| overflow(operator)),
+                        Multiplication => lhs.checked_mul(rhs).ok_or_else(|| overflow(operator)),
+                        Plus => lhs.checked_add(rhs).ok_or_else(|| overflow(operator)),
+                    }
+                }
+                (Err(msg), _) | (_, Err(msg)) => Err(msg),
+            }
+        }
+        Int(int) => Ok(*int),
+        Name(name) => match ctx.names.get(name) {
+            None => Err(Error::new(ErrorCode::UnknownName, format!("Unknown name '{}'.", name))),
+            Some(int) => Ok(*int),
+        },
+        UnaryOperation { operator, arg } => {
+            match evaluate(arg, ctx, token, observer) {
+                Ok(int) => match operator {
+                    Abs => int.checked_abs().ok_or_else(|| overflow(operator)),
+                    Negative => int.checked_neg().ok_or_else(|| overflow(operator)),
+                    Factorial => {
+                        if !int.is_negative() {
+                            factorial(int).ok_or_else(|| overflow(operator))
+                        } else {
+                            Err(Error::new(
+                                ErrorCode::NegativeFactorial,
+                                "Expected a non-negative number as an argument to factorial.",
+                            ))
+                        }
+                    }
+                    Predecessor => int.checked_sub(1).ok_or_else(|| overflow(operator)),
+                    Signum => Ok(int.signum()),
+                    Successor => int.checked_add(1).ok_or_else(|| overflow(operator)),
+                }
+                Err(msg) => Err(msg),
+            }
+        }
+        Variable(idx) => match ctx.history.get(*idx) {
+            None => Err(Error::new(ErrorCode::InvalidVariableIndex, format!("Invalid variable index '{}'.", idx))),
+            Some(int) => Ok(*int),
+        },
+    }
+}
+
+pub fn parse_line<'a, I: Iterator<Item = &'a str>>(mut iter: I) -> Result<Value, Error> {
+    let value = parse_value(&mut iter)?;
+    match iter.next() {
+        None => Ok(value),
+        Some(str) => Err(Error::new(ErrorCode::TrailingInput, format!("Expected end of line, instead found '{}'.", str))),
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum TrailingPolicy {
+    // Tokens after a complete expression are an error, as in worksheets.
+    #[default]
+    Reject,
+    // Tokens after a complete expression start the next one, as in quick-calc mode.
+    NextExpression,
+}
+
+pub fn parse_expressions<'a, I: Iterator<Item = &'a str>>(iter: I, policy: TrailingPolicy) -> Result<Vec<Value>, Error> {
+    match policy {
+        TrailingPolicy::Reject => Ok(vec![parse_line(iter)?]),
+        TrailingPolicy::NextExpression => {
+            let mut iter = iter.peekable();
+            let mut values = vec![parse_value(&mut iter)?];
+            while iter.peek().is_some() {
+                values.push(parse_value(&mut iter)?);
+            }
+            Ok(values)
+        }
+    }
+}
+
+pub fn process_line(line: String, history: &[isize]) -> Result<isize, Error> {
+    evaluate_value(&parse_line(line.split_whitespace())?, history)
+}
+
+// Evaluates every expression of the line in turn, pushing each result to history
+// so that the following expressions can refer to it. Nothing is evaluated when
+// the line does not parse.
+pub fn push_line(line: &str, history: &mut Vec<isize>, policy: TrailingPolicy) -> Result<(), Error> {
+    for value in parse_expressions(line.split_whitespace(), policy)? {
+        let result = evaluate_value(&value, history)?;
+        history.push(result);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    mod parser {
+        use crate::*;
+
+        fn to_result(str: &str) -> Result<Value, String> {
+            let mut iter = str.split_whitespace();
+            parse_value(&mut iter).map_err(|err| err.message)
+        }
+
+        #[test]
+        fn expressions() {
+            assert_eq!(to_result("+ 3 2"), Ok(BinaryOperation {
+                operator: Plus,
+                left: Box::new(Int(3)),
+                right: Box::new(Int(2)),
+            }));
+
+            assert_eq!(to_result("+ 3 * 8 / 2 3"), Ok(BinaryOperation {
+                operator: Plus,
+                left: Box::new(Int(3)),
+                right: Box::new(BinaryOperation {
+                    operator: Multiplication,
+                    left: Box::new(Int(8)),
+                    right: Box::new(BinaryOperation {
+                        operator: Division,
+                        left: Box::new(Int(2)),
+                        right: Box::new(Int(3)),
+                    }),
+                }),
+            }));
+        }
+
+        #[test]
+        fn variables() {
+            assert_eq!(to_result("- $0 $1"), Ok(BinaryOperation {
+                operator: Minus,
+                left: Box::new(Variable(0)),
+                right: Box::new(Variable(1)),
+            }));
+        }
+
+        #[test]
+        fn names() {
+            assert_eq!(to_result("* rate _total2"), Ok(BinaryOperation {
+                operator: Multiplication,
+                left: Box::new(Name(String::from("rate"))),
+                right: Box::new(Name(String::from("_total2"))),
+            }));
+            assert!(is_name("x"));
+            assert!(!is_name("abs"));
+            assert!(!is_name("2x"));
+            assert!(!is_name("$x"));
+        }
+
+        #[test]
+        fn display() {
+            for str in ["+ 3 * 8 / 2 3", "- $0 neg rate", "fact -5"] {
+                assert_eq!(to_result(str).unwrap().to_string(), str);
+            }
+            assert_eq!(to_result("!   3").unwrap().to_string(), "fact 3");
+        }
+
+        #[test]
+        fn errors() {
+            assert_eq!(to_result(""),
+                       Err(String::from("Expected arguments at the end of input.")));
+            assert_eq!(to_result("$a"),
+                       Err(String::from("Expected valid number as a variable name, instead got '$a'.")));
+            assert_eq!(to_result("* 1"),
+                       Err(String::from("Binary operator '*' expected two arguments.")));
+            assert_eq!(to_result("!#"),
+                       Err(String::from("Unexpected input '!#'.")));
+        }
+
+        #[test]
+        fn codes() {
+            let code = |str: &str| parse_line(str.split_whitespace()).unwrap_err().code;
+            assert_eq!(code(""), ErrorCode::EndOfInput);
+            assert_eq!(code("$a"), ErrorCode::InvalidVariableName);
+            assert_eq!(code("* 1"), ErrorCode::BinaryArity);
+            assert_eq!(code("abs"), ErrorCode::UnaryArity);
+            assert_eq!(code("!#"), ErrorCode::UnexpectedInput);
+            assert_eq!(code("-x"), ErrorCode::UnexpectedInput);
+            assert_eq!(code("+ 1 2x"), ErrorCode::InvalidLiteral);
+            assert_eq!(code("abs -5u"), ErrorCode::InvalidLiteral);
+            assert_eq!(code("* 99999999999999999999 1"), ErrorCode::Overflow);
+            assert_eq!(code("1 2"), ErrorCode::TrailingInput);
+        }
+
+        #[test]
+        fn literals() {
+            assert_eq!(parse_int("+5"), Some(Ok(5)));
+            assert_eq!(parse_int("-5"), Some(Ok(-5)));
+            assert_eq!(parse_int("5u"), Some(Ok(5)));
+            assert_eq!(parse_int("+0u"), Some(Ok(0)));
+            assert_eq!(parse_int("-9223372036854775808"), Some(Ok(isize::MIN)));
+            for str in ["-", "+", "x", "u", "-u", "$0"] {
+                assert_eq!(parse_int(str), None, "{}", str);
+            }
+            let code = |str: &str| parse_int(str).unwrap().unwrap_err().code;
+            for str in ["2x", "+-5", "--5", "-5u", "5uu", "1_000", "5-"] {
+                assert_eq!(code(str), ErrorCode::InvalidLiteral, "{}", str);
+            }
+            assert_eq!(code("9223372036854775808"), ErrorCode::Overflow);
+            assert_eq!(code("18446744073709551615u"), ErrorCode::Overflow);
+            assert_eq!(parse_line("+ +3 2u".split_whitespace()).unwrap().to_string(), "+ 3 2");
+        }
+    }
+
+    mod evaluator {
+        use crate::*;
+
+        fn to_result(str: &str) -> Result<isize, String> {
+            let mut history: Vec<isize> = Vec::new();
+            for line in str.lines() {
+                let mut iter = line.split_whitespace();
+                match evaluate_value(&parse_value(&mut iter).unwrap(), &history) {
+                    Ok(int) => history.push(int),
+                    Err(err) => return Err(err.message),
+                }
+            }
+            Ok(*history.last().unwrap())
+        }
+
+        #[test]
+        fn priority() {
+            assert_eq!(to_result("* + 3 - 2 1 / 16 4"), Ok(16))
+        }
+
+        #[test]
+        fn sequence() {
+            assert_eq!(
+                to_result(r#"
+                    + 3 2
+                    * 2 5
+                    / $1 $0
+                "#.trim()),
+                Ok(2),
+            )
+        }
+
+        #[test]
+        fn names() {
+            let names = HashMap::from([(String::from("rate"), 20)]);
+            let value = parse_line("* rate $0".split_whitespace()).unwrap();
+            assert_eq!(evaluate_in(&value, &Context::new(&[3], &names)), Ok(60));
+            assert_eq!(evaluate_value(&value, &[3]),
+                       Err(Error::new(ErrorCode::UnknownName, "Unknown name 'rate'.")));
+        }
+
+        #[test]
+        fn errors() {
+            assert_eq!(to_result("/ 2 0"),
+                       Err(String::from("Division by zero.")));
+            assert_eq!(to_result("fact -5"),
+                       Err(String::from("Expected a non-negative number as an argument to factorial.")));
+            assert_eq!(to_result("fact 0"), Ok(1));
+            assert_eq!(to_result("$2"),
+                       Err(String::from("Invalid variable index '2'.")));
+        }
+
+        #[test]
+        fn overflow() {
+            let code = |str: &str| process_line(String::from(str), &[]).unwrap_err().code;
+            assert_eq!(process_line(String::from("fact 20"), &[]), Ok(2_432_902_008_176_640_000));
+            for str in ["fact 21", "fact 100000000000", "* 9223372036854775807 2", "+ 9223372036854775807 1",
+                        "- -9223372036854775808 1", "/ -9223372036854775808 -1", "abs -9223372036854775808",
+                        "neg -9223372036854775808", "pred -9223372036854775808", "succ 9223372036854775807"] {
+                assert_eq!(code(str), ErrorCode::Overflow, "{}", str);
+            }
+        }
+
+        #[test]
+        fn codes() {
+            let code = |str: &str| process_line(String::from(str), &[]).unwrap_err().code;
+            assert_eq!(code("/ 2 0"), ErrorCode::DivisionByZero);
+            assert_eq!(code("fact -5"), ErrorCode::NegativeFactorial);
+            assert_eq!(code("$2"), ErrorCode::InvalidVariableIndex);
+        }
+    }
+
+    mod trailing {
+        use crate::*;
+
+        fn push(line: &str, policy: TrailingPolicy) -> (Vec<isize>, Option<ErrorCode>) {
+            let mut history = vec![10];
+            let result = push_line(line, &mut history, policy);
+            (history, result.err().map(|err| err.code))
+        }
+
+        #[test]
+        fn reject() {
+            assert_eq!(push("+ 3 2", TrailingPolicy::Reject), (vec![10, 5], None));
+            assert_eq!(push("+ 3 2 4", TrailingPolicy::Reject), (vec![10], Some(ErrorCode::TrailingInput)));
+        }
+
+        #[test]
+        fn next_expression() {
+            assert_eq!(push("+ 3 2", TrailingPolicy::NextExpression), (vec![10, 5], None));
+            assert_eq!(push("+ 3 2 4 * $1 $2", TrailingPolicy::NextExpression), (vec![10, 5, 4, 20], None));
+            assert_eq!(push("1 / $0 0 2", TrailingPolicy::NextExpression),
+                       (vec![10, 1], Some(ErrorCode::DivisionByZero)));
+            assert_eq!(push("1 2 +", TrailingPolicy::NextExpression), (vec![10], Some(ErrorCode::BinaryArity)));
+        }
+    }
+
+    mod cancellation {
+        use std::sync::mpsc;
+        use std::thread;
+        use std::time::{Duration, Instant};
+
+        use crate::*;
+
+        fn to_value(str: &str) -> Value {
+            parse_value(&mut str.split_whitespace()).unwrap()
+        }
+
+        #[test]
+        fn not_cancelled() {
+            let token = CancelToken::new();
+            assert_eq!(evaluate_with_cancel(&to_value("+ 3 2"), &Context::new(&[], &HashMap::new()), &token), Ok(5));
+        }
+
+        #[test]
+        fn cancelled() {
+            let token = CancelToken::new();
+            token.cancel();
+            assert_eq!(evaluate_with_cancel(&to_value("+ 3 2"), &Context::new(&[], &HashMap::new()), &token),
+                       Err(Error::new(ErrorCode::Cancelled, "Evaluation cancelled.")));
+        }
+
+        // Takes a millisecond per node, telling when the evaluation starts.
+        struct Slow {
+            started: Option<mpsc::Sender<()>>,
+            entered: usize,
+        }
+
+        impl Observer for Slow {
+            fn enter(&mut self, _value: &Value) {
+                if let Some(started) = self.started.take() {
+                    started.send(()).unwrap();
+                }
+                self.entered += 1;
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+
+        fn balanced(depth: usize) -> Value {
+            match depth {
+                0 => Int(1),
+                _ => BinaryOperation {
+                    operator: Plus,
+                    left: Box::new(balanced(depth - 1)),
+                    right: Box::new(balanced(depth - 1)),
+                },
+            }
+        }
+
+        #[test]
+        fn cancelled_from_another_thread() {
+            // 16383 nodes, which would take over 16 seconds to evaluate.
+            let value = balanced(13);
+            let token = CancelToken::new();
+            let (sender, receiver) = mpsc::channel();
+            let remote = token.clone();
+            let canceller = thread::spawn(move || {
+                receiver.recv().unwrap();
+                thread::sleep(Duration::from_millis(50));
+                remote.cancel();
+            });
+            let mut slow = Slow { started: Some(sender), entered: 0 };
+            let started = Instant::now();
+            let result = evaluate(&value, &Context::new(&[], &HashMap::new()), Some(&token), &mut slow);
+            canceller.join().unwrap();
+            assert_eq!(result, Err(Error::new(ErrorCode::Cancelled, "Evaluation cancelled.")));
+            assert!(started.elapsed() < Duration::from_secs(2), "took {:?}", started.elapsed());
+            assert!(slow.entered > 1 && slow.entered < 2000, "entered {} nodes", slow.entered);
+        }
+    }
+
+    mod concurrency {
+        use std::thread;
+
+        use crate::*;
+
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        #[test]
+        fn value_is_send_sync() {
+            assert_send_sync::<BinaryOperator>();
+            assert_send_sync::<UnaryOperator>();
+            assert_send_sync::<Value>();
+        }
+
+        #[test]
+        fn shared_evaluation() {
+            let value = parse_value(&mut "* + $0 $1 succ $2".split_whitespace()).unwrap();
+            let results: Vec<Result<isize, Error>> = thread::scope(|scope| {
+                let handles: Vec<_> = (0..8)
+                    .map(|i| {
+                        let value = &value;
+                        scope.spawn(move || evaluate_value(value, &[i, 1, 2]))
+                    })
+                    .collect();
+                handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+            });
+            for (i, result) in results.into_iter().enumerate() {
+                assert_eq!(result, Ok((i as isize + 1) * 3));
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::graph::Graph;
+use crate::script::{unbound_names, Location, ScriptError};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Lint {
+    UnusedResult,
+    DeadLine,
+    UndefinedVariable,
+    UndefinedName,
+}
+
+impl Lint {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Lint::UnusedResult => "unused-result",
+            Lint::DeadLine => "dead-line",
+            Lint::UndefinedVariable => "undefined-variable",
+            Lint::UndefinedName => "undefined-name",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Warning {
+    pub location: Location,
+    pub lint: Lint,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: warning[{}]: {}", self.location, self.lint.as_str(), self.message)
+    }
+}
+
+// Warnings about the run the graph was built from, sorted by line. Dead lines
+// are only reported when the run completed, as a failing line stops it.
+pub fn lint(graph: &Graph) -> Result<Vec<Warning>, ScriptError> {
+    let lines = graph.lines;
+    let mut warnings = Vec::new();
+    let mut warn = |idx: usize, lint, message| {
+        warnings.push((idx, Warning { location: lines[idx].location.clone(), lint, message }));
+    };
+
+    let referenced: HashSet<_> = graph.edges.iter().filter(|edge| edge.to.is_some()).map(|edge| edge.index).collect();
+    // The last result is what the script computes, whether referenced or not.
+    let mut producers: Vec<_> = graph.producers.iter().copied().collect::<HashSet<_>>().into_iter().collect();
+    producers.sort();
+    for idx in producers {
+        let last = graph.producers.last() == Some(&idx);
+        let used = graph.producers.iter().enumerate().any(|(entry, line)| *line == idx && referenced.contains(&entry));
+        if !last && !used {
+            warn(idx, Lint::UnusedResult, String::from("The result of this line is never referenced."));
+        }
+    }
+    if graph.error.is_none() {
+        for (idx, line) in lines.iter().enumerate() {
+            if !line.text.starts_with('@') && !graph.producers.contains(&idx) {
+                warn(idx, Lint::DeadLine, String::from("This line is never evaluated."));
+            }
+        }
+    }
+    for edge in &graph.edges {
+        match edge.to {
+            None => warn(edge.from, Lint::UndefinedVariable, format!("${} is never defined.", edge.index)),
+            Some(_) if edge.is_cyclic() => warn(
+                edge.from,
+                Lint::UndefinedVariable,
+                format!("${} is referenced before the line defining it, in an earlier iteration.", edge.index),
+            ),
+            Some(_) => {}
+        }
+    }
+    for unbound in unbound_names(lines)? {
+        let idx = lines.iter().position(|line| line == unbound.line).unwrap();
+        let message = match unbound.bound_elsewhere {
+            true => format!("'{}' is used outside of the lines binding it.", unbound.name),
+            false => format!("'{}' is never defined.", unbound.name),
+        };
+        warn(idx, Lint::UndefinedName, message);
+    }
+    warnings.sort_by_key(|(idx, warning)| (*idx, warning.lint));
+    Ok(warnings.into_iter().map(|(_, warning)| warning).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::graph::graph;
+    use crate::lint::{lint, Lint};
+    use crate::script::{split_lines, Runner};
+
+    #[test]
+    fn warnings() {
+        let text = "3\n4\n@if 0\n+ k $9\n@end\n@for i in 1..2\n@if - i 1\n+ $2 i\n@end\ni\n@end\n* $0 $4";
+        let lines = split_lines(Path::new("sheet.cwh"), text, 1);
+        let graph = graph(&lines, &mut Runner::new());
+        let warnings: Vec<_> = lint(&graph).unwrap().into_iter()
+            .map(|warning| (warning.location.line, warning.lint))
+            .collect();
+        assert_eq!(warnings, [
+            (2, Lint::UnusedResult),
+            (4, Lint::DeadLine),
+            (4, Lint::UndefinedVariable),
+            (4, Lint::UndefinedName),
+            (8, Lint::UnusedResult),
+            (8, Lint::UndefinedVariable),
+        ]);
+        assert_eq!(
+            lint(&graph).unwrap()[0].to_string(),
+            "sheet.cwh:2: warning[unused-result]: The result of this line is never referenced."
+        );
+    }
+}
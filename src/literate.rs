@@ -0,0 +1,92 @@
+use std::path::Path;
+
+use crate::script::{split_lines, Runner, ScriptError};
+
+pub const FENCE: &str = "```";
+pub const LANGUAGE: &str = "cwh";
+pub const OUTPUT: &str = "cwh-output";
+
+fn info(line: &str) -> Option<&str> {
+    line.trim().strip_prefix(FENCE).map(str::trim)
+}
+
+// Index of the line closing the fenced block opened on line `start`, or the
+// count of lines when it is never closed.
+fn closing(lines: &[&str], start: usize) -> usize {
+    (start + 1..lines.len()).find(|idx| info(lines[*idx]) == Some("")).unwrap_or(lines.len())
+}
+
+// Evaluates the `cwh` blocks of a Markdown document in order with a shared
+// history, putting what every block prints in a `cwh-output` block right
+// after it, which replaces the one left there by a previous run. Blocks after
+// a failing one are left untouched.
+pub fn render(text: &str, path: &Path, runner: &mut Runner) -> (String, Option<ScriptError>) {
+    let lines: Vec<_> = text.lines().collect();
+    let mut rendered = String::new();
+    let mut error = None;
+    let mut idx = 0;
+    while idx < lines.len() {
+        let start = idx;
+        let end = match info(lines[start]) {
+            Some(LANGUAGE) => closing(&lines, start),
+            _ => lines.len(),
+        };
+        if end == lines.len() {
+            rendered.push_str(lines[start]);
+            rendered.push('\n');
+            idx += 1;
+            continue;
+        }
+        for line in &lines[start..=end] {
+            rendered.push_str(line);
+            rendered.push('\n');
+        }
+        idx = end + 1;
+        if error.is_some() {
+            continue;
+        }
+        let mut output = Vec::new();
+        let code = split_lines(path, &lines[start + 1..end].join("\n"), start + 2);
+        if let Err(err) = runner.run(&code, &mut |line| output.push(line)) {
+            error = Some(err);
+            continue;
+        }
+        if idx < lines.len() && info(lines[idx]) == Some(OUTPUT) {
+            idx = closing(&lines, idx) + 1;
+        }
+        if !output.is_empty() {
+            rendered.push_str(&format!("{}{}\n{}\n{}\n", FENCE, OUTPUT, output.join("\n"), FENCE));
+        }
+    }
+    (rendered, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::literate::render;
+    use crate::script::Runner;
+    use crate::ErrorCode;
+
+    #[test]
+    fn blocks() {
+        let text = "# Costs\n```cwh\n+ 3 2 ; base\n```\n```cwh-output\n4\n```\nText.\n```rust\n1\n```\n\
+            ```cwh\n* $0 2\n@print \"total {$1}\"\n```\n";
+        let (rendered, error) = render(text, Path::new("costs.md"), &mut Runner::new());
+        assert_eq!(error, None);
+        assert_eq!(rendered, "# Costs\n```cwh\n+ 3 2 ; base\n```\n```cwh-output\n5\n```\nText.\n```rust\n1\n```\n\
+            ```cwh\n* $0 2\n@print \"total {$1}\"\n```\n```cwh-output\n10\ntotal 10\n```\n");
+        assert_eq!(render(&rendered, Path::new("costs.md"), &mut Runner::new()).0, rendered);
+    }
+
+    #[test]
+    fn failing() {
+        let text = "```cwh\n1\n\n/ $0 0\n```\n```cwh\n2\n```\n```cwh-output\n7\n```\n";
+        let (rendered, error) = render(text, Path::new("costs.md"), &mut Runner::new());
+        let error = error.unwrap();
+        assert_eq!(error.error.code, ErrorCode::DivisionByZero);
+        assert_eq!(error.location.unwrap().line, 4);
+        assert_eq!(rendered, text);
+    }
+}
@@ -1,299 +1,692 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
 use std::io;
-use std::io::Write;
-use std::str::{FromStr, SplitWhitespace};
-
-use crate::BinaryOperator::{Division, Minus, Multiplication, Plus};
-use crate::UnaryOperator::{Abs, Factorial, Negative, Predecessor, Signum, Successor};
-use crate::Value::{BinaryOperation, Int, UnaryOperation, Variable};
-
-#[derive(Copy, Clone, Debug, PartialEq)]
-enum BinaryOperator {
-    Division,
-    Minus,
-    Multiplication,
-    Plus,
+use std::io::{IsTerminal, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cwh::compat;
+use cwh::config::Config;
+use cwh::corpus::{self, Limits, Outcome};
+use cwh::format::{format, Layout, Style};
+use cwh::events::{self, Event};
+use cwh::grade::{grade, parse_exercises};
+use cwh::graph::graph;
+use cwh::lint::lint;
+use cwh::capabilities::features;
+use cwh::literate;
+use cwh::minimize::{minimize, Predicate};
+use cwh::notify::Notify;
+use cwh::protocol;
+use cwh::refactor::renumber;
+use cwh::{pager, registry, tui};
+use cwh::repl::Repl;
+use cwh::report::{load_inputs, Report};
+use cwh::script::{self, Cache, Runner};
+use cwh::summary::Summary;
+use cwh::teach::Rules;
+use cwh::{parse_line, ErrorCode, TrailingPolicy};
+
+const USAGE: &str = "Usage: cwh [[--echo | --summary | --summary-json <file>]... [--quick | --teach <rules>] | --compat bc | --protocol simple-json | --events | tui | selftest | replay-corpus <dir> \
+    | minimize <expr> [--predicate <error | panic | error-code=<code>>] | explain <code> | report <file> [--json] \
+    | capabilities [--json] | grade <exercises> <submissions dir> [--json] \
+    | run <file> [--param <name>=<int> | --notify-after <seconds> | --notify-command <command> | --summary \
+    | --summary-json <file>]... \
+    | watch <file> [--param <name>=<int>]... | graph <file> [--dot] [--param <name>=<int>]... \
+    | check <file> [--lint] [--param <name>=<int>]... | refactor renumber <file> --from <old file> \
+    | fmt <file>... [--check | --sexpr | --width <columns>]... | md <file> [--output <file>]]";
+
+// Lines up to this width are never wrapped, which spares querying the terminal.
+const MIN_COLUMNS: usize = 40;
+
+// Marks the input lines echoed before their results.
+const ECHO_PREFIX: &str = "> ";
+
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+fn usage() -> ExitCode {
+    eprintln!("{}", USAGE);
+    ExitCode::from(2)
 }
 
-impl FromStr for BinaryOperator {
-    type Err = ();
+// Where to report the summary of a batch run: printed to standard error,
+// written as JSON to a file, or both.
+#[derive(Default)]
+struct Reporting<'a> {
+    print: bool,
+    json: Option<&'a str>,
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "/" => Ok(Division),
-            "-" => Ok(Minus),
-            "*" => Ok(Multiplication),
-            "+" => Ok(Plus),
-            _ => Err(()),
+impl Reporting<'_> {
+    fn is_enabled(&self) -> bool {
+        self.print || self.json.is_some()
+    }
+
+    fn report(&self, summary: &Summary) {
+        if self.print {
+            eprintln!("{}", summary);
+        }
+        if let Some(path) = self.json {
+            if let Err(err) = fs::write(path, summary.to_json() + "\n") {
+                eprintln!("Error: Cannot write '{}': {}.", path, err);
+            }
         }
     }
 }
 
-fn factorial(n: usize) -> usize {
-    if n == 0 {
-        1
-    } else {
-        n * factorial(n - 1)
+fn new_prompt() {
+    print!("# ");
+    io::stdout().flush().unwrap();
+}
+
+fn config() -> Config {
+    match Config::path().map(|path| Config::load(&path)) {
+        Some(Ok(config)) => config,
+        Some(Err(err)) => {
+            eprintln!("{}", err);
+            Config::default()
+        }
+        None => Config::default(),
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
-enum UnaryOperator {
-    Abs,
-    Factorial,
-    Negative,
-    Predecessor,
-    Signum,
-    Successor,
+// With `echo` and input not typed in a terminal, every line is printed
+// before its result in place of the prompt, for the output to read on its own.
+fn repl(policy: TrailingPolicy, rules: Rules, echo: bool, reporting: Reporting) -> ExitCode {
+    let mut repl = Repl { rules, ..Repl::with_config(policy, config()) };
+    let mut summary = Summary::default();
+    let started = Instant::now();
+    let echo = echo && !io::stdin().is_terminal();
+    let prompt = || if !echo { new_prompt() };
+    prompt();
+    for line in io::stdin().lines() {
+        let line = line.unwrap();
+        if line.is_empty() {
+            prompt();
+            continue;
+        }
+        if echo {
+            println!("{}{}", ECHO_PREFIX, line);
+        }
+        let response = repl.handle(&line);
+        summary.record(response.error.as_ref());
+        let paged = repl.settings.pager
+            && response.lines.len() > 1
+            && io::stdout().is_terminal()
+            && response.lines.len() >= pager::terminal_size().0;
+        if paged {
+            if let Err(err) = pager::page(&response.lines) {
+                eprintln!("{}", err);
+            }
+        } else {
+            let columns = match response.lines.iter().any(|line| line.len() > MIN_COLUMNS) {
+                true => pager::terminal_size().1,
+                false => MIN_COLUMNS,
+            };
+            for line in response.lines.iter().flat_map(|line| pager::wrap(line, columns)) {
+                println!("{}", line);
+            }
+        }
+        if let Some(err) = response.error {
+            eprintln!("{}", err);
+        }
+        prompt();
+    }
+    if !echo {
+        println!();
+    }
+    summary.elapsed = started.elapsed();
+    summary.history = repl.session.len();
+    reporting.report(&summary);
+    ExitCode::SUCCESS
 }
 
-impl FromStr for UnaryOperator {
-    type Err = ();
+fn full_screen() -> ExitCode {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        eprintln!("Error: cwh tui has to run in a terminal.");
+        return ExitCode::FAILURE;
+    }
+    match tui::run(Repl::with_config(TrailingPolicy::Reject, config())) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "abs" => Ok(Abs),
-            "fact" | "!" => Ok(Factorial),
-            "neg" => Ok(Negative),
-            "pred" => Ok(Predecessor),
-            "sgn" => Ok(Signum),
-            "succ" => Ok(Successor),
-            _ => Err(()),
+// Behaves like bc reading its standard input: no prompt, results on
+// standard output and errors on standard error.
+fn compat_bc() -> ExitCode {
+    let mut history = Vec::new();
+    for line in io::stdin().lines() {
+        let line = line.unwrap();
+        if line.trim() == "quit" {
+            break;
+        }
+        for result in compat::bc_line(&line, &mut history) {
+            match result {
+                Ok(int) => println!("{}", int),
+                Err(err) => eprintln!("{}", err),
+            }
         }
     }
+    ExitCode::SUCCESS
 }
 
-#[derive(Debug, PartialEq)]
-enum Value {
-    BinaryOperation {
-        operator: BinaryOperator,
-        left: Box<Value>,
-        right: Box<Value>,
-    },
-    Int(isize),
-    UnaryOperation {
-        operator: UnaryOperator,
-        arg: Box<Value>,
-    },
-    Variable(usize),
+// Answers every line of standard input with a line of JSON, flushed right
+// away for the integration waiting on it.
+fn simple_json() -> ExitCode {
+    let mut repl = Repl::new(TrailingPolicy::Reject);
+    for line in io::stdin().lines() {
+        let line = line.unwrap();
+        if line.trim().is_empty() {
+            continue;
+        }
+        println!("{}", protocol::respond(&mut repl, &line));
+        io::stdout().flush().unwrap();
+    }
+    ExitCode::SUCCESS
 }
 
-fn parse_value(iter: &mut SplitWhitespace) -> Result<Value, String> {
-    let Some(str) = iter.next() else {
-        return Err(String::from("Expected arguments at the end of input."));
+// The REPL for wrapper interfaces, printing a line of JSON per event.
+fn event_stream() -> ExitCode {
+    let mut repl = Repl::new(TrailingPolicy::Reject);
+    let mut emit = |event: Event| {
+        println!("{}", event.to_json());
+        io::stdout().flush().unwrap();
     };
+    emit(Event::Prompt);
+    for line in io::stdin().lines() {
+        let line = line.unwrap();
+        if !line.is_empty() {
+            events::handle(&mut repl, &line, &mut emit);
+        }
+        emit(Event::Prompt);
+    }
+    ExitCode::SUCCESS
+}
 
-    if str.chars().nth(0).unwrap_or_default() == '$' {
-        match (&str[1..]).parse::<usize>() {
-            Ok(idx) => Ok(Variable(idx)),
-            Err(_) => Err(format!(
-                "Expected valid number as a variable name, instead got '{}'.",
-                str
-            )),
-        }
-    } else if let Ok(int) = str.parse::<isize>() {
-        Ok(Int(int))
-    } else if let Ok(op) = BinaryOperator::from_str(str) {
-        match (parse_value(iter), parse_value(iter)) {
-            (Ok(left), Ok(right)) => Ok(BinaryOperation {
-                operator: op,
-                left: Box::new(left),
-                right: Box::new(right),
-            }),
-            (Err(_), _) | (_, Err(_)) => {
-                Err(format!("Binary operator '{}' expected two arguments.", str))
+fn selftest() -> ExitCode {
+    let failures = registry::selftest();
+    for failure in &failures {
+        eprintln!("{}", failure);
+    }
+    if failures.is_empty() {
+        println!("All operator examples hold.");
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn replay_corpus(dir: &str) -> ExitCode {
+    let entries = match corpus::entries(Path::new(dir)) {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    // Panics are reported per entry, the default hook would only add noise.
+    panic::set_hook(Box::new(|_| {}));
+    let mut failures = 0;
+    for path in &entries {
+        let outcome = match fs::read_to_string(path) {
+            Ok(text) => corpus::replay(text, Limits::default()),
+            Err(err) => {
+                eprintln!("{}: Cannot read: {}.", path.display(), err);
+                failures += 1;
+                continue;
+            }
+        };
+        match &outcome {
+            Outcome::Completed { results, errors } => {
+                println!("{}: ok, {} results, {} errors", path.display(), results, errors);
             }
+            Outcome::Panicked(message) => println!("{}: PANIC {}", path.display(), message),
+            Outcome::TimedOut => println!("{}: TIMEOUT", path.display()),
+        }
+        if outcome.is_failure() {
+            failures += 1;
+        }
+    }
+    let _ = panic::take_hook();
+    println!("{} entries replayed, {} failed.", entries.len(), failures);
+    if failures == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn minimize_expression(expr: &str, predicate: Option<&str>) -> ExitCode {
+    let value = match parse_line(expr.split_whitespace()) {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
         }
-    } else if let Ok(op) = UnaryOperator::from_str(str) {
-        match parse_value(iter) {
-            Ok(value) => Ok(UnaryOperation {
-                operator: op,
-                arg: Box::new(value),
-            }),
-            Err(_) => Err(format!("Unary operator '{}' expected an argument.", str))
+    };
+    // Panicking candidates are expected while minimizing.
+    panic::set_hook(Box::new(|_| {}));
+    let predicate = match predicate {
+        Some(predicate) => predicate.parse(),
+        None => Predicate::of(&value).ok_or_else(|| String::from("The expression evaluates without failing.")),
+    };
+    let result = predicate.and_then(|predicate| {
+        if predicate.holds(&value) {
+            Ok(minimize(&value, predicate))
+        } else {
+            Err(String::from("The expression does not satisfy the predicate."))
+        }
+    });
+    let _ = panic::take_hook();
+    match result {
+        Ok(value) => {
+            println!("{}", value);
+            ExitCode::SUCCESS
         }
+        Err(message) => {
+            eprintln!("Error: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn explain(code: &str) -> ExitCode {
+    match ErrorCode::from_str(code) {
+        Ok(code) => {
+            println!("{}", code.explanation());
+            ExitCode::SUCCESS
+        }
+        Err(_) => {
+            eprintln!("Error: '{}' is not a known error code.", code);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn report(path: &str, json: bool) -> ExitCode {
+    let inputs = match load_inputs(Path::new(path)) {
+        Ok(inputs) => inputs,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let report = Report::new(inputs.iter().map(String::as_str));
+    if json {
+        println!("{}", report.to_json());
     } else {
-        Err(format!("Unexpected input '{}'.", str))
+        println!("{}", report);
     }
+    ExitCode::SUCCESS
 }
 
-fn evaluate_value(value: &Value, variables: &[isize]) -> Result<isize, String> {
-    match value {
-        BinaryOperation { operator, left, right } => {
-            match (evaluate_value(left, variables), evaluate_value(right, variables)) {
-                (Ok(lhs), Ok(rhs)) => {
-                    match operator {
-                        Division => {
-                            if rhs == 0 {
-                                Err(String::from("Division by zero."))
-                            } else { Ok(lhs / rhs) }
-                        }
-                        Minus => Ok(lhs - rhs),
-                        Multiplication => Ok(lhs * rhs),
-                        Plus => Ok(lhs + rhs),
-                    }
+// Prints a report per student, as JSON objects one per line with `json`.
+fn grade_submissions(path: &str, dir: &str, json: bool) -> ExitCode {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("Error: Cannot read '{}': {}", path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let exercises = match parse_exercises(&text) {
+        Ok(exercises) => exercises,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    match grade(&exercises, Path::new(dir)) {
+        Ok(grades) => {
+            for grade in grades {
+                match json {
+                    true => println!("{}", grade.to_json()),
+                    false => println!("{}", grade),
                 }
-                (Err(msg), _) | (_, Err(msg)) => Err(msg),
             }
+            ExitCode::SUCCESS
         }
-        Int(int) => Ok(*int),
-        UnaryOperation { operator, arg } => {
-            match evaluate_value(arg, variables) {
-                Ok(int) => match operator {
-                    Abs => Ok(int.abs()),
-                    Negative => Ok(-int),
-                    Factorial => {
-                        if int.is_positive() {
-                            Ok(factorial(int as usize) as isize)
-                        } else {
-                            Err(String::from("Expected a non-negative number as an argument to factorial."))
-                        }
-                    }
-                    Predecessor => Ok(int - 1),
-                    Signum => Ok(int.signum()),
-                    Successor => Ok(int + 1),
-                }
-                Err(msg) => Err(msg),
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_run_options(args: &[&str]) -> Option<(HashMap<String, isize>, Notify)> {
+    let mut params = HashMap::new();
+    let mut notify = Notify::default();
+    for pair in args.chunks(2) {
+        match pair {
+            ["--param", param] => {
+                let (name, int) = param.split_once('=')?;
+                params.insert(String::from(name), int.parse().ok()?);
             }
+            ["--notify-after", seconds] => notify.after = Some(Duration::from_secs(seconds.parse().ok()?)),
+            ["--notify-command", command] => notify.command = Some(String::from(*command)),
+            _ => return None,
         }
-        Variable(idx) => match variables.get(*idx) {
-            None => Err(format!("Invalid variable index '{}'.", idx)),
-            Some(int) => Ok(*int),
-        },
     }
+    Some((params, notify))
 }
 
-fn process_line(line: String, history: &[isize]) -> Result<isize, String> {
-    let mut iter = line.split_whitespace();
-    match parse_value(&mut iter) {
-        Ok(value) => match iter.next() {
-            None => evaluate_value(&value, history),
-            Some(str) => Err(format!("Expected end of line, instead found '{}'.", str)),
-        },
-        Err(msg) => Err(msg),
+fn run(path: &str, params: HashMap<String, isize>, notify: Notify, reporting: Reporting) -> ExitCode {
+    let mut runner = Runner::new();
+    runner.params = params;
+    let started = Instant::now();
+    let result = script::load(Path::new(path))
+        .and_then(|lines| runner.run(&lines, &mut |line| println!("{}", line)));
+    if reporting.is_enabled() {
+        let mut summary = Summary {
+            lines: runner.executed(),
+            elapsed: started.elapsed(),
+            history: runner.history.len(),
+            ..Summary::default()
+        };
+        if let Err(err) = &result {
+            summary.failed(&err.error);
+        }
+        reporting.report(&summary);
+    }
+    let summary = match &result {
+        Ok(()) => format!("Finished running '{}'.", path),
+        Err(err) => err.to_string(),
+    };
+    if let Err(err) = notify.finished(started.elapsed(), &summary) {
+        eprintln!("{}", err);
+    }
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
     }
 }
 
-fn new_prompt() {
-    print!("# ");
-    io::stdout().flush().unwrap();
+// Reruns the script whenever it or one of its includes changes, reusing the
+// results of the statements unchanged since the previous run.
+fn watch(path: &str, params: HashMap<String, isize>) -> ExitCode {
+    let modified = |path: &PathBuf| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    let mut cache = Cache::default();
+    loop {
+        let mut runner = Runner::new();
+        runner.params = params.clone();
+        let mut paths = vec![PathBuf::from(path)];
+        match script::load(Path::new(path)) {
+            Ok(lines) => {
+                paths.extend(lines.iter().map(|line| line.location.path.clone()));
+                match runner.run_cached(&lines, &mut |line| println!("{}", line), &mut cache) {
+                    Ok(reused) => eprintln!("-- Reused {} statements, watching for changes.", reused),
+                    Err(err) => eprintln!("{}", err),
+                }
+            }
+            Err(err) => eprintln!("{}", err),
+        }
+        paths.sort();
+        paths.dedup();
+        let stamps: Vec<_> = paths.iter().map(modified).collect();
+        while paths.iter().map(modified).eq(stamps.iter().cloned()) {
+            thread::sleep(WATCH_INTERVAL);
+        }
+    }
 }
 
-fn main() {
-    let mut history: Vec<isize> = Vec::new();
-    new_prompt();
-    for line in io::stdin().lines() {
-        let line = line.unwrap();
-        if line.is_empty() {
-            new_prompt();
-            continue;
+fn graph_script(path: &str, params: HashMap<String, isize>, dot: bool) -> ExitCode {
+    let lines = match script::load(Path::new(path)) {
+        Ok(lines) => lines,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
         }
-        match process_line(line, &history) {
-            Ok(result) => {
-                history.push(result);
-                println!("{}", result);
-            }
-            Err(msg) => eprintln!("Error: {}", msg),
+    };
+    let mut runner = Runner::new();
+    runner.params = params;
+    let graph = graph(&lines, &mut runner);
+    // Lines after a failing one produce nothing, the graph is still useful up to it.
+    if let Some(err) = &graph.error {
+        eprintln!("{}", err);
+    }
+    if dot {
+        print!("{}", graph.to_dot());
+    } else {
+        print!("{}", graph.to_text());
+    }
+    ExitCode::SUCCESS
+}
+
+// Runs the script without printing its results, only reporting whether it
+// fails and, with `lint`, the warnings about it.
+fn check(path: &str, params: HashMap<String, isize>, lint_script: bool) -> ExitCode {
+    let lines = match script::load(Path::new(path)) {
+        Ok(lines) => lines,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut runner = Runner::new();
+    runner.params = params;
+    let graph = graph(&lines, &mut runner);
+    if lint_script {
+        match lint(&graph) {
+            Ok(warnings) => warnings.iter().for_each(|warning| println!("{}", warning)),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+    match &graph.error {
+        None => ExitCode::SUCCESS,
+        Some(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
         }
-        new_prompt();
     }
-    println!();
 }
 
-#[cfg(test)]
-mod tests {
-    mod parser {
-        use crate::*;
-
-        fn to_result(str: &str) -> Result<Value, String> {
-            let mut iter = str.split_whitespace();
-            parse_value(&mut iter)
-        }
-
-        #[test]
-        fn expressions() {
-            assert_eq!(to_result("+ 3 2"), Ok(BinaryOperation {
-                operator: Plus,
-                left: Box::new(Int(3)),
-                right: Box::new(Int(2)),
-            }));
-
-            assert_eq!(to_result("+ 3 * 8 / 2 3"), Ok(BinaryOperation {
-                operator: Plus,
-                left: Box::new(Int(3)),
-                right: Box::new(BinaryOperation {
-                    operator: Multiplication,
-                    left: Box::new(Int(8)),
-                    right: Box::new(BinaryOperation {
-                        operator: Division,
-                        left: Box::new(Int(2)),
-                        right: Box::new(Int(3)),
-                    }),
-                }),
-            }));
-        }
-
-        #[test]
-        fn variables() {
-            assert_eq!(to_result("- $0 $1"), Ok(BinaryOperation {
-                operator: Minus,
-                left: Box::new(Variable(0)),
-                right: Box::new(Variable(1)),
-            }));
-        }
-
-        #[test]
-        fn errors() {
-            assert_eq!(to_result(""),
-                       Err(String::from("Expected arguments at the end of input.")));
-            assert_eq!(to_result("$a"),
-                       Err(String::from("Expected valid number as a variable name, instead got '$a'.")));
-            assert_eq!(to_result("* 1"),
-                       Err(String::from("Binary operator '*' expected two arguments.")));
-            assert_eq!(to_result("!#"),
-                       Err(String::from("Unexpected input '!#'.")));
-        }
-    }
-
-    mod evaluator {
-        use crate::*;
-
-        fn to_result(str: &str) -> Result<isize, String> {
-            let mut history: Vec<isize> = Vec::new();
-            for line in str.lines() {
-                let mut iter = line.split_whitespace();
-                match evaluate_value(&parse_value(&mut iter).unwrap(), &history) {
-                    Ok(int) => history.push(int),
-                    Err(msg) => return Err(msg),
-                }
+// Rewrites the script in place so that its references follow the lines
+// inserted or deleted since the old version.
+fn renumber_script(path: &str, old: &str) -> ExitCode {
+    let read = |path: &str| fs::read_to_string(path).map_err(|err| format!("Error: Cannot read '{}': {}.", path, err));
+    let renumbered = read(old).and_then(|old| {
+        let new = read(path)?;
+        renumber(&old, &new).map_err(|err| err.to_string())
+    });
+    let renumbered = match renumbered {
+        Ok(renumbered) => renumbered,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(err) = fs::write(path, &renumbered.text) {
+        eprintln!("Error: Cannot write '{}': {}.", path, err);
+        return ExitCode::FAILURE;
+    }
+    for (line, index) in &renumbered.dangling {
+        eprintln!("{}:{}: ${} refers to a deleted line.", path, line, index);
+    }
+    println!("Renumbered {} references.", renumbered.changed);
+    ExitCode::SUCCESS
+}
+
+// Formats the files in place or, with `--check`, lists those which would
+// change and fails if there are any. The `--sexpr` layout cannot run, so it
+// is only ever printed.
+fn fmt(args: &[&str]) -> ExitCode {
+    let (mut paths, mut style, mut check) = (Vec::new(), Style::default(), false);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--check" => check = true,
+            "--sexpr" => style.layout = Layout::Sexpr,
+            "--width" => match args.next().and_then(|width| width.parse().ok()) {
+                Some(width) => style.width = Some(width),
+                None => return usage(),
+            },
+            path => paths.push(path),
+        }
+    }
+    let print = style.layout == Layout::Sexpr;
+    if paths.is_empty() || (print && check) {
+        return usage();
+    }
+    let mut failed = false;
+    for path in paths {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) => {
+                eprintln!("Error: Cannot read '{}': {}.", path, err);
+                failed = true;
+                continue;
             }
-            Ok(*history.last().unwrap())
+        };
+        let formatted = format(&text, &style);
+        if print {
+            print!("{}", formatted);
+            continue;
+        }
+        if formatted == text {
+            continue;
         }
+        if check {
+            println!("{} is not formatted.", path);
+            failed = true;
+        } else if let Err(err) = fs::write(path, formatted) {
+            eprintln!("Error: Cannot write '{}': {}.", path, err);
+            failed = true;
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
 
-        #[test]
-        fn priority() {
-            assert_eq!(to_result("* + 3 - 2 1 / 16 4"), Ok(16))
+// Evaluates the document and writes it back with the results of its blocks,
+// to `output` if given, even when a block fails.
+fn markdown(path: &str, output: Option<&str>) -> ExitCode {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("Error: Cannot read '{}': {}.", path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let (rendered, error) = literate::render(&text, Path::new(path), &mut Runner::new());
+    let output = output.unwrap_or(path);
+    if let Err(err) = fs::write(output, rendered) {
+        eprintln!("Error: Cannot write '{}': {}.", output, err);
+        return ExitCode::FAILURE;
+    }
+    match error {
+        None => ExitCode::SUCCESS,
+        Some(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
         }
+    }
+}
+
+// Splits a flag off the other options, wherever it is.
+fn take_flag<'a>(args: &[&'a str], flag: &str) -> (bool, Vec<&'a str>) {
+    (args.contains(&flag), args.iter().copied().filter(|arg| *arg != flag).collect())
+}
 
-        #[test]
-        fn sequence() {
-            assert_eq!(
-                to_result(r#"
-                    + 3 2
-                    * 2 5
-                    / $1 $0
-                "#.trim()),
-                Ok(2),
-            )
+// Splits the summary options off the other options, wherever they are.
+// Returns `None` when `--summary-json` lacks its file.
+fn take_reporting<'a>(args: &[&'a str]) -> Option<(Reporting<'a>, Vec<&'a str>)> {
+    let (print, mut rest) = take_flag(args, "--summary");
+    let json = match rest.iter().position(|arg| *arg == "--summary-json") {
+        Some(idx) => {
+            let path = *rest.get(idx + 1)?;
+            rest.drain(idx..idx + 2);
+            Some(path)
         }
+        None => None,
+    };
+    Some((Reporting { print, json }, rest))
+}
 
-        #[test]
-        fn errors() {
-            assert_eq!(to_result("/ 2 0"),
-                       Err(String::from("Division by zero.")));
-            assert_eq!(to_result("fact -5"),
-                       Err(String::from("Expected a non-negative number as an argument to factorial.")));
-            assert_eq!(to_result("$2"),
-                       Err(String::from("Invalid variable index '2'.")));
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<_> = args.iter().map(String::as_str).collect();
+    let (echo, repl_args) = take_flag(&args, "--echo");
+    let Some((reporting, repl_args)) = take_reporting(&repl_args) else { return usage() };
+    match repl_args.as_slice() {
+        [] => return repl(TrailingPolicy::Reject, Rules::default(), echo, reporting),
+        ["--quick"] => return repl(TrailingPolicy::NextExpression, Rules::default(), echo, reporting),
+        ["--teach", path] => return match Rules::load(Path::new(path)) {
+            Ok(rules) => repl(TrailingPolicy::Reject, rules, echo, reporting),
+            Err(err) => {
+                eprintln!("{}", err);
+                ExitCode::FAILURE
+            }
+        },
+        [first, ..] if echo || (reporting.is_enabled() && *first != "run") => return usage(),
+        _ => {}
+    }
+    match args.as_slice() {
+        ["--compat", "bc"] => compat_bc(),
+        ["--protocol", "simple-json"] => simple_json(),
+        ["--events"] => event_stream(),
+        ["tui"] => full_screen(),
+        ["selftest"] => selftest(),
+        ["replay-corpus", dir] => replay_corpus(dir),
+        ["minimize", expr] => minimize_expression(expr, None),
+        ["minimize", expr, "--predicate", predicate] => minimize_expression(expr, Some(predicate)),
+        ["explain", code] => explain(code),
+        ["report", path] => report(path, false),
+        ["report", path, "--json"] | ["report", "--json", path] => report(path, true),
+        ["grade", exercises, dir] => grade_submissions(exercises, dir, false),
+        ["grade", exercises, dir, "--json"] => grade_submissions(exercises, dir, true),
+        ["capabilities"] => {
+            println!("{}", features());
+            ExitCode::SUCCESS
+        }
+        ["capabilities", "--json"] => {
+            println!("{}", features().to_json());
+            ExitCode::SUCCESS
+        }
+        ["watch", path, rest @ ..] => match parse_run_options(rest) {
+            Some((params, notify)) if notify == Notify::default() => watch(path, params),
+            _ => usage(),
+        },
+        ["graph", path, rest @ ..] => {
+            let (dot, rest) = take_flag(rest, "--dot");
+            match parse_run_options(&rest) {
+                Some((params, notify)) if notify == Notify::default() => graph_script(path, params, dot),
+                _ => usage(),
+            }
+        }
+        ["check", path, rest @ ..] => {
+            let (lint, rest) = take_flag(rest, "--lint");
+            match parse_run_options(&rest) {
+                Some((params, notify)) if notify == Notify::default() => check(path, params, lint),
+                _ => usage(),
+            }
+        }
+        ["refactor", "renumber", path, "--from", old] => renumber_script(path, old),
+        ["fmt", rest @ ..] => fmt(rest),
+        ["md", path] => markdown(path, None),
+        ["md", path, "--output", output] => markdown(path, Some(output)),
+        ["run", path, rest @ ..] => {
+            let options = take_reporting(rest)
+                .and_then(|(reporting, rest)| Some((parse_run_options(&rest)?, reporting)));
+            match options {
+                Some(((params, notify), reporting)) => run(path, params, notify, reporting),
+                None => usage(),
+            }
         }
+        _ => usage(),
     }
 }
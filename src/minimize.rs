@@ -0,0 +1,146 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+
+use crate::Value::{BinaryOperation, Int, Name, UnaryOperation, Variable};
+use crate::{evaluate_value, ErrorCode, Value};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Predicate {
+    Error,
+    ErrorCode(ErrorCode),
+    Panic,
+}
+
+impl FromStr for Predicate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            None if s == "error" => Ok(Predicate::Error),
+            None if s == "panic" => Ok(Predicate::Panic),
+            Some(("error-code", code)) => ErrorCode::from_str(code)
+                .map(Predicate::ErrorCode)
+                .map_err(|_| format!("'{}' is not a known error code.", code)),
+            _ => Err(format!("Expected 'error', 'panic' or 'error-code=<code>' as a predicate, instead got '{}'.", s)),
+        }
+    }
+}
+
+impl Predicate {
+    // The predicate a failing expression satisfies most specifically.
+    pub fn of(value: &Value) -> Option<Self> {
+        match panic::catch_unwind(AssertUnwindSafe(|| evaluate_value(value, &[]))) {
+            Ok(Ok(_)) => None,
+            Ok(Err(err)) => Some(Predicate::ErrorCode(err.code)),
+            Err(_) => Some(Predicate::Panic),
+        }
+    }
+
+    pub fn holds(&self, value: &Value) -> bool {
+        match (self, Predicate::of(value)) {
+            (Predicate::Error, Some(Predicate::ErrorCode(_))) => true,
+            (Predicate::ErrorCode(code), Some(Predicate::ErrorCode(actual))) => *code == actual,
+            (Predicate::Panic, Some(Predicate::Panic)) => true,
+            _ => false,
+        }
+    }
+}
+
+fn weight(value: &Value) -> (usize, usize) {
+    fn magnitude(value: &Value) -> usize {
+        match value {
+            BinaryOperation { left, right, .. } => magnitude(left).saturating_add(magnitude(right)),
+            Int(int) => int.unsigned_abs(),
+            Name(_) | Variable(_) => 1,
+            UnaryOperation { arg, .. } => magnitude(arg),
+        }
+    }
+    (value.size(), magnitude(value))
+}
+
+// Every expression reachable from `value` by one reduction step: replacing
+// a subexpression by a small constant or by one of its own subexpressions,
+// or shrinking a leaf.
+fn reductions(value: &Value) -> Vec<Value> {
+    let mut candidates = Vec::new();
+    match value {
+        BinaryOperation { operator, left, right } => {
+            candidates.extend([Int(0), Int(1)]);
+            candidates.push((**left).clone());
+            candidates.push((**right).clone());
+            candidates.extend(reductions(left).into_iter().map(|left| BinaryOperation {
+                operator: *operator,
+                left: Box::new(left),
+                right: right.clone(),
+            }));
+            candidates.extend(reductions(right).into_iter().map(|right| BinaryOperation {
+                operator: *operator,
+                left: left.clone(),
+                right: Box::new(right),
+            }));
+        }
+        Int(int) => candidates.extend([0, 1, int / 2, int.signum()].map(Int)),
+        Name(_) | Variable(_) => candidates.extend([Int(0), Int(1)]),
+        UnaryOperation { operator, arg } => {
+            candidates.extend([Int(0), Int(1)]);
+            candidates.push((**arg).clone());
+            candidates.extend(reductions(arg).into_iter().map(|arg| UnaryOperation {
+                operator: *operator,
+                arg: Box::new(arg),
+            }));
+        }
+    }
+    candidates
+}
+
+// Greedily applies the reduction that keeps the predicate and makes the
+// expression lightest, until no reduction does. Each step strictly lowers
+// the weight, so this terminates.
+pub fn minimize(value: &Value, predicate: Predicate) -> Value {
+    let mut current = value.clone();
+    loop {
+        let next = reductions(&current).into_iter()
+            .filter(|candidate| weight(candidate) < weight(&current))
+            .filter(|candidate| predicate.holds(candidate))
+            .min_by_key(weight);
+        match next {
+            None => return current,
+            Some(next) => current = next,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::minimize::{minimize, Predicate};
+    use crate::{parse_line, ErrorCode, Value};
+
+    fn to_value(str: &str) -> Value {
+        parse_line(str.split_whitespace()).unwrap()
+    }
+
+    fn minimized(str: &str, predicate: &str) -> String {
+        minimize(&to_value(str), Predicate::from_str(predicate).unwrap()).to_string()
+    }
+
+    #[test]
+    fn predicates() {
+        assert_eq!(Predicate::from_str("error-code=E0102"), Ok(Predicate::ErrorCode(ErrorCode::DivisionByZero)));
+        assert_eq!(Predicate::from_str("error"), Ok(Predicate::Error));
+        assert!(Predicate::from_str("error-code=E9999").is_err());
+        assert!(Predicate::from_str("crash").is_err());
+        assert_eq!(Predicate::of(&to_value("+ 1 2")), None);
+        assert!(Predicate::Error.holds(&to_value("/ 1 0")));
+        assert!(!Predicate::ErrorCode(ErrorCode::NegativeFactorial).holds(&to_value("/ 1 0")));
+    }
+
+    #[test]
+    fn shrinks() {
+        assert_eq!(minimized("+ 100 * 3 / succ 7 - 4 4", "error-code=E0102"), "/ 0 0");
+        assert_eq!(minimized("* 2 fact neg abs 17", "error-code=E0103"), "fact neg 1");
+        assert_eq!(minimized("+ 1 * $3 2", "error-code=E0101"), "$3");
+        assert_eq!(minimized("+ 1 2", "error"), "+ 1 2");
+    }
+}
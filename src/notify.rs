@@ -0,0 +1,69 @@
+use std::process::Command;
+use std::time::Duration;
+
+use crate::{Error, ErrorCode};
+
+// Announces the completion of evaluations lasting at least `after`, by
+// running `command` through the shell or else by a desktop notification.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Notify {
+    pub after: Option<Duration>,
+    pub command: Option<String>,
+}
+
+impl Notify {
+    // Returns whether the completion was announced.
+    pub fn finished(&self, elapsed: Duration, summary: &str) -> Result<bool, Error> {
+        if self.after.is_none_or(|after| elapsed < after) {
+            return Ok(false);
+        }
+        let mut command = match &self.command {
+            Some(command) => {
+                let mut shell = Command::new("sh");
+                shell.args(["-c", command]);
+                shell
+            }
+            None => {
+                let mut notify = Command::new("notify-send");
+                notify.args(["cwh", summary]);
+                notify
+            }
+        };
+        let program = command.get_program().to_string_lossy().into_owned();
+        let status = command
+            .env("CWH_SUMMARY", summary)
+            .env("CWH_ELAPSED", format!("{:.3}", elapsed.as_secs_f64()))
+            .status()
+            .map_err(|err| Error::new(ErrorCode::Unavailable, format!("Cannot run {} to notify: {}.", program, err)))?;
+        if !status.success() {
+            return Err(Error::new(ErrorCode::Unavailable, format!("Notification by {} exited with {}.", program, status)));
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use crate::notify::Notify;
+    use crate::ErrorCode;
+
+    #[test]
+    fn hook() {
+        let path = std::env::temp_dir().join(format!("cwh-notify-{}", std::process::id()));
+        let mut notify = Notify {
+            after: Some(Duration::from_secs(2)),
+            command: Some(format!("echo \"$CWH_SUMMARY in $CWH_ELAPSED\" > {}", path.display())),
+        };
+        assert_eq!(notify.finished(Duration::from_secs(1), "Done"), Ok(false));
+        assert_eq!(notify.finished(Duration::from_secs(3), "Done"), Ok(true));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Done in 3.000\n");
+        fs::remove_file(&path).unwrap();
+
+        notify.command = Some(String::from("exit 1"));
+        assert_eq!(notify.finished(Duration::from_secs(3), "Done").unwrap_err().code, ErrorCode::Unavailable);
+        assert_eq!(Notify::default().finished(Duration::MAX, "Done"), Ok(false));
+    }
+}
@@ -0,0 +1,98 @@
+use std::env;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::{Error, ErrorCode};
+
+pub const DEFAULT_SIZE: (usize, usize) = (24, 80);
+
+// Rows and columns of the terminal, from $LINES and $COLUMNS when the shell
+// exports them, else from stty.
+pub fn terminal_size() -> (usize, usize) {
+    let var = |name| env::var(name).ok().and_then(|value| value.parse().ok());
+    if let (Some(rows), Some(columns)) = (var("LINES"), var("COLUMNS")) {
+        return (rows, columns);
+    }
+    Command::new("stty")
+        .arg("size")
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .and_then(|output| parse_size(&String::from_utf8_lossy(&output.stdout)))
+        .unwrap_or(DEFAULT_SIZE)
+}
+
+fn parse_size(text: &str) -> Option<(usize, usize)> {
+    let (rows, columns) = text.trim().split_once(' ')?;
+    Some((rows.parse().ok()?, columns.parse().ok()?))
+}
+
+// Breaks lines wider than the terminal, marking every broken line with a
+// trailing backslash.
+pub fn wrap(line: &str, width: usize) -> Vec<String> {
+    let chars: Vec<_> = line.chars().collect();
+    if chars.len() <= width || width < 2 {
+        return vec![String::from(line)];
+    }
+    let chunks = chars.chunks(width - 1);
+    let last = chunks.len() - 1;
+    chunks.enumerate()
+        .map(|(idx, chunk)| {
+            let line: String = chunk.iter().collect();
+            if idx < last { line + "\\" } else { line }
+        })
+        .collect()
+}
+
+pub fn pager() -> String {
+    env::var("PAGER").unwrap_or_else(|_| String::from("less"))
+}
+
+pub fn page(lines: &[String]) -> Result<(), Error> {
+    page_with(&pager(), lines)
+}
+
+pub fn page_with(pager: &str, lines: &[String]) -> Result<(), Error> {
+    let unavailable = |err| Error::new(ErrorCode::Unavailable, format!("Cannot run pager '{}': {}.", pager, err));
+    let mut child = Command::new("sh")
+        .args(["-c", pager])
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(unavailable)?;
+    let mut stdin = child.stdin.take().unwrap();
+    // The pager may be quit before reading everything, which is fine.
+    let _ = stdin.write_all(format!("{}\n", lines.join("\n")).as_bytes());
+    drop(stdin);
+    let status = child.wait().map_err(unavailable)?;
+    if !status.success() {
+        return Err(Error::new(ErrorCode::Unavailable, format!("Pager '{}' exited with {}.", pager, status)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pager::{page_with, parse_size, wrap};
+    use crate::ErrorCode;
+
+    #[test]
+    fn size() {
+        assert_eq!(parse_size("50 132\n"), Some((50, 132)));
+        assert_eq!(parse_size(""), None);
+    }
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(wrap("1234567", 4), ["123\\", "456\\", "7"]);
+        assert_eq!(wrap("123456", 4), ["123\\", "456"]);
+        assert_eq!(wrap("1234", 4), ["1234"]);
+    }
+
+    #[test]
+    fn pager() {
+        let lines = vec![String::from("1"), String::from("2")];
+        assert_eq!(page_with("grep -q 2", &lines), Ok(()));
+        assert_eq!(page_with("grep -q 3", &lines).unwrap_err().code, ErrorCode::Unavailable);
+    }
+}
@@ -0,0 +1,59 @@
+use crate::json::{self, Json};
+use crate::repl::Repl;
+use crate::{Error, ErrorCode};
+
+// Answers a request of the line-based JSON protocol, one JSON object per
+// line either way. The input of the request is handled like a line typed in
+// the REPL, so commands work too, and the response lists the printed lines,
+// the results pushed to history and the error if any.
+pub fn respond(repl: &mut Repl, request: &str) -> String {
+    let request = json::parse(request);
+    let id = request.as_ref().and_then(|request| request.get("id")).cloned().unwrap_or(Json::Null);
+    let (lines, results, error) = match request.as_ref().and_then(|request| request.get("input")) {
+        Some(Json::String(input)) => {
+            let start = repl.session.len();
+            let response = repl.handle(input);
+            let results = repl.session.history().get(start..).unwrap_or_default().to_vec();
+            (response.lines, results, response.error)
+        }
+        _ => {
+            let message = "Expected a JSON object with an 'input' string.";
+            (Vec::new(), Vec::new(), Some(Error::new(ErrorCode::InvalidRequest, message)))
+        }
+    };
+    let error = match error {
+        None => String::from("null"),
+        Some(err) => json::object([("code", json::string(err.code.as_str())), ("message", json::string(&err.message))]),
+    };
+    json::object([
+        ("id", id.to_string()),
+        ("lines", json::array(lines.iter().map(|line| json::string(line)))),
+        ("results", json::array(results)),
+        ("error", error),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::respond;
+    use crate::repl::Repl;
+    use crate::TrailingPolicy;
+
+    #[test]
+    fn requests() {
+        let mut repl = Repl::new(TrailingPolicy::Reject);
+        assert_eq!(
+            respond(&mut repl, r#"{"id": 1, "input": "+ 3 2"}"#),
+            r#"{"id":1,"lines":["5"],"results":[5],"error":null}"#
+        );
+        assert_eq!(
+            respond(&mut repl, r#"{"id": "b", "input": "/ $0 0"}"#),
+            r#"{"id":"b","lines":[],"results":[],"error":{"code":"E0102","message":"Division by zero."}}"#
+        );
+        assert_eq!(
+            respond(&mut repl, r#"{"input": ":history"}"#),
+            r#"{"id":null,"lines":["$0: + 3 2 => 5"],"results":[],"error":null}"#
+        );
+        assert!(respond(&mut repl, "+ 3 2").ends_with(r#""error":{"code":"E0306","message":"Expected a JSON object with an 'input' string."}}"#));
+    }
+}
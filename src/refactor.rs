@@ -0,0 +1,140 @@
+use crate::script::strip_comment;
+use crate::{Error, ErrorCode};
+
+// Index of the line producing each entry of history, counting every
+// expression line once.
+fn producers(text: &str) -> Result<Vec<usize>, Error> {
+    let mut producers = Vec::new();
+    let mut depth = 0usize;
+    let mut continued = false;
+    for (idx, line) in text.lines().enumerate() {
+        let code = strip_comment(line).trim();
+        // Continuation lines are part of the line they continue.
+        if std::mem::replace(&mut continued, code.ends_with('\\')) {
+            continue;
+        }
+        let directive = code.strip_prefix('@').map(|rest| rest.split_whitespace().next().unwrap_or(""));
+        match directive {
+            _ if code.is_empty() => {}
+            Some("if" | "for") => depth += 1,
+            Some("end") => depth = depth.saturating_sub(1),
+            Some(_) => {}
+            None if code.starts_with("include") => {
+                return Err(Error::new(ErrorCode::CannotRefactor, format!("Line {} includes another file.", idx + 1)));
+            }
+            None if depth > 0 => {
+                return Err(Error::new(
+                    ErrorCode::CannotRefactor,
+                    format!("Line {} is inside a block, its entries cannot be numbered.", idx + 1),
+                ));
+            }
+            None => producers.push(idx),
+        }
+    }
+    Ok(producers)
+}
+
+// Pairs the indices of the lines both texts have in common, following their
+// longest common subsequence.
+fn matching(old: &[&str], new: &[&str]) -> Vec<(usize, usize)> {
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = match old[i] == new[j] {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+    let (mut i, mut j, mut pairs) = (0, 0, Vec::new());
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            pairs.push((i, j));
+            (i, j) = (i + 1, j + 1);
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Renumbered {
+    pub text: String,
+    pub changed: usize,
+    // Line numbers and references to entries whose line was deleted.
+    pub dangling: Vec<(usize, usize)>,
+}
+
+// Rewrites the references of the lines `new` kept from `old` so that they
+// still refer to the same lines, after lines producing entries were inserted
+// or deleted. Inserted lines are expected to be numbered already.
+pub fn renumber(old: &str, new: &str) -> Result<Renumbered, Error> {
+    let (old_producers, new_producers) = (producers(old)?, producers(new)?);
+    let (old_lines, new_lines): (Vec<_>, Vec<_>) = (old.lines().collect(), new.lines().collect());
+    let pairs = matching(&old_lines, &new_lines);
+    let moved = |index: usize| -> Option<Option<usize>> {
+        let line = *old_producers.get(index)?;
+        let new_line = pairs.iter().find(|(old, _)| *old == line).map(|(_, new)| *new);
+        Some(new_line.and_then(|new_line| new_producers.iter().position(|producer| *producer == new_line)))
+    };
+
+    let mut renumbered = Renumbered { text: String::new(), changed: 0, dangling: Vec::new() };
+    for (idx, line) in new_lines.iter().enumerate() {
+        let code = strip_comment(line);
+        let line = match pairs.iter().any(|(_, new)| *new == idx) {
+            false => String::from(*line),
+            true => {
+                let mut parts = code.split('$');
+                let mut rewritten = String::from(parts.next().unwrap_or(""));
+                for part in parts {
+                    let digits = part.find(|char: char| !char.is_ascii_digit()).unwrap_or(part.len());
+                    let index = part[..digits].parse().ok();
+                    rewritten.push('$');
+                    match index.and_then(moved) {
+                        Some(Some(new_index)) => {
+                            renumbered.changed += usize::from(Some(new_index) != index);
+                            rewritten.push_str(&new_index.to_string());
+                            rewritten.push_str(&part[digits..]);
+                        }
+                        Some(None) => {
+                            renumbered.dangling.push((idx + 1, index.unwrap()));
+                            rewritten.push_str(part);
+                        }
+                        None => rewritten.push_str(part),
+                    }
+                }
+                rewritten + &line[code.len()..]
+            }
+        };
+        renumbered.text.push_str(&line);
+        renumbered.text.push('\n');
+    }
+    Ok(renumbered)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::refactor::{renumber, Renumbered};
+    use crate::ErrorCode;
+
+    #[test]
+    fn renumber_inserted_and_deleted() {
+        let old = "3 ; base\n4\n@print \"{$1}\"\n+ $0 \\\n  $1 ; sum\n* $2 $7\n";
+        let new = "1\n3 ; base\n+ $0 $1\n@print \"{$1}\"\n+ $0 \\\n  $1 ; sum\n* $2 $7\n";
+        assert_eq!(renumber(old, new), Ok(Renumbered {
+            text: String::from("1\n3 ; base\n+ $0 $1\n@print \"{$1}\"\n+ $1 \\\n  $1 ; sum\n* $3 $7\n"),
+            changed: 2,
+            dangling: vec![(4, 1), (6, 1)],
+        }));
+    }
+
+    #[test]
+    fn blocks() {
+        let err = renumber("1\n", "@for i in 1..2\n+ 1 i\n@end\n").unwrap_err();
+        assert_eq!(err.code, ErrorCode::CannotRefactor);
+        assert_eq!(renumber("include \"a.cwh\"\n", "").unwrap_err().code, ErrorCode::CannotRefactor);
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt::Write;
+
+use crate::BinaryOperator::{Division, Minus, Multiplication, Plus};
+use crate::UnaryOperator::{Abs, Factorial, Negative, Predecessor, Signum, Successor};
+use crate::{process_line, BinaryOperator, ErrorCode, UnaryOperator};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Operator {
+    Binary(BinaryOperator),
+    Unary(UnaryOperator),
+}
+
+#[derive(Debug)]
+pub struct OperatorDoc {
+    pub operator: Operator,
+    // The first symbol is the canonical one, the others are aliases.
+    pub symbols: &'static [&'static str],
+    pub summary: &'static str,
+    pub examples: &'static [(&'static str, Result<isize, ErrorCode>)],
+}
+
+impl OperatorDoc {
+    pub fn symbol(&self) -> &'static str {
+        self.symbols[0]
+    }
+}
+
+pub const OPERATORS: [OperatorDoc; 10] = [
+    OperatorDoc {
+        operator: Operator::Binary(Division),
+        symbols: &["/"],
+        summary: "Integer division truncating toward zero.",
+        examples: &[("/ 7 2", Ok(3)), ("/ -7 2", Ok(-3)), ("/ 1 0", Err(ErrorCode::DivisionByZero))],
+    },
+    OperatorDoc {
+        operator: Operator::Binary(Minus),
+        symbols: &["-"],
+        summary: "Subtracts the second argument from the first.",
+        examples: &[("- 3 5", Ok(-2))],
+    },
+    OperatorDoc {
+        operator: Operator::Binary(Multiplication),
+        symbols: &["*"],
+        summary: "Multiplies both arguments.",
+        examples: &[("* 6 9", Ok(54))],
+    },
+    OperatorDoc {
+        operator: Operator::Binary(Plus),
+        symbols: &["+"],
+        summary: "Adds both arguments.",
+        examples: &[("+ 3 2", Ok(5))],
+    },
+    OperatorDoc {
+        operator: Operator::Unary(Abs),
+        symbols: &["abs"],
+        summary: "Absolute value.",
+        examples: &[("abs -5", Ok(5)), ("abs 5", Ok(5))],
+    },
+    OperatorDoc {
+        operator: Operator::Unary(Factorial),
+        symbols: &["fact", "!"],
+        summary: "Factorial of a non-negative number.",
+        examples: &[("fact 7", Ok(5040)), ("! 3", Ok(6)), ("fact 0", Ok(1)), ("fact -3", Err(ErrorCode::NegativeFactorial)), ("fact 21", Err(ErrorCode::Overflow))],
+    },
+    OperatorDoc {
+        operator: Operator::Unary(Negative),
+        symbols: &["neg"],
+        summary: "Negates the argument.",
+        examples: &[("neg 5", Ok(-5)), ("neg -5", Ok(5))],
+    },
+    OperatorDoc {
+        operator: Operator::Unary(Predecessor),
+        symbols: &["pred"],
+        summary: "Subtracts one.",
+        examples: &[("pred 7", Ok(6))],
+    },
+    OperatorDoc {
+        operator: Operator::Unary(Signum),
+        symbols: &["sgn"],
+        summary: "Sign of the argument: -1, 0 or 1.",
+        examples: &[("sgn -5", Ok(-1)), ("sgn 0", Ok(0)), ("sgn 42", Ok(1))],
+    },
+    OperatorDoc {
+        operator: Operator::Unary(Successor),
+        symbols: &["succ"],
+        summary: "Adds one.",
+        examples: &[("succ 6", Ok(7))],
+    },
+];
+
+pub fn lookup(symbol: &str) -> Option<&'static OperatorDoc> {
+    OPERATORS.iter().find(|doc| doc.symbols.contains(&symbol))
+}
+
+pub fn doc(operator: Operator) -> &'static OperatorDoc {
+    OPERATORS.iter()
+        .find(|doc| doc.operator == operator)
+        .expect("every operator is registered")
+}
+
+fn show(result: &Result<isize, ErrorCode>) -> String {
+    match result {
+        Ok(int) => int.to_string(),
+        Err(code) => format!("Error[{}]", code),
+    }
+}
+
+pub fn help() -> String {
+    let usages: Vec<_> = OPERATORS.iter()
+        .map(|doc| match doc.operator {
+            Operator::Binary(_) => format!("{} <a> <b>", doc.symbols.join(" | ")),
+            Operator::Unary(_) => format!("{} <a>", doc.symbols.join(" | ")),
+        })
+        .collect();
+    let width = usages.iter().map(String::len).max().unwrap_or_default();
+    let mut out = String::from("Operators, written before their arguments:");
+    for (doc, usage) in OPERATORS.iter().zip(usages) {
+        write!(out, "\n  {:<width$} {}", usage, doc.summary).unwrap();
+        for (input, result) in doc.examples {
+            write!(out, "\n      {} => {}", input, show(result)).unwrap();
+        }
+    }
+    out.push_str("\nPrevious results are referred to by their index in history, like $0.");
+    out
+}
+
+// Evaluates every documented example, returning a description of each one
+// whose result differs from the documented one.
+pub fn selftest() -> Vec<String> {
+    OPERATORS.iter()
+        .flat_map(|doc| doc.examples.iter())
+        .filter_map(|(input, expected)| {
+            let actual = process_line(String::from(*input), &[]).map_err(|err| err.code);
+            (actual != *expected).then(|| {
+                format!("'{}' documented as {}, evaluated to {}.", input, show(expected), show(&actual))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::registry::{doc, lookup, selftest, Operator, OPERATORS};
+    use crate::BinaryOperator::{Division, Minus, Multiplication, Plus};
+    use crate::UnaryOperator::{Abs, Factorial, Negative, Predecessor, Signum, Successor};
+
+    #[test]
+    fn examples_hold() {
+        assert_eq!(selftest(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn symbols_are_unique() {
+        for doc in &OPERATORS {
+            for symbol in doc.symbols {
+                assert_eq!(lookup(symbol).unwrap().operator, doc.operator);
+            }
+        }
+    }
+
+    #[test]
+    fn every_operator_is_documented() {
+        let operators = [
+            Operator::Binary(Division),
+            Operator::Binary(Minus),
+            Operator::Binary(Multiplication),
+            Operator::Binary(Plus),
+            Operator::Unary(Abs),
+            Operator::Unary(Factorial),
+            Operator::Unary(Negative),
+            Operator::Unary(Predecessor),
+            Operator::Unary(Signum),
+            Operator::Unary(Successor),
+        ];
+        assert_eq!(operators.len(), OPERATORS.len());
+        for operator in operators {
+            assert!(!doc(operator).examples.is_empty());
+        }
+    }
+}
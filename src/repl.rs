@@ -0,0 +1,1114 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::Instant;
+
+use crate::{editor, registry, trace};
+use crate::anonymize::anonymize;
+use crate::config::{apply_filters, Config, Filter};
+use crate::grid::{Cell, Grid};
+use crate::script::{parse_string, strip_comment};
+use crate::session::Session;
+use crate::settings::{Settings, SETTINGS};
+use crate::store::Store;
+use crate::teach::Rules;
+use crate::template::interpolate;
+use crate::trace::Trace;
+use crate::{evaluate_in, evaluate_value, parse_expressions, parse_line, Context, Error, ErrorCode, TrailingPolicy};
+
+pub const COMMANDS: [(&str, &str); 29] = [
+    (":help", "Lists operators and commands."),
+    (":set [<name> <value>]", "Changes a setting, or lists them all."),
+    (":history [<tag>] [--notes]", "Lists the inputs and results of the session, or of the entries tagged."),
+    (":save <file> [<tag>]", "Saves the session, or the entries tagged and those they refer to, to a file."),
+    (":load <file>", "Replaces the session by one saved to a file."),
+    (":export-tests <file> [<tag>]", "Writes a script asserting the results of the session, or of the entries tagged."),
+    (":tag <n> <tag>...", "Tags entry $n."),
+    (":untag <n> <tag>...", "Removes tags from entry $n."),
+    (":note <n> [\"<text>\"]", "Sets the note of entry $n, or removes it."),
+    (":trace <expr>", "Evaluates an expression, showing the result and time of every node."),
+    (":anonymize <expr>", "Renames the names of an expression and strips comments, for sharing it."),
+    (":store <expr>", "Stores an expression, printing the #hash referring to it."),
+    (":push [<expr>]", "Pushes the expression, or the last result, on the stack."),
+    (":pop", "Moves the top of the stack to history."),
+    (":swap", "Swaps both values on top of the stack."),
+    (":dup", "Duplicates the top of the stack."),
+    (":edit-buffer [$<n> | <expr>]", "Edits the expression, or the input of $n, in the editor and evaluates it."),
+    (":copy", "Copies the last result to the clipboard (clipboard feature)."),
+    (":paste-eval", "Evaluates the clipboard contents (clipboard feature)."),
+    (":branch <n> <name>", "Copies the session up to $n into a new session and switches to it."),
+    (":switch <name>", "Switches to another session."),
+    (":sessions", "Lists the sessions, marking the current one."),
+    (":diff <a> <b>", "Shows the entries where two sessions diverge."),
+    (":whatif <n> over <a>..<b> [step <s>]", "Tabulates the last result while $n sweeps a range."),
+    (":goalseek [<k>] target <value> by <n> in <a>..<b>", "Finds the $n making $k, by default the last entry, equal the value."),
+    (":trend [<n>]", "Tells whether the last n results, by default 5, increase or decrease."),
+    (":grid [set <cell> [<expr>] | export <file> | import <file>]", "Shows or edits the grid of cells, or saves it as a script."),
+    (":macro <record | stop | list | play <n> [<repeat>]>", "Records inputs as a macro, or replays macro n."),
+    (":teach <file | off>", "Explains operators as they are used, following a rules file."),
+];
+
+// Tokens standing for expressions over the last two results, expanded
+// before the line is parsed.
+pub const SHORTCUTS: [(&str, &str); 2] = [
+    ("delta", "Difference between the last two results."),
+    ("ratio", "Last result in percent of the one before."),
+];
+
+// A result pushed to history by the line, displayed by `lines[line]`.
+#[derive(Debug, PartialEq)]
+pub struct Pushed {
+    pub index: usize,
+    pub value: isize,
+    pub line: usize,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Response {
+    pub lines: Vec<String>,
+    // Results among the lines, which also hold teaching explanations and the
+    // output of the on-result hook.
+    pub results: Vec<Pushed>,
+    pub error: Option<Error>,
+}
+
+impl Response {
+    fn from_result(result: Result<Vec<String>, Error>) -> Self {
+        match result {
+            Ok(lines) => Response { lines, ..Response::default() },
+            Err(err) => Response { error: Some(err), ..Response::default() },
+        }
+    }
+}
+
+pub const MAIN_SESSION: &str = "main";
+pub const MAX_SWEEP: usize = 1000;
+pub const MAX_SEARCH: usize = 100_000;
+pub const MAX_REPEAT: usize = 1000;
+pub const TREND_LENGTH: usize = 5;
+
+#[derive(Debug)]
+pub struct Repl {
+    pub session: Session,
+    pub name: String,
+    // Every session but the current one, by name.
+    pub others: BTreeMap<String, Session>,
+    // Shared by every session, so that stored expressions survive `:load`.
+    pub store: Store,
+    // RPN workspace, independent of history apart from `:push` and `:pop`.
+    pub stack: Vec<isize>,
+    // Cells referring to each other by name, shared by every session.
+    pub grid: Grid,
+    // Recorded inputs, numbered from zero, and the inputs of the macro being
+    // recorded.
+    pub macros: Vec<Vec<String>>,
+    pub recording: Option<Vec<String>>,
+    // Explanations printed before the results, for teaching.
+    pub rules: Rules,
+    pub settings: Settings,
+    pub filters: Vec<Filter>,
+    pub policy: TrailingPolicy,
+}
+
+impl Repl {
+    pub fn new(policy: TrailingPolicy) -> Self {
+        Repl {
+            session: Session::new(),
+            name: String::from(MAIN_SESSION),
+            others: BTreeMap::new(),
+            store: Store::new(),
+            stack: Vec::new(),
+            grid: Grid::new(),
+            macros: Vec::new(),
+            recording: None,
+            rules: Rules::default(),
+            settings: Settings::default(),
+            filters: Vec::new(),
+            policy,
+        }
+    }
+
+    pub fn with_config(policy: TrailingPolicy, config: Config) -> Self {
+        Repl { settings: config.settings, filters: config.filters, ..Repl::new(policy) }
+    }
+
+    fn named(&self, name: &str) -> Result<&Session, Error> {
+        match name == self.name {
+            true => Ok(&self.session),
+            false => self.others.get(name)
+                .ok_or_else(|| Error::new(ErrorCode::InvalidSessionName, format!("No session named '{}'.", name))),
+        }
+    }
+
+    fn switch(&mut self, name: &str, session: Session) {
+        let previous = std::mem::replace(&mut self.session, session);
+        self.others.insert(std::mem::replace(&mut self.name, String::from(name)), previous);
+    }
+
+    // Inputs handled without error are recorded while a macro is.
+    pub fn handle(&mut self, line: &str) -> Response {
+        let response = self.respond(line);
+        let command = line.trim_start().strip_prefix(':').and_then(|command| command.split_whitespace().next());
+        if let (Some(recording), None) = (&mut self.recording, &response.error) {
+            if command != Some("macro") {
+                recording.push(String::from(line));
+            }
+        }
+        response
+    }
+
+    fn respond(&mut self, line: &str) -> Response {
+        if let Some(command) = line.trim_start().strip_prefix(':') {
+            let command = command.trim_start();
+            let name = command.split_whitespace().next().unwrap_or_default();
+            let rest = &command[name.len()..];
+            return Response::from_result(self.command(name, rest.split_whitespace().collect(), rest));
+        }
+        let (start, started) = (self.session.len(), Instant::now());
+        let mut lines = Vec::new();
+        let result = self.evaluate(line, &mut lines);
+        let results = &self.session.history()[start..];
+        let displayed: Vec<_> = results.iter().map(|int| self.settings.display(*int)).collect();
+        let summary = match &result {
+            Ok(()) => format!("Evaluated to {}.", displayed.join(" ")),
+            Err(err) => err.to_string(),
+        };
+        let pushed = results.iter().enumerate()
+            .map(|(idx, int)| Pushed { index: start + idx, value: *int, line: lines.len() + idx })
+            .collect();
+        lines.extend(displayed);
+        let notified = self.settings.notify.finished(started.elapsed(), &summary);
+        let hooked = results.iter().enumerate().try_for_each(|(idx, int)| {
+            lines.extend(self.on_result(start + idx, *int)?);
+            Ok(())
+        });
+        Response { lines, results: pushed, error: result.err().or(notified.err()).or(hooked.err()) }
+    }
+
+    // Evaluates the expression as `:trace` does, without pushing it to history.
+    pub fn trace(&self, line: &str) -> Result<Trace, Error> {
+        let line = self.store.expand(&apply_filters(&self.filters, line))?;
+        let value = parse_line(line.split_whitespace())?;
+        Ok(trace::trace(&value, &Context::new(self.session.history(), &HashMap::new())))
+    }
+
+    // Runs the on-result template for the result of entry `idx`, with the
+    // result bound to the name `result`.
+    fn on_result(&self, idx: usize, int: isize) -> Result<Vec<String>, Error> {
+        let Some(template) = &self.settings.on_result else {
+            return Ok(Vec::new());
+        };
+        let names = HashMap::from([(String::from("result"), int)]);
+        let ctx = Context::new(&self.session.history()[..=idx], &names);
+        let text = interpolate(template, |expr| evaluate_in(&parse_line(expr.split_whitespace())?, &ctx))?;
+        let Some(command) = text.strip_prefix('!') else {
+            return Ok(vec![text]);
+        };
+        let output = Command::new("sh").args(["-c", command]).output()
+            .map_err(|err| Error::new(ErrorCode::Unavailable, format!("Cannot run on-result command: {}.", err)))?;
+        if !output.status.success() {
+            return Err(Error::new(
+                ErrorCode::Unavailable,
+                format!("On-result command '{}' exited with {}.", command, output.status),
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).lines().map(String::from).collect())
+    }
+
+    // Adds the explanations of the teaching rules to `explained`.
+    fn evaluate(&mut self, line: &str, explained: &mut Vec<String>) -> Result<(), Error> {
+        let line = expand_shortcuts(&self.store.expand(&apply_filters(&self.filters, line))?, self.session.len())?;
+        for value in parse_expressions(line.split_whitespace(), self.policy)? {
+            let (result, explanations) = self.rules.evaluate(&value, &Context::new(self.session.history(), &HashMap::new()));
+            explained.extend(explanations);
+            self.session.push(value.to_string(), result?);
+        }
+        Ok(())
+    }
+
+    // Evaluates text from outside the prompt, which may span several lines.
+    fn evaluate_lines(&mut self, text: &str) -> Result<Vec<String>, Error> {
+        let start = self.session.len();
+        let mut lines = Vec::new();
+        self.evaluate(text, &mut lines)?;
+        lines.extend(self.session.history()[start..].iter().map(|int| self.settings.display(*int)));
+        Ok(lines)
+    }
+
+    // `rest` is the text of the arguments as typed, for those which are free
+    // text.
+    fn command(&mut self, name: &str, args: Vec<&str>, rest: &str) -> Result<Vec<String>, Error> {
+        match (name, args.as_slice()) {
+            ("help", []) => Ok(help()),
+            ("history", rest) if rest.iter().filter(|arg| **arg != "--notes").count() <= 1 => {
+                let notes = rest.contains(&"--notes");
+                let rows: Vec<_> = self.session.entries().iter()
+                    .zip(self.session.history())
+                    .enumerate()
+                    .filter(|(_, (entry, _))| rest.iter().all(|tag| *tag == "--notes" || entry.tags.contains(*tag)))
+                    .map(|(idx, (entry, int))| {
+                        let tags = entry.tags.iter().map(|tag| format!("#{}", tag)).collect::<Vec<_>>().join(" ");
+                        let mut row = vec![format!("${}:", idx), entry.input.clone(), String::from("=>"), self.settings.display(*int), tags];
+                        if notes {
+                            row.push(entry.note.as_ref().map(|note| format!("; {}", note)).unwrap_or_default());
+                        }
+                        row
+                    })
+                    .collect();
+                Ok(table(&rows, " ", &[1]))
+            }
+            ("note", [index, ..]) => {
+                let idx = self.index(index)?;
+                let text = rest.trim_start()[index.len()..].trim();
+                let note = match text.is_empty() {
+                    true => None,
+                    false => Some(parse_string(text).map(String::from).ok_or_else(|| usage(name))?),
+                };
+                let entry = self.session.entry_mut(idx).unwrap();
+                entry.note = note.filter(|note| !note.trim().is_empty());
+                Ok(vec![match &entry.note {
+                    Some(note) => format!("${}: {}", idx, note),
+                    None => format!("Removed the note of ${}.", idx),
+                }])
+            }
+            ("tag" | "untag", [index, tags @ ..]) if !tags.is_empty() => {
+                let idx = self.index(index)?;
+                if let Some(tag) = tags.iter().find(|tag| !tag.chars().all(|char| char.is_alphanumeric() || "-_".contains(char))) {
+                    return Err(invalid(format!("Tags are made of letters, digits, '-' and '_', instead found '{}'.", tag)));
+                }
+                let entry = &mut self.session.entry_mut(idx).unwrap().tags;
+                for tag in tags {
+                    match name {
+                        "tag" => entry.insert(String::from(*tag)),
+                        _ => entry.remove(*tag),
+                    };
+                }
+                let tags = entry.iter().map(|tag| format!("#{}", tag)).collect::<Vec<_>>();
+                Ok(vec![format!("${}: {}", idx, match tags.is_empty() {
+                    true => String::from("(no tags)"),
+                    false => tags.join(" "),
+                })])
+            }
+            ("set", []) => Ok(self.settings.describe()),
+            ("set", [name, value @ ..]) if !value.is_empty() => {
+                self.settings.set(name, &value.join(" "))?;
+                Ok(Vec::new())
+            }
+            ("branch", [index, name]) => {
+                let index = index.strip_prefix('$').unwrap_or(index);
+                let len = match index.parse::<usize>() {
+                    Ok(index) if index < self.session.len() => index + 1,
+                    _ => return Err(Error::new(
+                        ErrorCode::InvalidVariableIndex,
+                        format!("Cannot branch at '{}', the session has {} entries.", index, self.session.len()),
+                    )),
+                };
+                if *name == self.name || self.others.contains_key(*name) {
+                    return Err(Error::new(ErrorCode::InvalidSessionName, format!("Session '{}' already exists.", name)));
+                }
+                let session = self.session.prefix(len);
+                self.switch(name, session);
+                Ok(vec![format!("Switched to new session '{}' with {} entries.", name, len)])
+            }
+            ("switch", [name]) => {
+                self.named(name)?;
+                if *name == self.name {
+                    return Ok(vec![format!("Already in session '{}'.", name)]);
+                }
+                let session = self.others.remove(*name).unwrap_or_default();
+                self.switch(name, session);
+                Ok(vec![format!("Switched to session '{}'.", name)])
+            }
+            ("sessions", []) => {
+                let mut sessions: Vec<_> = self.others.iter().map(|(name, session)| (name, session, " ")).collect();
+                sessions.push((&self.name, &self.session, "*"));
+                sessions.sort_by_key(|(name, _, _)| *name);
+                Ok(sessions.iter()
+                    .map(|(name, session, mark)| format!("{} {} ({} entries)", mark, name, session.len()))
+                    .collect())
+            }
+            ("diff", [a, b]) => {
+                let (left, right) = (self.named(a)?, self.named(b)?);
+                let Some(start) = left.divergence(right) else {
+                    return Ok(vec![format!("Sessions '{}' and '{}' are identical.", a, b)]);
+                };
+                let show = |session: &Session, idx| match session.entry(idx) {
+                    Some((input, result)) => format!("{} => {}", input, result),
+                    None => String::from("(none)"),
+                };
+                let mut lines = vec![format!("Sessions '{}' and '{}' diverge from ${}:", a, b, start)];
+                for idx in start..left.len().max(right.len()) {
+                    lines.push(format!("  ${}: {} | {}", idx, show(left, idx), show(right, idx)));
+                }
+                Ok(lines)
+            }
+            ("whatif", [index, "over", range]) => self.what_if(index, range, "1"),
+            ("whatif", [index, "over", range, "step", step]) => self.what_if(index, range, step),
+            ("goalseek", ["target", goal, "by", index, "in", range]) => self.goal_seek(None, goal, index, range),
+            ("goalseek", [target, "target", goal, "by", index, "in", range]) => {
+                self.goal_seek(Some(target), goal, index, range)
+            }
+            ("trend", rest @ ([] | [_])) => {
+                let count = match rest {
+                    [count] => count.parse().ok().filter(|count| *count >= 2)
+                        .ok_or_else(|| invalid(format!("Expected at least 2 results, instead got '{}'.", count)))?,
+                    _ => TREND_LENGTH,
+                };
+                Ok(vec![self.trend(count)?])
+            }
+            ("push", []) => {
+                let Some(last) = self.session.history().last() else {
+                    return Err(Error::new(ErrorCode::InvalidVariableIndex, "History is empty, nothing to push."));
+                };
+                self.stack.push(*last);
+                Ok(self.show_stack())
+            }
+            ("push", [_, ..]) => {
+                let value = parse_line(self.store.expand(&args.join(" "))?.split_whitespace())?;
+                self.stack.push(evaluate_value(&value, self.session.history())?);
+                Ok(self.show_stack())
+            }
+            ("pop", []) => {
+                let top = self.pop(1)?[0];
+                self.session.push(top.to_string(), top);
+                Ok(vec![self.settings.display(top)])
+            }
+            ("swap", []) => {
+                let values = self.pop(2)?;
+                self.stack.extend([values[1], values[0]]);
+                Ok(self.show_stack())
+            }
+            ("dup", []) => {
+                let top = self.pop(1)?[0];
+                self.stack.extend([top, top]);
+                Ok(self.show_stack())
+            }
+            ("edit-buffer", rest) => {
+                let initial = match rest {
+                    [index] if index.starts_with('$') => &self.session.entries()[self.index(index)?].input,
+                    rest => &rest.join(" "),
+                };
+                let text = match &self.settings.editor {
+                    Some(command) => editor::edit_with(command, initial)?,
+                    None => editor::edit(initial)?,
+                };
+                self.evaluate_lines(&text)
+            }
+            #[cfg(feature = "clipboard")]
+            ("copy", []) => {
+                let Some(last) = self.session.history().last() else {
+                    return Err(Error::new(ErrorCode::InvalidVariableIndex, "History is empty, nothing to copy."));
+                };
+                crate::clipboard::copy(&last.to_string())?;
+                Ok(vec![format!("Copied {}.", last)])
+            }
+            #[cfg(feature = "clipboard")]
+            ("paste-eval", []) => {
+                let text = crate::clipboard::paste()?;
+                self.evaluate_lines(&text)
+            }
+            #[cfg(not(feature = "clipboard"))]
+            ("copy" | "paste-eval", []) => {
+                Err(Error::new(ErrorCode::Unavailable, "cwh was built without the clipboard feature."))
+            }
+            ("trace", [_, ..]) => Ok(trace::render(&self.trace(&args.join(" "))?)),
+            ("anonymize", [_, ..]) => {
+                let line = args.join(" ");
+                let value = parse_line(strip_comment(&line).split_whitespace())?;
+                Ok(vec![anonymize(&value).to_string()])
+            }
+            ("store", [_, ..]) => Ok(vec![self.store.store_line(&args.join(" "))?]),
+            ("save", [path]) => {
+                self.session.save(Path::new(path))?;
+                Ok(vec![format!("Saved {} entries to '{}'.", self.session.len(), path)])
+            }
+            ("save", [path, tag]) => {
+                let (tagged, selected) = self.tagged(tag)?;
+                selected.save(Path::new(path))?;
+                Ok(vec![format!(
+                    "Saved {} entries tagged '{}' and {} they refer to to '{}'.",
+                    tagged, tag, selected.len() - tagged, path,
+                )])
+            }
+            ("export-tests", [path, tag @ ..]) if tag.len() <= 1 => {
+                let session = match tag {
+                    [tag] => self.tagged(tag)?.1,
+                    _ => self.session.clone(),
+                };
+                fs::write(path, session.to_tests()).map_err(|err| {
+                    Error::new(ErrorCode::WriteFailed, format!("Cannot write '{}': {}.", path, err))
+                })?;
+                Ok(vec![format!("Wrote {} assertions to '{}'.", session.len(), path)])
+            }
+            ("load", [path]) => {
+                self.session = Session::load(Path::new(path))?;
+                Ok(vec![format!("Loaded {} entries from '{}'.", self.session.len(), path)])
+            }
+            ("macro", ["record"]) => match self.recording {
+                Some(_) => Err(invalid(String::from("A macro is already being recorded, stop it with ':macro stop'."))),
+                None => {
+                    self.recording = Some(Vec::new());
+                    Ok(vec![format!("Recording macro {}.", self.macros.len())])
+                }
+            },
+            ("macro", ["stop"]) => {
+                let inputs = self.recording.take()
+                    .ok_or_else(|| invalid(String::from("No macro is being recorded, start one with ':macro record'.")))?;
+                self.macros.push(inputs);
+                Ok(vec![format!("Recorded macro {} with {} inputs.", self.macros.len() - 1, self.macros.last().unwrap().len())])
+            }
+            ("macro", ["list"]) => Ok(self.macros.iter()
+                .enumerate()
+                .map(|(idx, inputs)| format!("{}: {}", idx, inputs.join(" ; ")))
+                .collect()),
+            ("macro", ["play", number, rest @ ..]) => {
+                let inputs = number.parse::<usize>().ok()
+                    .and_then(|idx| self.macros.get(idx))
+                    .ok_or_else(|| invalid(format!("No macro numbered '{}', there are {}.", number, self.macros.len())))?
+                    .clone();
+                let repeat = match rest {
+                    [] => 1,
+                    [repeat] => repeat.parse().ok().filter(|repeat| (1..=MAX_REPEAT).contains(repeat)).ok_or_else(|| {
+                        invalid(format!("Expected a repeat count from 1 to {}, instead got '{}'.", MAX_REPEAT, repeat))
+                    })?,
+                    _ => return Err(usage(name)),
+                };
+                let mut lines = Vec::new();
+                for input in (0..repeat).flat_map(|_| &inputs) {
+                    let response = self.respond(input);
+                    lines.extend(response.lines);
+                    if let Some(err) = response.error {
+                        return Err(Error::new(err.code, format!("Macro {} stopped at '{}': {}", number, input, err.message)));
+                    }
+                }
+                Ok(lines)
+            }
+            ("teach", ["off"]) => {
+                self.rules = Rules::default();
+                Ok(vec![String::from("Stopped explaining operators.")])
+            }
+            ("teach", [path]) => {
+                self.rules = Rules::load(Path::new(path))?;
+                Ok(vec![format!("Explaining operators following '{}'.", path)])
+            }
+            ("grid", []) => Ok(self.show_grid()),
+            ("grid", ["set", cell, expr @ ..]) => {
+                let cell = Cell::parse(cell)
+                    .ok_or_else(|| invalid(format!("Expected a cell from A1 to Z99, instead got '{}'.", cell)))?;
+                match self.grid.set(cell, &expr.join(" "))? {
+                    Some(int) => Ok(vec![format!("{} = {}", cell, self.settings.display(int))]),
+                    None => Ok(vec![format!("Cleared {}.", cell)]),
+                }
+            }
+            ("grid", ["export", path]) => {
+                fs::write(path, self.grid.to_script()?).map_err(|err| {
+                    Error::new(ErrorCode::WriteFailed, format!("Cannot write '{}': {}.", path, err))
+                })?;
+                Ok(vec![format!("Exported {} cells to '{}'.", self.grid.len(), path)])
+            }
+            ("grid", ["import", path]) => {
+                let text = fs::read_to_string(path).map_err(|err| {
+                    Error::new(ErrorCode::ReadFailed, format!("Cannot read '{}': {}.", path, err))
+                })?;
+                self.grid = Grid::from_script(&text)?;
+                Ok(vec![format!("Imported {} cells from '{}'.", self.grid.len(), path)])
+            }
+            _ => Err(usage(name)),
+        }
+    }
+}
+
+impl Repl {
+    // Removes the `count` values on top of the stack, bottom one first.
+    fn pop(&mut self, count: usize) -> Result<Vec<isize>, Error> {
+        let Some(start) = self.stack.len().checked_sub(count) else {
+            return Err(Error::new(
+                ErrorCode::StackUnderflow,
+                format!("Expected {} values on the stack, found {}.", count, self.stack.len()),
+            ));
+        };
+        Ok(self.stack.split_off(start))
+    }
+
+    // Displays a cell, failing ones by their error code.
+    pub fn show_cell(&self, cell: Cell) -> String {
+        match self.grid.value(cell) {
+            None => String::new(),
+            Some(Ok(int)) => self.settings.display(*int),
+            Some(Err(err)) => format!("#{}", err.code.as_str()),
+        }
+    }
+
+    fn show_grid(&self) -> Vec<String> {
+        let Some(extent) = self.grid.extent() else {
+            return vec![String::from("The grid is empty.")];
+        };
+        let mut header = vec![String::new()];
+        header.extend((0..=extent.column).map(|column| Cell::column_name(column).to_string()));
+        let mut rows = vec![header];
+        for row in 0..=extent.row {
+            let mut cells = vec![(row + 1).to_string()];
+            cells.extend((0..=extent.column).map(|column| self.show_cell(Cell { row, column })));
+            rows.push(cells);
+        }
+        table(&rows, " | ", &[])
+    }
+
+    fn show_stack(&self) -> Vec<String> {
+        let values: Vec<_> = self.stack.iter().map(|int| self.settings.display(*int)).collect();
+        vec![format!("Stack: [{}]", values.join(" "))]
+    }
+
+    fn trend(&self, count: usize) -> Result<String, Error> {
+        let history = self.session.history();
+        let recent = &history[history.len().saturating_sub(count)..];
+        let [first, .., last] = recent else {
+            return Err(invalid(String::from("A trend needs at least two results.")));
+        };
+        let ups = recent.windows(2).filter(|pair| pair[1] > pair[0]).count();
+        let downs = recent.windows(2).filter(|pair| pair[1] < pair[0]).count();
+        let direction = match (ups, downs) {
+            (0, 0) => String::from("Flat"),
+            (_, 0) => String::from("Increasing"),
+            (0, _) => String::from("Decreasing"),
+            (ups, downs) => format!("Mixed, {} up and {} down,", ups, downs),
+        };
+        let change = match last.checked_sub(*first) {
+            Some(change) => format!("{}{}", if change > 0 { "+" } else { "" }, self.settings.display(change)),
+            None => String::from("out of range"),
+        };
+        Ok(format!(
+            "{} over the last {} results, from {} to {} ({}).",
+            direction, recent.len(), self.settings.display(*first), self.settings.display(*last), change,
+        ))
+    }
+
+    // The count of entries tagged, and a session of them along with the
+    // entries they refer to.
+    fn tagged(&self, tag: &str) -> Result<(usize, Session), Error> {
+        let tagged = self.session.tagged(tag);
+        if tagged.is_empty() {
+            return Err(invalid(format!("No entry is tagged '{}'.", tag)));
+        }
+        Ok((tagged.len(), self.session.select(&tagged)?))
+    }
+
+    fn index(&self, index: &str) -> Result<usize, Error> {
+        index.strip_prefix('$').unwrap_or(index).parse::<usize>().ok()
+            .filter(|idx| *idx < self.session.len())
+            .ok_or_else(|| Error::new(
+                ErrorCode::InvalidVariableIndex,
+                format!("Expected an entry of the session, which has {} entries, instead found '{}'.", self.session.len(), index),
+            ))
+    }
+
+    // Tries every value of the range in turn, so that non-monotonic chains
+    // are handled too, settling for the closest result when none is exact.
+    fn goal_seek(&self, target: Option<&str>, goal: &str, index: &str, range: &str) -> Result<Vec<String>, Error> {
+        let target = match target {
+            Some(target) => self.index(target)?,
+            None => self.session.len().checked_sub(1).ok_or_else(|| invalid(String::from("The session is empty.")))?,
+        };
+        let goal = goal.parse::<isize>()
+            .map_err(|_| invalid(format!("Expected an integer goal, instead found '{}'.", goal)))?;
+        let idx = self.index(index)?;
+        let (from, to) = parse_range(range)?;
+        if (to as i128 - from as i128) >= MAX_SEARCH as i128 {
+            return Err(invalid(format!("The search is limited to {} values.", MAX_SEARCH)));
+        }
+
+        let mut closest: Option<(isize, isize)> = None;
+        for value in from..=to {
+            let Ok(history) = self.session.recompute(idx, value) else { continue };
+            let result = history[target];
+            if result == goal {
+                return Ok(vec![format!("${} = {} makes ${} equal {}.", idx, value, target, goal)]);
+            }
+            if closest.is_none_or(|(_, best)| result.abs_diff(goal) < best.abs_diff(goal)) {
+                closest = Some((value, result));
+            }
+        }
+        Ok(vec![match closest {
+            Some((value, result)) => format!(
+                "No value of ${} in {} makes ${} equal {}, the closest is ${} = {} giving {}.",
+                idx, range, target, goal, idx, value, result,
+            ),
+            None => format!("Every value of ${} in {} fails to evaluate.", idx, range),
+        }])
+    }
+
+    fn what_if(&self, index: &str, range: &str, step: &str) -> Result<Vec<String>, Error> {
+        let idx = self.index(index)?;
+        let (from, to) = parse_range(range)?;
+        let step = step.parse::<usize>().ok().filter(|step| *step > 0)
+            .ok_or_else(|| invalid(format!("Expected a positive step, instead found '{}'.", step)))?;
+        let values: Vec<_> = (from..=to).step_by(step).take(MAX_SWEEP + 1).collect();
+        if values.len() > MAX_SWEEP {
+            return Err(invalid(format!("The sweep is limited to {} values.", MAX_SWEEP)));
+        }
+
+        let last = self.session.len() - 1;
+        let header = vec![format!("${}", idx), format!("${}", last)];
+        let rows: Vec<_> = [header].into_iter()
+            .chain(values.iter().map(|value| {
+                let result = match self.session.recompute(idx, *value) {
+                    Ok(history) => self.settings.display(history[last]),
+                    Err(err) => format!("Error[{}]", err.code),
+                };
+                vec![self.settings.display(*value), result]
+            }))
+            .collect();
+        Ok(table(&rows, " | ", &[]))
+    }
+}
+
+// Pads every column to its widest cell, aligning numbers and history
+// variables to the right, except in the `text` columns which are always
+// aligned to the left.
+fn table(rows: &[Vec<String>], separator: &str, text: &[usize]) -> Vec<String> {
+    let columns = rows.first().map_or(0, Vec::len);
+    let widths: Vec<_> = (0..columns)
+        .map(|column| rows.iter().map(|row| row[column].chars().count()).max().unwrap_or_default())
+        .collect();
+    let numeric = |cell: &str| {
+        cell.chars().any(|char| char.is_ascii_digit())
+            && cell.chars().all(|char| char.is_ascii_digit() || "-$,_ :".contains(char))
+    };
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<_> = row.iter().zip(&widths)
+                .enumerate()
+                .map(|(column, (cell, width))| match numeric(cell) && !text.contains(&column) {
+                    true => format!("{:>width$}", cell),
+                    false => format!("{:<width$}", cell),
+                })
+                .collect();
+            String::from(cells.join(separator).trim_end())
+        })
+        .collect()
+}
+
+// Shortcuts refer to the last two of the `len` results before the line, even
+// when the line holds several expressions.
+pub fn expand_shortcuts(line: &str, len: usize) -> Result<String, Error> {
+    let tokens = line.split_whitespace()
+        .map(|token| {
+            let (last, previous) = match (token, len) {
+                ("delta" | "ratio", 0 | 1) => return Err(Error::new(
+                    ErrorCode::InvalidVariableIndex,
+                    format!("'{}' needs two results in history, there are {}.", token, len),
+                )),
+                ("delta" | "ratio", _) => (len - 1, len - 2),
+                _ => return Ok(String::from(token)),
+            };
+            Ok(match token {
+                "delta" => format!("- ${} ${}", last, previous),
+                _ => format!("/ * ${} 100 ${}", last, previous),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(tokens.join(" "))
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorCode::InvalidCommand, message)
+}
+
+fn parse_range(range: &str) -> Result<(isize, isize), Error> {
+    range.split_once("..")
+        .and_then(|(from, to)| Some((from.parse().ok()?, to.parse().ok()?)))
+        .ok_or_else(|| invalid(format!("Expected an integer range like 1..10, instead found '{}'.", range)))
+}
+
+fn usage(name: &str) -> Error {
+    let command = format!(":{}", name);
+    match COMMANDS.iter().find(|(usage, _)| usage.split_whitespace().next() == Some(&command)) {
+        Some((usage, _)) => Error::new(ErrorCode::InvalidCommand, format!("Usage: {}", usage)),
+        None => Error::new(ErrorCode::InvalidCommand, format!("Unknown command '{}'.", command)),
+    }
+}
+
+fn help() -> Vec<String> {
+    let mut lines: Vec<_> = registry::help().lines().map(String::from).collect();
+    lines.push(String::from("Commands:"));
+    let width = COMMANDS.iter().map(|(usage, _)| usage.len()).max().unwrap_or_default();
+    lines.extend(COMMANDS.iter().map(|(usage, summary)| format!("  {:<width$} {}", usage, summary)));
+    lines.push(String::from("History shortcuts:"));
+    let width = SHORTCUTS.iter().map(|(name, _)| name.len()).max().unwrap_or_default();
+    lines.extend(SHORTCUTS.iter().map(|(name, summary)| format!("  {:<width$} {}", name, summary)));
+    lines.push(String::from("Settings:"));
+    let width = SETTINGS.iter().map(|(usage, _)| usage.len()).max().unwrap_or_default();
+    lines.extend(SETTINGS.iter().map(|(usage, summary)| format!("  {:<width$} {}", usage, summary)));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::repl::{Pushed, Repl, Response};
+    use crate::{ErrorCode, TrailingPolicy};
+
+    #[test]
+    fn expressions() {
+        let mut repl = Repl::new(TrailingPolicy::Reject);
+        assert_eq!(repl.handle("+ 3 2"), Response {
+            lines: vec![String::from("5")],
+            results: vec![Pushed { index: 0, value: 5, line: 0 }],
+            error: None,
+        });
+        assert_eq!(repl.handle("/ $0 0").error.unwrap().code, ErrorCode::DivisionByZero);
+        assert_eq!(repl.session.history(), [5]);
+        assert_eq!(repl.session.entries()[0].input, "+ 3 2");
+    }
+
+    #[test]
+    fn on_result() {
+        let mut repl = Repl::new(TrailingPolicy::NextExpression);
+        repl.handle(":set on-result 'hex {result:x}, doubled {* result 2}, previous {$0}'");
+        assert_eq!(repl.handle("255").lines, ["255", "hex ff, doubled 510, previous 255"]);
+        repl.handle(":set on-result \"!echo {result},{- result $0}\"");
+        assert_eq!(repl.handle("256 300").lines, ["256", "300", "256,1", "300,45"]);
+        repl.handle(":set on-result '{$9}'");
+        let response = repl.handle("1");
+        assert_eq!((response.lines, response.error.unwrap().code), (vec![String::from("1")], ErrorCode::InvalidVariableIndex));
+    }
+
+    #[test]
+    fn filters() {
+        let config = Config::parse("filter \"€\" \"\"\ntoken x *\nset sigfigs 1").unwrap();
+        let mut repl = Repl::with_config(TrailingPolicy::Reject, config);
+        assert_eq!(repl.handle("x 3€ 12€").lines, ["40"]);
+        assert_eq!(repl.session.entries()[0].input, "* 3 12");
+    }
+
+    #[test]
+    fn anonymize() {
+        let mut repl = Repl::new(TrailingPolicy::Reject);
+        assert_eq!(repl.handle(":anonymize *  salary   ! salary ; Alice's pay").lines, ["* x0 fact x0"]);
+        assert_eq!(repl.handle(":anonymize + salary").error.unwrap().code, ErrorCode::BinaryArity);
+    }
+
+    #[test]
+    fn trace() {
+        let mut repl = Repl::new(TrailingPolicy::Reject);
+        let lines = repl.handle(":trace * 6 ! 3").lines;
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("* => 36  ") && lines[0].ends_with("100% ##########"));
+        assert!(lines[2].starts_with("  fact => 6  "));
+        assert!(repl.session.is_empty());
+    }
+
+    #[test]
+    fn history() {
+        let mut repl = Repl::new(TrailingPolicy::NextExpression);
+        repl.handle("+ 3 2 ! $0");
+        assert_eq!(repl.handle(":history").lines, ["$0: + 3 2   =>   5", "$1: fact $0 => 120"]);
+        repl.handle(":set grouping ,");
+        repl.handle("* 1000 1000 1 2 3 4 5 6 7 8");
+        assert_eq!(repl.handle(":history").lines[1..4], [
+            " $1: fact $0     =>       120",
+            " $2: * 1000 1000 => 1,000,000",
+            " $3: 1           =>         1",
+        ]);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("cwh-repl-{}.session", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut repl = Repl::new(TrailingPolicy::NextExpression);
+        repl.handle("3 4 * $0 $1");
+        assert!(repl.handle(&format!(":save {}", path)).error.is_none());
+
+        let mut other = Repl::new(TrailingPolicy::Reject);
+        other.handle("42");
+        assert_eq!(other.handle(&format!(":load {}", path)).lines, [format!("Loaded 3 entries from '{}'.", path)]);
+        assert_eq!(other.session, repl.session);
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(other.handle(&format!(":load {}", path)).error.unwrap().code, ErrorCode::ReadFailed);
+        assert_eq!(other.session.len(), 3);
+    }
+
+    #[test]
+    fn store() {
+        let mut repl = Repl::new(TrailingPolicy::Reject);
+        let reference = repl.handle(":store * $0 $0").lines.remove(0);
+        repl.handle("7");
+        assert_eq!(repl.handle(&format!("+ {} 1", reference)).lines, ["50"]);
+        assert_eq!(repl.session.entries()[1].input, "+ * $0 $0 1");
+        assert_eq!(repl.handle("#ffffff").error.unwrap().code, ErrorCode::UnknownReference);
+        assert_eq!(repl.handle(":store").error.unwrap().message, "Usage: :store <expr>");
+    }
+
+    #[test]
+    fn branches() {
+        let mut repl = Repl::new(TrailingPolicy::NextExpression);
+        repl.handle("1 2 3 4");
+        assert_eq!(repl.handle(":branch $1 low").lines, ["Switched to new session 'low' with 2 entries."]);
+        repl.handle("+ $0 $1");
+        assert_eq!(repl.session.history(), [1, 2, 3]);
+        assert_eq!(repl.handle(":sessions").lines, ["* low (3 entries)", "  main (4 entries)"]);
+
+        repl.handle(":switch main");
+        assert_eq!(repl.session.history(), [1, 2, 3, 4]);
+        assert_eq!(repl.handle(":switch main").lines, ["Already in session 'main'."]);
+        assert_eq!(repl.handle(":sessions").lines, ["  low (3 entries)", "* main (4 entries)"]);
+        assert_eq!(repl.handle(":branch 4 high").error.unwrap().code, ErrorCode::InvalidVariableIndex);
+        assert_eq!(repl.handle(":branch 0 low").error.unwrap().code, ErrorCode::InvalidSessionName);
+        assert_eq!(repl.handle(":switch nowhere").error.unwrap().code, ErrorCode::InvalidSessionName);
+        assert_eq!(repl.name, "main");
+        assert_eq!(repl.handle("$0").lines, ["1"]);
+    }
+
+    #[test]
+    fn diff() {
+        let mut repl = Repl::new(TrailingPolicy::NextExpression);
+        repl.handle("1 2 3");
+        repl.handle(":branch 1 other");
+        assert_eq!(repl.handle(":diff main other").lines, [
+            "Sessions 'main' and 'other' diverge from $2:",
+            "  $2: 3 => 3 | (none)",
+        ]);
+        repl.handle("+ $0 $1 5");
+        assert_eq!(repl.handle(":diff main other").lines[1..], ["  $2: 3 => 3 | + $0 $1 => 3", "  $3: (none) | 5 => 5"]);
+        assert_eq!(repl.handle(":diff other other").lines, ["Sessions 'other' and 'other' are identical."]);
+        assert_eq!(repl.handle(":diff main nowhere").error.unwrap().code, ErrorCode::InvalidSessionName);
+    }
+
+    #[test]
+    fn what_if() {
+        let mut repl = Repl::new(TrailingPolicy::NextExpression);
+        repl.handle("100 5 * $0 $1 sgn $1 / $2 $3");
+        assert_eq!(repl.handle(":whatif $1 over -1..3 step 2").lines, [
+            "$1 |  $4",
+            "-1 | 100",
+            " 1 | 100",
+            " 3 | 300",
+        ]);
+        assert_eq!(repl.handle(":whatif 1 over 0..0").lines[1], " 0 | Error[E0102]");
+        assert_eq!(repl.handle(":whatif 5 over 0..1").error.unwrap().code, ErrorCode::InvalidVariableIndex);
+        assert_eq!(repl.handle(":whatif 1 over 0..1 step 0").error.unwrap().code, ErrorCode::InvalidCommand);
+        assert_eq!(repl.handle(":whatif 1 over 0..5000").error.unwrap().code, ErrorCode::InvalidCommand);
+        assert!(repl.handle(":whatif 1 0..5").error.unwrap().message.starts_with("Usage: :whatif <n> over"));
+    }
+
+    #[test]
+    fn goal_seek() {
+        let mut repl = Repl::new(TrailingPolicy::NextExpression);
+        repl.handle("100 5 * $0 $1 sgn $1 / $2 $3");
+        assert_eq!(repl.handle(":goalseek target 700 by $1 in 0..10").lines, ["$1 = 7 makes $4 equal 700."]);
+        assert_eq!(repl.handle(":goalseek $3 target -1 by 1 in -10..10").lines, ["$1 = -10 makes $3 equal -1."]);
+        assert_eq!(repl.handle(":goalseek target 750 by 1 in 0..10").lines, [
+            "No value of $1 in 0..10 makes $4 equal 750, the closest is $1 = 7 giving 700.",
+        ]);
+        assert_eq!(repl.handle(":goalseek target 1 by 1 in 0..0").lines, ["Every value of $1 in 0..0 fails to evaluate."]);
+        assert_eq!(repl.handle(":goalseek target 1 by 1 in 0..1000000").error.unwrap().code, ErrorCode::InvalidCommand);
+        assert_eq!(repl.handle(":goalseek $9 target 1 by 1 in 0..1").error.unwrap().code, ErrorCode::InvalidVariableIndex);
+    }
+
+    #[test]
+    fn settings() {
+        let mut repl = Repl::new(TrailingPolicy::Reject);
+        assert_eq!(repl.handle(":set sigfigs 2").error, None);
+        assert_eq!(repl.handle("* 1234 10").lines, ["12000"]);
+        assert_eq!(repl.session.history(), [12340]);
+        assert_eq!(repl.handle(":set").lines[0], "sigfigs 2");
+        repl.handle(":set notify-after 0");
+        repl.handle(":set notify-command exit   3");
+        assert_eq!(repl.handle("1").error.unwrap().code, ErrorCode::Unavailable);
+        assert_eq!(repl.session.history(), [12340, 1]);
+        assert_eq!(repl.handle(":set sigfigs").error.unwrap().code, ErrorCode::InvalidCommand);
+        assert_eq!(repl.handle(":set sigfigs -1").error.unwrap().code, ErrorCode::InvalidSetting);
+    }
+
+    #[test]
+    fn stack() {
+        let mut repl = Repl::new(TrailingPolicy::Reject);
+        repl.handle("3");
+        assert_eq!(repl.handle(":push").lines, ["Stack: [3]"]);
+        assert_eq!(repl.handle(":push * $0 4").lines, ["Stack: [3 12]"]);
+        assert_eq!(repl.handle(":swap").lines, ["Stack: [12 3]"]);
+        assert_eq!(repl.handle(":dup").lines, ["Stack: [12 3 3]"]);
+        assert_eq!(repl.handle(":pop").lines, ["3"]);
+        assert_eq!(repl.session.history(), [3, 3]);
+        assert_eq!(repl.stack, [12, 3]);
+
+        repl.stack.clear();
+        assert_eq!(repl.handle(":swap").error.unwrap().code, ErrorCode::StackUnderflow);
+        assert_eq!(repl.handle(":pop").error.unwrap().code, ErrorCode::StackUnderflow);
+        assert_eq!(repl.handle(":push / 1 0").error.unwrap().code, ErrorCode::DivisionByZero);
+        assert!(repl.stack.is_empty());
+    }
+
+    #[test]
+    fn edit_buffer() {
+        let mut repl = Repl::new(TrailingPolicy::Reject);
+        repl.handle(":set editor sed -i s/3/4/");
+        assert_eq!(repl.handle(":edit-buffer + 3 3").lines, ["7"]);
+        assert_eq!(repl.handle(":edit-buffer $0").lines, ["8"]);
+        assert_eq!(repl.session.entries()[1].input, "+ 4 4");
+        assert_eq!(repl.handle(":edit-buffer $2").error.unwrap().code, ErrorCode::InvalidVariableIndex);
+    }
+
+    #[test]
+    #[cfg(not(feature = "clipboard"))]
+    fn clipboard() {
+        let mut repl = Repl::new(TrailingPolicy::Reject);
+        assert_eq!(repl.handle(":copy").error.unwrap().code, ErrorCode::Unavailable);
+    }
+
+    #[test]
+    fn grid() {
+        let mut repl = Repl::new(TrailingPolicy::Reject);
+        assert_eq!(repl.handle(":grid").lines, ["The grid is empty."]);
+        assert_eq!(repl.handle(":grid set B2 * A1 2").error.unwrap().code, ErrorCode::UnknownName);
+        assert_eq!(repl.handle(":grid set A1 3").lines, ["A1 = 3"]);
+        assert_eq!(repl.handle(":grid").lines, ["  | A | B", "1 | 3 |", "2 |   | 6"]);
+        assert_eq!(repl.handle(":grid set A1").lines, ["Cleared A1."]);
+        assert_eq!(repl.handle(":grid set a1 3").error.unwrap().code, ErrorCode::InvalidCommand);
+
+        let path = std::env::temp_dir().join(format!("cwh-grid-{}.cwh", std::process::id()));
+        repl.handle(":grid set A1 4");
+        assert_eq!(repl.handle(&format!(":grid export {}", path.display())).error, None);
+        let mut other = Repl::new(TrailingPolicy::Reject);
+        assert_eq!(other.handle(&format!(":grid import {}", path.display())).error, None);
+        assert_eq!(other.show_cell(crate::grid::Cell { row: 1, column: 1 }), "8");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tags() {
+        let mut repl = Repl::new(TrailingPolicy::Reject);
+        for line in ["1000", "* $0 20", "/ $1 100", "+ 3 4", "- $0 $2"] {
+            repl.handle(line);
+        }
+        assert_eq!(repl.handle(":tag 2 taxes q1").lines, ["$2: #q1 #taxes"]);
+        assert_eq!(repl.handle(":tag $4 taxes").lines, ["$4: #taxes"]);
+        assert_eq!(repl.handle(":untag 2 q1").lines, ["$2: #taxes"]);
+        assert_eq!(repl.handle(":tag 5 taxes").error.unwrap().code, ErrorCode::InvalidVariableIndex);
+        assert_eq!(repl.handle(":tag 1 a,b").error.unwrap().code, ErrorCode::InvalidCommand);
+        assert_eq!(repl.handle(":history taxes").lines, ["$2: / $1 100 => 200 #taxes", "$4: - $0 $2  => 800 #taxes"]);
+        assert_eq!(repl.handle(":history").lines[3], "$3: + 3 4    =>     7");
+
+        let path = std::env::temp_dir().join(format!("cwh-repl-tags-{}", std::process::id()));
+        let save = format!(":save {} taxes", path.display());
+        assert_eq!(repl.handle(&save).lines, [format!("Saved 2 entries tagged 'taxes' and 2 they refer to to '{}'.", path.display())]);
+        assert_eq!(repl.handle(&format!(":save {} q1", path.display())).error.unwrap().code, ErrorCode::InvalidCommand);
+        repl.handle(&format!(":load {}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(repl.handle(":history").lines, [
+            "$0: 1000     =>  1000",
+            "$1: * $0 20  => 20000",
+            "$2: / $1 100 =>   200 #taxes",
+            "$3: - $0 $2  =>   800 #taxes",
+        ]);
+    }
+
+    #[test]
+    fn notes() {
+        let mut repl = Repl::new(TrailingPolicy::Reject);
+        for line in ["1000", "* $0 9", "/ $1 10"] {
+            repl.handle(line);
+        }
+        assert_eq!(repl.handle(":note 1  \"before  discount\" ").lines, ["$1: before  discount"]);
+        assert_eq!(repl.handle(":note 2 \"after\"").lines, ["$2: after"]);
+        assert_eq!(repl.handle(":note 2 after").error.unwrap().code, ErrorCode::InvalidCommand);
+        assert_eq!(repl.handle(":note 3 \"x\"").error.unwrap().code, ErrorCode::InvalidVariableIndex);
+        repl.handle(":tag 2 net");
+        assert_eq!(repl.handle(":history --notes").lines, [
+            "$0: 1000    => 1000",
+            "$1: * $0 9  => 9000      ; before  discount",
+            "$2: / $1 10 =>  900 #net ; after",
+        ]);
+        assert_eq!(repl.handle(":history net --notes").lines, ["$2: / $1 10 => 900 #net ; after"]);
+        assert_eq!(repl.handle(":history").lines[1], "$1: * $0 9  => 9000");
+        assert_eq!(repl.handle(":note 2").lines, ["Removed the note of $2."]);
+        assert_eq!(repl.session.entries()[2].note, None);
+    }
+
+    #[test]
+    fn export_tests() {
+        let mut repl = Repl::new(TrailingPolicy::Reject);
+        for line in ["+ 3 2", "* $0 2", "7"] {
+            repl.handle(line);
+        }
+        repl.handle(":tag 1 double");
+        let path = std::env::temp_dir().join(format!("cwh-repl-tests-{}.cwh", std::process::id()));
+        let export = |repl: &mut Repl, args: &str| repl.handle(&format!(":export-tests {}{}", path.display(), args));
+        assert_eq!(export(&mut repl, "").lines, [format!("Wrote 3 assertions to '{}'.", path.display())]);
+        let mut output = Vec::new();
+        let lines = crate::script::load(&path).unwrap();
+        crate::script::Runner::new().run(&lines, &mut |line| output.push(line)).unwrap();
+        assert_eq!(output, ["5", "10", "7"]);
+
+        assert_eq!(export(&mut repl, " double").lines, [format!("Wrote 2 assertions to '{}'.", path.display())]);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "+ 3 2\n@assert-eq $0 5\n* $0 2\n@assert-eq $1 10\n");
+        assert_eq!(export(&mut repl, " none").error.unwrap().code, ErrorCode::InvalidCommand);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn teach() {
+        let mut repl = Repl::new(TrailingPolicy::Reject);
+        let path = std::env::temp_dir().join(format!("cwh-repl-teach-{}", std::process::id()));
+        std::fs::write(&path, "/ \"integer division truncates toward zero: {a}/{b} = {result}\"\n").unwrap();
+        repl.handle(&format!(":teach {}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(repl.handle("+ / 7 2 1").lines, ["integer division truncates toward zero: 7/2 = 3", "4"]);
+        assert_eq!(repl.handle(":teach off").lines, ["Stopped explaining operators."]);
+        assert_eq!(repl.handle("/ 7 2").lines, ["3"]);
+        assert_eq!(repl.handle(&format!(":teach {}", path.display())).error.unwrap().code, ErrorCode::ReadFailed);
+    }
+
+    #[test]
+    fn shortcuts() {
+        let mut repl = Repl::new(TrailingPolicy::Reject);
+        assert_eq!(repl.handle("delta").error.unwrap().code, ErrorCode::InvalidVariableIndex);
+        repl.handle("80");
+        repl.handle("100");
+        assert_eq!(repl.handle("delta").lines, ["20"]);
+        assert_eq!(repl.handle("* ratio 2").lines, ["40"]);
+        assert_eq!(repl.session.entries()[3].input, "* / * $2 100 $1 2");
+        assert_eq!(repl.handle(":trend").lines, ["Mixed, 2 up and 1 down, over the last 4 results, from 80 to 40 (-40)."]);
+        assert_eq!(repl.handle(":trend 2").lines, ["Increasing over the last 2 results, from 20 to 40 (+20)."]);
+        assert_eq!(repl.handle(":trend 1").error.unwrap().code, ErrorCode::InvalidCommand);
+        assert_eq!(Repl::new(TrailingPolicy::Reject).handle(":trend").error.unwrap().code, ErrorCode::InvalidCommand);
+    }
+
+    #[test]
+    fn macros() {
+        let mut repl = Repl::new(TrailingPolicy::Reject);
+        assert_eq!(repl.handle(":macro stop").error.unwrap().code, ErrorCode::InvalidCommand);
+        assert_eq!(repl.handle(":macro record").lines, ["Recording macro 0."]);
+        repl.handle("10");
+        repl.handle("/ 1 0");
+        repl.handle(":push");
+        repl.handle(":macro list");
+        assert_eq!(repl.handle(":macro stop").lines, ["Recorded macro 0 with 2 inputs."]);
+        assert_eq!(repl.handle(":macro list").lines, ["0: 10 ; :push"]);
+        assert_eq!(repl.handle(":macro play 0 2").lines, ["10", "Stack: [10 10]", "10", "Stack: [10 10 10]"]);
+        assert_eq!(repl.session.len(), 3);
+        assert_eq!(repl.handle(":macro play 1").error.unwrap().code, ErrorCode::InvalidCommand);
+        assert_eq!(repl.handle(":macro play 0 0").error.unwrap().code, ErrorCode::InvalidCommand);
+
+        repl.handle(":macro record");
+        repl.handle(":pop");
+        repl.handle(":macro stop");
+        let err = repl.handle(":macro play 1 3").error.unwrap();
+        assert_eq!(err.code, ErrorCode::StackUnderflow);
+        assert!(err.message.starts_with("Macro 1 stopped at ':pop': "));
+        assert!(repl.stack.is_empty());
+    }
+
+    #[test]
+    fn commands() {
+        let mut repl = Repl::new(TrailingPolicy::Reject);
+        let help = repl.handle(":help");
+        assert!(help.lines.iter().any(|line| line.contains("fact | !")));
+        assert!(help.lines.iter().any(|line| line.contains(":help")));
+        assert_eq!(repl.handle(":hlep").error.unwrap().code, ErrorCode::InvalidCommand);
+        assert_eq!(repl.handle(":help me").error.unwrap().message, "Usage: :help");
+    }
+}
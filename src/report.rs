@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::json;
+use crate::repl::expand_shortcuts;
+use crate::session::{self, Session};
+use crate::{evaluate_value, parse_line, Error, ErrorCode, Value};
+use crate::Value::{BinaryOperation, Int, Name, UnaryOperation, Variable};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    pub inputs: usize,
+    pub results: usize,
+    pub errors: BTreeMap<&'static str, usize>,
+    pub operators: BTreeMap<String, usize>,
+    pub max_depth: usize,
+    total_depth: usize,
+    parsed: usize,
+}
+
+// Transcripts copied from the terminal contain prompts followed by the output,
+// plain session files contain only the inputs.
+pub fn session_inputs(text: &str) -> Vec<&str> {
+    let lines = text.lines().map(str::trim_end);
+    if text.lines().any(|line| line.starts_with("# ")) {
+        lines.filter_map(|line| line.strip_prefix("# ")).filter(|line| !line.trim().is_empty()).collect()
+    } else {
+        lines.filter(|line| !line.trim().is_empty()).collect()
+    }
+}
+
+// Inputs of the file, be it a session saved by `:save`, in either format,
+// a transcript or a plain list of inputs.
+pub fn load_inputs(path: &Path) -> Result<Vec<String>, Error> {
+    let bytes = fs::read(path).map_err(|err| {
+        Error::new(ErrorCode::ReadFailed, format!("Cannot read '{}': {}.", path.display(), err))
+    })?;
+    if bytes.starts_with(session::MAGIC) || bytes.starts_with(session::HEADER.as_bytes()) {
+        let session = Session::load(path)?;
+        return Ok(session.entries().iter().map(|entry| entry.input.clone()).collect());
+    }
+    let text = String::from_utf8(bytes)
+        .map_err(|_| Error::new(ErrorCode::ReadFailed, format!("Cannot read '{}': not valid UTF-8.", path.display())))?;
+    Ok(session_inputs(&text).into_iter().map(String::from).collect())
+}
+
+fn count_operators(value: &Value, operators: &mut BTreeMap<String, usize>) {
+    match value {
+        BinaryOperation { operator, left, right } => {
+            *operators.entry(operator.to_string()).or_default() += 1;
+            count_operators(left, operators);
+            count_operators(right, operators);
+        }
+        Int(_) | Name(_) | Variable(_) => {}
+        UnaryOperation { operator, arg } => {
+            *operators.entry(operator.to_string()).or_default() += 1;
+            count_operators(arg, operators);
+        }
+    }
+}
+
+impl Report {
+    // REPL commands are skipped, they are not calculations, and shortcuts
+    // are expanded as the REPL does.
+    pub fn new<'a>(inputs: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut report = Report::default();
+        let mut history = Vec::new();
+        for input in inputs.into_iter().filter(|input| !input.trim_start().starts_with(':')) {
+            report.inputs += 1;
+            let expanded = expand_shortcuts(input, history.len());
+            let result = expanded.and_then(|input| parse_line(input.split_whitespace())).and_then(|value| {
+                report.parsed += 1;
+                report.total_depth += value.depth();
+                report.max_depth = report.max_depth.max(value.depth());
+                count_operators(&value, &mut report.operators);
+                evaluate_value(&value, &history)
+            });
+            match result {
+                Ok(int) => {
+                    report.results += 1;
+                    history.push(int);
+                }
+                Err(err) => *report.errors.entry(err.code.as_str()).or_default() += 1,
+            }
+        }
+        report
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.errors.values().sum()
+    }
+
+    pub fn average_depth(&self) -> f64 {
+        if self.parsed == 0 {
+            0.0
+        } else {
+            self.total_depth as f64 / self.parsed as f64
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        json::object([
+            ("inputs", self.inputs.to_string()),
+            ("results", self.results.to_string()),
+            ("errors", json::object(&self.errors)),
+            ("operators", json::object(&self.operators)),
+            ("average_depth", format!("{:.2}", self.average_depth())),
+            ("max_depth", self.max_depth.to_string()),
+        ])
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Inputs: {}", self.inputs)?;
+        writeln!(f, "Results: {}", self.results)?;
+        writeln!(f, "Errors: {}", self.error_count())?;
+        for (code, count) in &self.errors {
+            writeln!(f, "  {} {}", code, count)?;
+        }
+        writeln!(f, "Average depth: {:.2}", self.average_depth())?;
+        writeln!(f, "Maximum depth: {}", self.max_depth)?;
+        write!(f, "Operators:")?;
+        for (operator, count) in &self.operators {
+            write!(f, "\n  {} {}", operator, count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use crate::report::{load_inputs, session_inputs, Report};
+    use crate::session::Session;
+
+    #[test]
+    fn inputs() {
+        assert_eq!(session_inputs("+ 3 2\n\n* $0 2\n"), ["+ 3 2", "* $0 2"]);
+        assert_eq!(session_inputs("# + 3 2\n5\n# \n# / 1 0\nError[E0102]: Division by zero.\n"),
+                   ["+ 3 2", "/ 1 0"]);
+    }
+
+    #[test]
+    fn transcript_commands() {
+        let transcript = "# 100\n100\n# :help\nCommands:\n# 80\n80\n# delta\n-20\n# :save notes.cwh\n\
+            Saved 3 entries to 'notes.cwh'.\n# ratio\n-25\n";
+        let report = Report::new(session_inputs(transcript));
+        assert_eq!((report.inputs, report.results, report.error_count()), (4, 4, 0));
+        assert_eq!(report.operators.get("-"), Some(&1));
+        assert_eq!(Report::new(["delta"]).errors.get("E0101"), Some(&1));
+    }
+
+    #[test]
+    fn saved_sessions() {
+        let mut session = Session::new();
+        session.push(String::from("+ 3 2"), 5);
+        session.push(String::from("* $0 2"), 10);
+        for extension in ["cwh", "cwhs"] {
+            let path = env::temp_dir().join(format!("cwh-report-{}.{}", std::process::id(), extension));
+            session.save(&path).unwrap();
+            let inputs = load_inputs(&path);
+            fs::remove_file(&path).unwrap();
+            let inputs = inputs.unwrap();
+            assert_eq!(inputs, ["+ 3 2", "* $0 2"]);
+            let report = Report::new(inputs.iter().map(String::as_str));
+            assert_eq!((report.results, report.error_count()), (2, 0));
+        }
+    }
+
+    #[test]
+    fn summary() {
+        let report = Report::new(["+ 3 2", "* $0 fact 3", "/ $1 0", "$7", "+ 1"]);
+        assert_eq!(report.inputs, 5);
+        assert_eq!(report.results, 2);
+        assert_eq!(report.error_count(), 3);
+        assert_eq!(report.errors.get("E0102"), Some(&1));
+        assert_eq!(report.errors.get("E0101"), Some(&1));
+        assert_eq!(report.errors.get("E0004"), Some(&1));
+        assert_eq!(report.operators.get("+"), Some(&1));
+        assert_eq!(report.operators.get("fact"), Some(&1));
+        assert_eq!(report.max_depth, 3);
+        assert_eq!(report.average_depth(), 2.0);
+    }
+
+    #[test]
+    fn json() {
+        let report = Report::new(["+ 3 2", "/ $0 0"]);
+        assert_eq!(
+            report.to_json(),
+            r#"{"inputs":2,"results":1,"errors":{"E0102":1},"operators":{"+":1,"/":1},"average_depth":2.00,"max_depth":2}"#
+        );
+    }
+}
@@ -0,0 +1,1025 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::template::interpolate;
+use crate::{evaluate_in, is_name, parse_expressions, parse_line, Context, Error, ErrorCode, TrailingPolicy, Value};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Location {
+    pub path: PathBuf,
+    pub line: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.path.display(), self.line)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    pub location: Location,
+    pub text: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ScriptError {
+    pub location: Option<Location>,
+    pub error: Error,
+}
+
+impl ScriptError {
+    fn new(location: Option<&Location>, code: ErrorCode, message: impl Into<String>) -> Self {
+        ScriptError { location: location.cloned(), error: Error::new(code, message) }
+    }
+
+    fn at(location: &Location, error: Error) -> Self {
+        ScriptError { location: Some(location.clone()), error }
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            None => write!(f, "{}", self.error),
+            Some(location) => write!(f, "{}: {}", location, self.error),
+        }
+    }
+}
+
+// Strips a `;` comment, ignoring semicolons inside double quotes.
+pub fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (idx, char) in line.char_indices() {
+        match char {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..idx],
+            _ => {}
+        }
+    }
+    line
+}
+
+// Parses a double quoted string spanning the whole of `str`.
+pub fn parse_string(str: &str) -> Option<&str> {
+    let inner = str.strip_prefix('"')?.strip_suffix('"')?;
+    if inner.contains('"') {
+        None
+    } else {
+        Some(inner)
+    }
+}
+
+pub fn load(path: &Path) -> Result<Vec<Line>, ScriptError> {
+    let mut lines = Vec::new();
+    load_into(path, None, &mut Vec::new(), &mut lines)?;
+    Ok(lines)
+}
+
+// Splits text into lines of code, without comments and empty lines, the
+// first line of the text being numbered `first`. Lines ending with a
+// backslash continue on the next one, the joined line being located at its
+// first part.
+pub fn split_lines(path: &Path, text: &str, first: usize) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut continued: Option<(Location, String)> = None;
+    // The empty line added at the end ends a continuation left open.
+    for (idx, line) in text.lines().chain([""]).enumerate() {
+        let mut location = Location { path: path.to_path_buf(), line: first + idx };
+        let mut code = String::from(strip_comment(line).trim());
+        if let Some((start, mut previous)) = continued.take() {
+            previous.push(' ');
+            (location, code) = (start, previous + &code);
+        }
+        if let Some(rest) = code.strip_suffix('\\') {
+            continued = Some((location, String::from(rest.trim_end())));
+            continue;
+        }
+        let code = code.trim();
+        if !code.is_empty() {
+            lines.push(Line { location, text: String::from(code) });
+        }
+    }
+    lines
+}
+
+fn load_into(
+    path: &Path,
+    from: Option<&Location>,
+    stack: &mut Vec<PathBuf>,
+    lines: &mut Vec<Line>,
+) -> Result<(), ScriptError> {
+    let read_failed = |err| ScriptError::new(
+        from,
+        ErrorCode::ReadFailed,
+        format!("Cannot read '{}': {}.", path.display(), err),
+    );
+    let canonical = path.canonicalize().map_err(read_failed)?;
+    if let Some(start) = stack.iter().position(|included| *included == canonical) {
+        let cycle: Vec<_> = stack[start..].iter()
+            .chain([&canonical])
+            .map(|included| included.display().to_string())
+            .collect();
+        return Err(ScriptError::new(
+            from,
+            ErrorCode::IncludeCycle,
+            format!("Include cycle {}.", cycle.join(" -> ")),
+        ));
+    }
+    let text = fs::read_to_string(path).map_err(read_failed)?;
+
+    stack.push(canonical);
+    for Line { location, text: code } in split_lines(path, &text, 1) {
+        if let Some(rest) = code.strip_prefix("include").filter(|rest| rest.starts_with(char::is_whitespace)) {
+            let Some(included) = parse_string(rest.trim()) else {
+                return Err(ScriptError::new(
+                    Some(&location),
+                    ErrorCode::InvalidDirective,
+                    format!("Expected a quoted path after include, instead got '{}'.", rest.trim()),
+                ));
+            };
+            let included = path.parent().unwrap_or(Path::new("")).join(included);
+            load_into(&included, Some(&location), stack, lines)?;
+        } else {
+            lines.push(Line { location, text: code });
+        }
+    }
+    stack.pop();
+    Ok(())
+}
+
+#[derive(Debug)]
+enum Source<'a> {
+    Now,
+    Env(&'a str),
+    Seed,
+    Expression(&'a str),
+}
+
+#[derive(Debug)]
+enum Statement<'a> {
+    AssertEq {
+        line: &'a Line,
+        actual: Value,
+        expected: Value,
+    },
+    Capture {
+        line: &'a Line,
+        name: &'a str,
+        source: Source<'a>,
+    },
+    Expression(&'a Line),
+    If {
+        line: &'a Line,
+        condition: &'a str,
+        then: Vec<Statement<'a>>,
+        otherwise: Vec<Statement<'a>>,
+    },
+    For {
+        line: &'a Line,
+        name: &'a str,
+        from: &'a str,
+        to: &'a str,
+        body: Vec<Statement<'a>>,
+    },
+    Param {
+        line: &'a Line,
+        name: &'a str,
+        default: Option<&'a str>,
+    },
+    Print(&'a Line, &'a str),
+    Raise(&'a Line, &'a str),
+}
+
+impl<'a> Statement<'a> {
+    fn line(&self) -> &'a Line {
+        match self {
+            Statement::AssertEq { line, .. }
+            | Statement::Capture { line, .. }
+            | Statement::Expression(line)
+            | Statement::If { line, .. }
+            | Statement::For { line, .. }
+            | Statement::Param { line, .. }
+            | Statement::Print(line, _)
+            | Statement::Raise(line, _) => line,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Cached {
+    text: Vec<String>,
+    // Length of history and bound names after the statement.
+    history: usize,
+    names: HashMap<String, isize>,
+    output: Vec<String>,
+}
+
+// Results of a previous run, see `Runner::run_cached`.
+#[derive(Debug, Default)]
+pub struct Cache {
+    start: Option<(Vec<isize>, HashMap<String, isize>)>,
+    statements: Vec<Cached>,
+    history: Vec<isize>,
+    sources: Vec<Location>,
+}
+
+fn directive(line: &Line) -> Option<(&str, &str)> {
+    let rest = line.text.strip_prefix('@')?;
+    match rest.split_once(char::is_whitespace) {
+        None => Some((rest, "")),
+        Some((name, rest)) => Some((name, rest.trim())),
+    }
+}
+
+fn invalid(line: &Line, message: impl Into<String>) -> ScriptError {
+    ScriptError::new(Some(&line.location), ErrorCode::InvalidDirective, message)
+}
+
+// Statements up to the first `@else` or `@end`, which is kept so that the
+// enclosing block can check it.
+struct Block<'a> {
+    statements: Vec<Statement<'a>>,
+    end: Option<(&'a Line, &'a str)>,
+}
+
+fn parse_block<'a>(lines: &mut std::slice::Iter<'a, Line>, nested: bool) -> Result<Block<'a>, ScriptError> {
+    let mut statements = Vec::new();
+    while let Some(line) = lines.next() {
+        let Some((name, rest)) = directive(line) else {
+            statements.push(Statement::Expression(line));
+            continue;
+        };
+        match name {
+            "else" | "end" => return Ok(Block { statements, end: Some((line, name)) }),
+            "if" => {
+                if rest.is_empty() {
+                    return Err(invalid(line, "Expected a condition after @if."));
+                }
+                let Block { statements: then, mut end } = parse_block(lines, true)?;
+                let mut otherwise = Vec::new();
+                if let Some((_, "else")) = end {
+                    Block { statements: otherwise, end } = parse_block(lines, true)?;
+                }
+                match end {
+                    Some((_, "end")) => {}
+                    Some((other, _)) => return Err(invalid(other, "Unexpected @else after @else.")),
+                    None => return Err(invalid(line, "Expected @end to close this @if.")),
+                }
+                statements.push(Statement::If { line, condition: rest, then, otherwise });
+            }
+            "for" => {
+                let Some((name, from, to)) = parse_for(rest) else {
+                    return Err(invalid(line, "Expected '@for <name> in <expr>..<expr>'."));
+                };
+                let body = match parse_block(lines, true)? {
+                    Block { statements, end: Some((_, "end")) } => statements,
+                    Block { end: Some((other, _)), .. } => {
+                        return Err(invalid(other, "Unexpected @else in @for."));
+                    }
+                    Block { end: None, .. } => return Err(invalid(line, "Expected @end to close this @for.")),
+                };
+                statements.push(Statement::For { line, name, from, to, body });
+            }
+            "param" => {
+                let (name, default) = match rest.split_once(char::is_whitespace) {
+                    None => (rest, None),
+                    Some((name, rest)) => match rest.trim_start().strip_prefix("default ") {
+                        Some(default) => (name, Some(default.trim())),
+                        None => (name, Some("")),
+                    },
+                };
+                if !is_name(name) || default.is_some_and(str::is_empty) {
+                    return Err(invalid(line, "Expected '@param <name>' or '@param <name> default <expr>'."));
+                }
+                statements.push(Statement::Param { line, name, default });
+            }
+            "capture" => {
+                if nested {
+                    return Err(invalid(line, "Expected @capture outside of blocks."));
+                }
+                let (name, source) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                let source = match source.trim() {
+                    "" => return Err(invalid(line, "Expected '@capture <name> <now | env VAR | seed | expr>'.")),
+                    "now" => Source::Now,
+                    "seed" => Source::Seed,
+                    source => match source.strip_prefix("env ") {
+                        Some(var) => Source::Env(var.trim()),
+                        None => Source::Expression(source),
+                    },
+                };
+                if !is_name(name) {
+                    return Err(invalid(line, format!("Expected a name to capture into, instead got '{}'.", name)));
+                }
+                statements.push(Statement::Capture { line, name, source });
+            }
+            "print" => match parse_string(rest) {
+                Some(template) => statements.push(Statement::Print(line, template)),
+                None => return Err(invalid(line, "Expected a quoted template after @print.")),
+            },
+            "error" => match parse_string(rest) {
+                Some(message) => statements.push(Statement::Raise(line, message)),
+                None => return Err(invalid(line, "Expected a quoted message after @error.")),
+            },
+            "assert-eq" => match parse_expressions(rest.split_whitespace(), TrailingPolicy::NextExpression) {
+                Ok(values) if values.len() == 2 => {
+                    let [actual, expected] = <[Value; 2]>::try_from(values).unwrap();
+                    statements.push(Statement::AssertEq { line, actual, expected });
+                }
+                _ => return Err(invalid(line, "Expected '@assert-eq <expr> <expr>'.")),
+            },
+            _ => return Err(invalid(line, format!("Unknown directive '@{}'.", name))),
+        }
+    }
+    Ok(Block { statements, end: None })
+}
+
+fn parse(lines: &[Line]) -> Result<Vec<Statement<'_>>, ScriptError> {
+    match parse_block(&mut lines.iter(), false)? {
+        Block { statements, end: None } => Ok(statements),
+        Block { end: Some((line, name)), .. } => Err(invalid(line, format!("Unexpected @{} outside of a block.", name))),
+    }
+}
+
+// A name used on a line where neither an enclosing `@for` nor any `@param`
+// or `@capture` binds it. Names in `@print` templates are not checked.
+#[derive(Debug, PartialEq)]
+pub struct Unbound<'a> {
+    pub line: &'a Line,
+    pub name: String,
+    // Whether another line of the script binds the name.
+    pub bound_elsewhere: bool,
+}
+
+pub fn unbound_names(lines: &[Line]) -> Result<Vec<Unbound<'_>>, ScriptError> {
+    let statements = parse(lines)?;
+    let mut bound = HashSet::new();
+    collect_bound(&statements, &mut bound);
+    let mut scope: Vec<_> = statements.iter()
+        .filter_map(|statement| match statement {
+            Statement::Param { name, .. } | Statement::Capture { name, .. } => Some(*name),
+            _ => None,
+        })
+        .collect();
+    let mut unbound = Vec::new();
+    collect_unbound(&statements, &mut scope, &mut unbound);
+    Ok(unbound.into_iter()
+        .map(|(line, name)| Unbound { line, bound_elsewhere: bound.contains(name.as_str()), name })
+        .collect())
+}
+
+fn collect_bound<'a>(statements: &[Statement<'a>], bound: &mut HashSet<&'a str>) {
+    for statement in statements {
+        match statement {
+            Statement::Param { name, .. } | Statement::Capture { name, .. } => {
+                bound.insert(*name);
+            }
+            Statement::For { name, body, .. } => {
+                bound.insert(*name);
+                collect_bound(body, bound);
+            }
+            Statement::If { then, otherwise, .. } => {
+                collect_bound(then, bound);
+                collect_bound(otherwise, bound);
+            }
+            Statement::AssertEq { .. } | Statement::Expression(_) | Statement::Print(..) | Statement::Raise(..) => {}
+        }
+    }
+}
+
+fn collect_unbound<'a>(statements: &[Statement<'a>], scope: &mut Vec<&'a str>, unbound: &mut Vec<(&'a Line, String)>) {
+    for statement in statements {
+        let line = statement.line();
+        let mut check = |text: &str, scope: &[&str]| {
+            let Ok(value) = parse_line(text.split_whitespace()) else { return };
+            for name in value.names() {
+                if !scope.contains(&name) && !unbound.iter().any(|(other, known)| *other == line && known == name) {
+                    unbound.push((line, String::from(name)));
+                }
+            }
+        };
+        match statement {
+            Statement::Expression(line) => check(&line.text, scope),
+            Statement::If { condition, then, otherwise, .. } => {
+                check(condition, scope);
+                collect_unbound(then, scope, unbound);
+                collect_unbound(otherwise, scope, unbound);
+            }
+            Statement::For { name, from, to, body, .. } => {
+                check(from, scope);
+                check(to, scope);
+                scope.push(name);
+                collect_unbound(body, scope, unbound);
+                scope.pop();
+            }
+            Statement::AssertEq { actual, expected, .. } => {
+                check(&actual.to_string(), scope);
+                check(&expected.to_string(), scope);
+            }
+            Statement::Param { default: Some(default), .. } => check(default, scope),
+            Statement::Capture { source: Source::Expression(expr), .. } => check(expr, scope),
+            Statement::Param { .. } | Statement::Capture { .. } | Statement::Print(..) | Statement::Raise(..) => {}
+        }
+    }
+}
+
+// Splits `<name> in <expr>..<expr>` of a `@for` directive.
+fn parse_for(rest: &str) -> Option<(&str, &str, &str)> {
+    let (name, rest) = rest.split_once(char::is_whitespace)?;
+    let (from, to) = rest.trim_start().strip_prefix("in")?.split_once("..")?;
+    let (from, to) = (from.trim(), to.trim());
+    if is_name(name) && !from.is_empty() && !to.is_empty() {
+        Some((name, from, to))
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Runner {
+    pub history: Vec<isize>,
+    // Location of the line which produced each entry of history.
+    pub sources: Vec<Location>,
+    pub names: HashMap<String, isize>,
+    pub params: HashMap<String, isize>,
+    constants: HashSet<String>,
+    // Variables `@capture env` reads instead of those of the process.
+    pub environment: Option<HashMap<String, String>>,
+    // Lines which ran, each counted once however many times a loop ran it.
+    executed: HashSet<Location>,
+}
+
+impl Runner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Count of the lines run so far, the failing one included.
+    pub fn executed(&self) -> usize {
+        self.executed.len()
+    }
+
+    pub fn run(&mut self, lines: &[Line], output: &mut dyn FnMut(String)) -> Result<(), ScriptError> {
+        let (statements, headers) = self.prepare(lines)?;
+        self.execute(&statements[headers..], output)
+    }
+
+    // Like `run`, but reuses the results of the longest run of top-level
+    // statements unchanged since the run which filled the cache, as long as
+    // parameters and captures are unchanged too. Returns the count of reused
+    // statements.
+    pub fn run_cached(
+        &mut self,
+        lines: &[Line],
+        output: &mut dyn FnMut(String),
+        cache: &mut Cache,
+    ) -> Result<usize, ScriptError> {
+        let (statements, headers) = self.prepare(lines)?;
+        let statements = &statements[headers..];
+        let start = (self.history.clone(), self.names.clone());
+        if cache.start.as_ref() != Some(&start) {
+            *cache = Cache { start: Some(start), ..Cache::default() };
+        }
+
+        let mut texts = Vec::new();
+        let mut starts = statements.iter()
+            .map(|statement| lines.iter().position(|line| std::ptr::eq(line, statement.line())).unwrap())
+            .chain([lines.len()])
+            .peekable();
+        while let (Some(start), Some(end)) = (starts.next(), starts.peek()) {
+            texts.push(lines[start..*end].iter().map(|line| line.text.clone()).collect::<Vec<_>>());
+        }
+
+        let reused = cache.statements.iter().zip(&texts).take_while(|(cached, text)| cached.text == **text).count();
+        cache.statements.truncate(reused);
+        if let Some(last) = cache.statements.last() {
+            self.history = cache.history[..last.history].to_vec();
+            self.sources = cache.sources[..last.history].to_vec();
+            self.names = last.names.clone();
+        }
+        for line in cache.statements.iter().flat_map(|cached| &cached.output) {
+            output(line.clone());
+        }
+        for (statement, text) in statements[reused..].iter().zip(texts.into_iter().skip(reused)) {
+            let mut printed = Vec::new();
+            let result = self.execute(std::slice::from_ref(statement), &mut |line| {
+                printed.push(line.clone());
+                output(line);
+            });
+            (cache.history, cache.sources) = (self.history.clone(), self.sources.clone());
+            result?;
+            cache.statements.push(Cached { text, history: self.history.len(), names: self.names.clone(), output: printed });
+        }
+        (cache.history, cache.sources) = (self.history.clone(), self.sources.clone());
+        Ok(reused)
+    }
+
+    // Parses the script, binds its parameters and evaluates its captures,
+    // returning the statements along with the count of parameter headers.
+    fn prepare<'a>(&mut self, lines: &'a [Line]) -> Result<(Vec<Statement<'a>>, usize), ScriptError> {
+        let statements = parse(lines)?;
+        let headers = statements.iter()
+            .take_while(|statement| matches!(statement, Statement::Param { .. }))
+            .count();
+        self.bind_params(&statements[..headers])?;
+        for statement in &statements {
+            if let Statement::Capture { line, name, source } = statement {
+                self.capture(line, name, source)?;
+            }
+        }
+        Ok((statements, headers))
+    }
+
+    fn capture(&mut self, line: &Line, name: &str, source: &Source) -> Result<(), ScriptError> {
+        let failed = |message: String| ScriptError::new(Some(&line.location), ErrorCode::CaptureFailed, message);
+        let int = match source {
+            Source::Now => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)
+                    .map_err(|err| failed(format!("Cannot read the current time: {}.", err)))?;
+                now.as_secs() as isize
+            }
+            Source::Env(var) => {
+                let value = match &self.environment {
+                    Some(environment) => environment.get(*var).cloned(),
+                    None => env::var(var).ok(),
+                };
+                let value = value.ok_or_else(|| failed(format!("Environment variable '{}' is not set.", var)))?;
+                value.trim().parse().map_err(|_| failed(format!(
+                    "Expected an integer in environment variable '{}', instead got '{}'.",
+                    var,
+                    value
+                )))?
+            }
+            Source::Seed => (RandomState::new().build_hasher().finish() >> 1) as isize,
+            Source::Expression(expr) => self.evaluate(line, expr)?,
+        };
+        if !self.constants.insert(String::from(name)) || self.names.contains_key(name) {
+            return Err(invalid(line, format!("Cannot capture into '{}', the name is already bound.", name)));
+        }
+        self.names.insert(String::from(name), int);
+        Ok(())
+    }
+
+    // Checks all declared parameters before binding any of them, so that every
+    // missing or unknown parameter is reported in a single error.
+    fn bind_params(&mut self, headers: &[Statement]) -> Result<(), ScriptError> {
+        let params: Vec<_> = headers.iter()
+            .filter_map(|header| match header {
+                Statement::Param { line, name, default } => Some((*line, *name, *default)),
+                _ => None,
+            })
+            .collect();
+        let mut problems: Vec<_> = params.iter()
+            .filter(|(_, name, default)| default.is_none() && !self.params.contains_key(*name))
+            .map(|(_, name, _)| format!("Missing parameter '{}'.", name))
+            .collect();
+        let mut unknown: Vec<_> = self.params.keys()
+            .filter(|param| !params.iter().any(|(_, name, _)| name == param))
+            .collect();
+        unknown.sort();
+        problems.extend(unknown.into_iter().map(|param| format!("Unknown parameter '{}'.", param)));
+        if !problems.is_empty() {
+            let expected = if params.is_empty() {
+                String::from("The script declares no parameters.")
+            } else {
+                let expected: Vec<_> = params.iter()
+                    .map(|(_, name, default)| match default {
+                        None => String::from(*name),
+                        Some(default) => format!("{} (default {})", name, default),
+                    })
+                    .collect();
+                format!("Expected parameters: {}.", expected.join(", "))
+            };
+            return Err(ScriptError::new(
+                None,
+                ErrorCode::InvalidParameters,
+                format!("{} {}", problems.join(" "), expected),
+            ));
+        }
+
+        for (line, name, default) in params {
+            let int = match (self.params.get(name), default) {
+                (Some(int), _) => *int,
+                (None, Some(default)) => self.evaluate(line, default)?,
+                (None, None) => unreachable!("missing parameters are reported above"),
+            };
+            self.names.insert(String::from(name), int);
+        }
+        Ok(())
+    }
+
+    fn evaluate(&self, line: &Line, text: &str) -> Result<isize, ScriptError> {
+        self.evaluate_text(text).map_err(|err| ScriptError::at(&line.location, err))
+    }
+
+    fn evaluate_text(&self, text: &str) -> Result<isize, Error> {
+        parse_line(text.split_whitespace())
+            .and_then(|value| evaluate_in(&value, &Context::new(&self.history, &self.names)))
+    }
+
+    fn execute(&mut self, statements: &[Statement], output: &mut dyn FnMut(String)) -> Result<(), ScriptError> {
+        for statement in statements {
+            self.executed.insert(statement.line().location.clone());
+            match statement {
+                Statement::Expression(line) => {
+                    let result = self.evaluate(line, &line.text)?;
+                    self.history.push(result);
+                    self.sources.push(line.location.clone());
+                    output(result.to_string());
+                }
+                Statement::If { line, condition, then, otherwise } => {
+                    if self.evaluate(line, condition)? != 0 {
+                        self.execute(then, output)?;
+                    } else {
+                        self.execute(otherwise, output)?;
+                    }
+                }
+                Statement::Capture { .. } => {}
+                Statement::For { line, name, from, to, body } => {
+                    if self.constants.contains(*name) {
+                        return Err(invalid(line, format!("Cannot rebind '{}', it is a captured constant.", name)));
+                    }
+                    let (from, to) = (self.evaluate(line, from)?, self.evaluate(line, to)?);
+                    let shadowed = self.names.get(*name).copied();
+                    for int in from..=to {
+                        self.names.insert(String::from(*name), int);
+                        self.execute(body, output)?;
+                    }
+                    match shadowed {
+                        None => self.names.remove(*name),
+                        Some(int) => self.names.insert(String::from(*name), int),
+                    };
+                }
+                Statement::Param { line, .. } => {
+                    return Err(invalid(line, "Expected @param before any other line."));
+                }
+                Statement::Print(line, template) => {
+                    let text = interpolate(template, |expr| self.evaluate_text(expr))
+                        .map_err(|err| ScriptError::at(&line.location, err))?;
+                    output(text);
+                }
+                Statement::Raise(line, message) => {
+                    return Err(ScriptError::new(Some(&line.location), ErrorCode::Raised, *message));
+                }
+                Statement::AssertEq { line, actual, expected } => {
+                    let context = Context::new(&self.history, &self.names);
+                    let evaluate = |value| evaluate_in(value, &context).map_err(|err| ScriptError::at(&line.location, err));
+                    let (got, wanted) = (evaluate(actual)?, evaluate(expected)?);
+                    if got != wanted {
+                        return Err(ScriptError::new(
+                            Some(&line.location),
+                            ErrorCode::AssertionFailed,
+                            format!("Expected '{}' to be {}, instead got {}.", actual, wanted, got),
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use crate::script::*;
+    use crate::{Error, ErrorCode};
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("cwh-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+
+        fn write(&self, name: &str, text: &str) -> PathBuf {
+            let path = self.0.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, text).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn run(path: &Path) -> Result<Vec<String>, ScriptError> {
+        let mut output = Vec::new();
+        Runner::new().run(&load(path)?, &mut |line| output.push(line))?;
+        Ok(output)
+    }
+
+    #[test]
+    fn cache() {
+        let dir = TempDir::new("cache");
+        let script = "@param n default 2\n+ n 1\n@for i in 1..2\n* i $0\n@end\n";
+        let path = dir.write("sheet.cwh", &format!("{}@print \"{{$2}}\"\n", script));
+        let mut cache = Cache::default();
+        let mut run_cached = |params: &[(&str, isize)]| {
+            let mut runner = Runner::new();
+            runner.params = params.iter().map(|(name, int)| (String::from(*name), *int)).collect();
+            let mut output = Vec::new();
+            let reused = runner.run_cached(&load(&path).unwrap(), &mut |line| output.push(line), &mut cache);
+            (reused.map_err(|err| err.error.code), output.join(" "), runner.history)
+        };
+        assert_eq!(run_cached(&[]), (Ok(0), String::from("3 3 6 6"), vec![3, 3, 6]));
+        assert_eq!(run_cached(&[]), (Ok(3), String::from("3 3 6 6"), vec![3, 3, 6]));
+
+        dir.write("sheet.cwh", &format!("{}; edited\n@print \"{{+ $2 1}}\"\n", script));
+        assert_eq!(run_cached(&[]), (Ok(2), String::from("3 3 6 7"), vec![3, 3, 6]));
+        dir.write("sheet.cwh", &format!("{}* 3 $0\n/ 1 0\n", script));
+        assert_eq!(run_cached(&[]), (Err(ErrorCode::DivisionByZero), String::from("3 3 6 9"), vec![3, 3, 6, 9]));
+        assert_eq!(run_cached(&[]), (Err(ErrorCode::DivisionByZero), String::from("3 3 6 9"), vec![3, 3, 6, 9]));
+        assert_eq!(run_cached(&[("n", 1)]).0, Err(ErrorCode::DivisionByZero));
+        dir.write("sheet.cwh", script);
+        assert_eq!(run_cached(&[("n", 1)]), (Ok(2), String::from("2 2 4"), vec![2, 2, 4]));
+    }
+
+    #[test]
+    fn executed() {
+        let text = "1\n@for i in 1..3\n+ i $0\n@end\n@print \"{$3}\"\n; done\n@assert-eq $0 1\n/ 1 0\n* 2 2\n";
+        let mut runner = Runner::new();
+        let result = runner.run(&split_lines(Path::new("sheet.cwh"), text, 1), &mut |_| {});
+        assert_eq!(result.unwrap_err().error.code, ErrorCode::DivisionByZero);
+        assert_eq!(runner.history.len(), 4);
+        assert_eq!(runner.executed(), 6);
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(strip_comment("+ 3 2 ; five"), "+ 3 2 ");
+        assert_eq!(strip_comment(r#"include "a;b.cwh" ; c"#), r#"include "a;b.cwh" "#);
+        assert_eq!(strip_comment("; only a comment"), "");
+    }
+
+    #[test]
+    fn continuation() {
+        let dir = TempDir::new("continuation");
+        let path = dir.write("main.cwh", "+ \\\n    * 2 3 \\ ; six\n    4\n@print \"{$0}\" \\");
+        assert_eq!(load(&path).unwrap()[0], Line {
+            location: Location { path: path.clone(), line: 1 },
+            text: String::from("+ * 2 3 4"),
+        });
+        assert_eq!(run(&path), Ok(vec![String::from("10"), String::from("10")]));
+    }
+
+    #[test]
+    fn sequence() {
+        let dir = TempDir::new("sequence");
+        let main = dir.write("main.cwh", "; totals\n+ 3 2\n\n* $0 2 ; doubled\n");
+        assert_eq!(run(&main), Ok(vec![String::from("5"), String::from("10")]));
+    }
+
+    #[test]
+    fn includes() {
+        let dir = TempDir::new("includes");
+        dir.write("lib/rates.cwh", "20\n");
+        dir.write("lib/base.cwh", "include \"rates.cwh\"\n100\n");
+        let main = dir.write("main.cwh", "include \"lib/base.cwh\"\n/ * $1 $0 100\n");
+        assert_eq!(run(&main), Ok(vec![String::from("20"), String::from("100"), String::from("20")]));
+    }
+
+    #[test]
+    fn errors() {
+        let dir = TempDir::new("errors");
+        let main = dir.write("main.cwh", "1\n/ $0 0\n");
+        let err = run(&main).unwrap_err();
+        assert_eq!(err.error.code, ErrorCode::DivisionByZero);
+        assert_eq!(err.location, Some(Location { path: main.clone(), line: 2 }));
+
+        let main = dir.write("missing.cwh", "include \"nowhere.cwh\"\n");
+        assert_eq!(run(&main).unwrap_err().error.code, ErrorCode::ReadFailed);
+
+        let main = dir.write("unquoted.cwh", "include nowhere.cwh\n");
+        assert_eq!(run(&main).unwrap_err().error.code, ErrorCode::InvalidDirective);
+    }
+
+    #[test]
+    fn conditions() {
+        let dir = TempDir::new("conditions");
+        let main = dir.write("main.cwh", r#"
+            2
+            @if - $0 1
+                100
+                @if 0
+                    @error "Unreachable."
+                @end
+            @else
+                200
+            @end
+            @if - $0 2
+                300
+            @else
+                400
+            @end
+        "#);
+        assert_eq!(run(&main), Ok(vec![String::from("2"), String::from("100"), String::from("400")]));
+    }
+
+    #[test]
+    fn loops() {
+        let dir = TempDir::new("loops");
+        let main = dir.write("main.cwh", r#"
+            3
+            @for i in 1..$0
+                * i i
+                @for j in 0..- i 2
+                    + * 10 i j
+                @end
+            @end
+            @for i in 1..0
+                @error "Empty ranges run no lines."
+            @end
+        "#);
+        let expected = ["3", "1", "4", "20", "9", "30", "31"];
+        assert_eq!(run(&main), Ok(expected.map(String::from).to_vec()));
+
+        let main = dir.write("unbound.cwh", "@for i in 1..2\ni\n@end\ni\n");
+        let err = run(&main).unwrap_err();
+        assert_eq!(err.error.code, ErrorCode::UnknownName);
+        assert_eq!(err.location.unwrap().line, 4);
+    }
+
+    #[test]
+    fn params() {
+        let dir = TempDir::new("params");
+        let main = dir.write("main.cwh", r#"
+            @param rate default 20 ; percent
+            @param amount
+            @param fee default / amount 100
+            + fee / * amount rate 100
+        "#);
+        let run_with = |params: &[(&str, isize)]| {
+            let mut runner = Runner::new();
+            runner.params = params.iter().map(|(name, int)| (String::from(*name), *int)).collect();
+            let mut output = Vec::new();
+            runner.run(&load(&main)?, &mut |line| output.push(line)).map(|_| output)
+        };
+        assert_eq!(run_with(&[("amount", 300)]), Ok(vec![String::from("63")]));
+        assert_eq!(run_with(&[("amount", 300), ("rate", 10), ("fee", 0)]), Ok(vec![String::from("30")]));
+
+        let err = run_with(&[("rate", 10), ("years", 2), ("months", 3)]).unwrap_err();
+        assert_eq!(err.error, Error::new(
+            ErrorCode::InvalidParameters,
+            "Missing parameter 'amount'. Unknown parameter 'months'. Unknown parameter 'years'. \
+             Expected parameters: rate (default 20), amount, fee (default / amount 100).",
+        ));
+
+        let main = dir.write("late.cwh", "1\n@param rate\n");
+        assert_eq!(run(&main).unwrap_err().error.code, ErrorCode::InvalidDirective);
+    }
+
+    #[test]
+    fn assert_eq() {
+        let dir = TempDir::new("assert");
+        let main = dir.write("main.cwh", "@for i in 1..2\n* i 2\n@assert-eq - i 1 * i 2\n@end\n");
+        let err = run(&main).unwrap_err();
+        assert_eq!(err.error.code, ErrorCode::AssertionFailed);
+        assert_eq!(err.error.message, "Expected '- i 1' to be 2, instead got 0.");
+        assert_eq!(err.location.unwrap().line, 3);
+
+        let main = dir.write("passing.cwh", "+ 3 2\n@assert-eq $0 5\n@assert-eq - 0 $0 -5\n");
+        assert_eq!(run(&main), Ok(vec![String::from("5")]));
+        let main = dir.write("failing.cwh", "@assert-eq / 1 0 0\n");
+        assert_eq!(run(&main).unwrap_err().error.code, ErrorCode::DivisionByZero);
+    }
+
+    #[test]
+    fn print() {
+        let dir = TempDir::new("print");
+        let main = dir.write("main.cwh", r#"
+            1234567
+            @print "Total: {$0:,} EUR ; not a comment"
+            @for i in 14..15
+                @print "{i:>3}|{i:02x}|{* i i:b}"
+            @end
+        "#);
+        let expected = ["1234567", "Total: 1,234,567 EUR ; not a comment", " 14|0e|11000100", " 15|0f|11100001"];
+        assert_eq!(run(&main), Ok(expected.map(String::from).to_vec()));
+
+        let main = dir.write("error.cwh", "@print \"{/ 1 0}\"\n");
+        let err = run(&main).unwrap_err();
+        assert_eq!(err.error.code, ErrorCode::DivisionByZero);
+        assert_eq!(err.location.unwrap().line, 1);
+    }
+
+    #[test]
+    fn captures() {
+        let dir = TempDir::new("captures");
+        let main = dir.write("main.cwh", r#"
+            @param base default 10
+            * start 0
+            @capture start now
+            @capture seed seed
+            @capture path_len env CWH_TEST_CAPTURE
+            @capture double * 2 base
+            + double path_len
+            sgn seed
+        "#);
+        let mut runner = Runner::new();
+        runner.environment = Some(HashMap::from([(String::from("CWH_TEST_CAPTURE"), String::from("5"))]));
+        let mut output = Vec::new();
+        runner.run(&load(&main).unwrap(), &mut |line| output.push(line)).unwrap();
+        assert_eq!(output[0], "0");
+        assert_eq!(output[1], "25");
+        assert!(output[2] == "0" || output[2] == "1");
+
+        let main = dir.write("unset.cwh", "@capture x env CWH_TEST_CAPTURE_UNSET\n");
+        assert_eq!(run(&main).unwrap_err().error.code, ErrorCode::CaptureFailed);
+
+        let main = dir.write("rebind.cwh", "@capture i 3\n@for i in 1..2\ni\n@end\n");
+        let err = run(&main).unwrap_err();
+        assert_eq!(err.error.code, ErrorCode::InvalidDirective);
+        assert_eq!(err.location.unwrap().line, 2);
+
+        let main = dir.write("twice.cwh", "@capture a 1\n@capture a 2\n");
+        assert_eq!(run(&main).unwrap_err().location.unwrap().line, 2);
+
+        let main = dir.write("nested.cwh", "@if 1\n@capture a 1\n@end\n");
+        assert_eq!(run(&main).unwrap_err().location.unwrap().line, 2);
+    }
+
+    #[test]
+    fn raised() {
+        let dir = TempDir::new("raised");
+        let main = dir.write("main.cwh", "-5
+@if - sgn $0 1
+@error \"The rate has to be positive.\"
+@end
+");
+        let err = run(&main).unwrap_err();
+        assert_eq!(err.error, Error::new(ErrorCode::Raised, "The rate has to be positive."));
+        assert_eq!(err.location, Some(Location { path: main, line: 3 }));
+    }
+
+    #[test]
+    fn invalid_blocks() {
+        let dir = TempDir::new("invalid-blocks");
+        for (text, line) in [
+            ("@if 1\n1\n", 1),
+            ("1\n@end\n", 2),
+            ("@if 1\n@else\n@else\n@end\n", 3),
+            ("@if\n@end\n", 1),
+            ("@error unquoted\n", 1),
+            ("@unknown\n", 1),
+            ("@for i in 1..2\n", 1),
+            ("@for i from 1..2\n@end\n", 1),
+            ("@for abs in 1..2\n@end\n", 1),
+            ("@for i in 1..2\n@else\n@end\n", 2),
+            ("@param 2x\n", 1),
+            ("@param rate 20\n", 1),
+            ("@assert-eq 1\n", 1),
+            ("@assert-eq 1 2 3\n", 1),
+        ] {
+            let main = dir.write("main.cwh", text);
+            let err = run(&main).unwrap_err();
+            assert_eq!(err.error.code, ErrorCode::InvalidDirective, "{}", text);
+            assert_eq!(err.location.unwrap().line, line, "{}", text);
+        }
+    }
+
+    #[test]
+    fn cycles() {
+        let dir = TempDir::new("cycles");
+        dir.write("a.cwh", "include \"b.cwh\"\n");
+        dir.write("b.cwh", "1\ninclude \"a.cwh\"\n");
+        let main = dir.write("main.cwh", "include \"a.cwh\"\n");
+        let err = run(&main).unwrap_err();
+        assert_eq!(err.error.code, ErrorCode::IncludeCycle);
+        assert_eq!(err.location, Some(Location { path: dir.0.join("b.cwh"), line: 2 }));
+
+        dir.write("twice.cwh", "include \"b2.cwh\"\ninclude \"b2.cwh\"\n");
+        dir.write("b2.cwh", "7\n");
+        assert_eq!(run(&dir.0.join("twice.cwh")), Ok(vec![String::from("7"), String::from("7")]));
+    }
+
+    #[test]
+    fn unbound() {
+        let dir = TempDir::new("unbound");
+        let path = dir.write("main.cwh", "@param rate default 2\n* rate total\n@for i in 1..3\n+ i j\n@end\n\
+            @capture start now\n- start i\n@for j in 1..i\n@end\n");
+        let lines = load(&path).unwrap();
+        let unbound: Vec<_> = unbound_names(&lines).unwrap().into_iter()
+            .map(|unbound| (unbound.line.location.line, unbound.name, unbound.bound_elsewhere))
+            .collect();
+        assert_eq!(unbound, [
+            (2, String::from("total"), false),
+            (4, String::from("j"), true),
+            (7, String::from("i"), true),
+            (8, String::from("i"), true),
+        ]);
+    }
+}
@@ -0,0 +1,474 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::{evaluate_value, parse_line, Error, ErrorCode};
+
+pub const HEADER: &str = "cwh-session";
+pub const VERSION: u32 = 2;
+pub const MAGIC: &[u8; 4] = b"CWHS";
+pub const BINARY_VERSION: u32 = 2;
+pub const BINARY_EXTENSION: &str = "cwhs";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Entry {
+    pub input: String,
+    pub tags: BTreeSet<String>,
+    pub note: Option<String>,
+}
+
+// History values are kept apart from the rest of the entries so that they
+// can be borrowed as a slice for evaluation.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Session {
+    history: Vec<isize>,
+    entries: Vec<Entry>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn history(&self) -> &[isize] {
+        &self.history
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+
+    pub fn push(&mut self, input: String, value: isize) {
+        self.history.push(value);
+        self.entries.push(Entry { input, tags: BTreeSet::new(), note: None });
+    }
+
+    pub fn entry_mut(&mut self, idx: usize) -> Option<&mut Entry> {
+        self.entries.get_mut(idx)
+    }
+
+    pub fn tagged(&self, tag: &str) -> Vec<usize> {
+        (0..self.len()).filter(|idx| self.entries[*idx].tags.contains(tag)).collect()
+    }
+
+    // Copies the given entries along with every entry they refer to, directly
+    // or not, renumbering their references.
+    pub fn select(&self, indices: &[usize]) -> Result<Session, Error> {
+        let mut kept = vec![false; self.len()];
+        let mut pending = indices.to_vec();
+        let mut values = vec![None; self.len()];
+        while let Some(idx) = pending.pop() {
+            if std::mem::replace(&mut kept[idx], true) {
+                continue;
+            }
+            let value = parse_line(self.entries[idx].input.split_whitespace())?;
+            pending.extend(value.variables().into_iter().filter(|other| *other < idx));
+            values[idx] = Some(value);
+        }
+        let positions: Vec<_> = kept.iter()
+            .scan(0, |position, kept| {
+                *position += usize::from(*kept);
+                Some(*position - usize::from(*kept))
+            })
+            .collect();
+        let mut session = Session::new();
+        for (idx, value) in values.into_iter().enumerate() {
+            if let Some(value) = value {
+                session.history.push(self.history[idx]);
+                session.entries.push(Entry {
+                    input: value.map_variables(&|other| positions[other]).to_string(),
+                    ..self.entries[idx].clone()
+                });
+            }
+        }
+        Ok(session)
+    }
+
+    // Copies the first `len` entries.
+    pub fn prefix(&self, len: usize) -> Session {
+        Session {
+            history: self.history[..len].to_vec(),
+            entries: self.entries[..len].to_vec(),
+        }
+    }
+
+    // Index of the first entry whose input or result differs between both sessions.
+    pub fn divergence(&self, other: &Session) -> Option<usize> {
+        (0..self.len().max(other.len())).find(|idx| self.entry(*idx) != other.entry(*idx))
+    }
+
+    pub fn entry(&self, idx: usize) -> Option<(&str, isize)> {
+        Some((self.entries.get(idx)?.input.as_str(), *self.history.get(idx)?))
+    }
+
+    // Re-evaluates every entry after `idx` from its input, as if entry `idx`
+    // had resulted in `value`, returning the new history.
+    pub fn recompute(&self, idx: usize, value: isize) -> Result<Vec<isize>, Error> {
+        let mut history = self.history[..idx].to_vec();
+        history.push(value);
+        for entry in &self.entries[idx + 1..] {
+            let result = evaluate_value(&parse_line(entry.input.split_whitespace())?, &history)?;
+            history.push(result);
+        }
+        Ok(history)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{} {}\n", HEADER, VERSION);
+        for (entry, value) in self.entries.iter().zip(&self.history) {
+            text.push_str(&format!("{}\t{}", entry.input, value));
+            if !entry.tags.is_empty() || entry.note.is_some() {
+                text.push('\t');
+                text.push_str(&entry.tags.iter().map(String::as_str).collect::<Vec<_>>().join(","));
+            }
+            if let Some(note) = &entry.note {
+                text.push('\t');
+                text.push_str(&escape(note));
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    // A script evaluating every input and asserting its result, notes
+    // becoming comments, for `cwh run` to check that results do not change.
+    pub fn to_tests(&self) -> String {
+        let mut script = String::new();
+        for (idx, (entry, value)) in self.entries.iter().zip(&self.history).enumerate() {
+            match &entry.note {
+                Some(note) => script.push_str(&format!("{} ; {}\n", entry.input, note.replace('\n', " "))),
+                None => script.push_str(&format!("{}\n", entry.input)),
+            }
+            script.push_str(&format!("@assert-eq ${} {}\n", idx, value));
+        }
+        script
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, Error> {
+        let mut lines = text.lines().enumerate();
+        let version = match text.lines().next().and_then(|line| line.strip_prefix(HEADER)) {
+            None => 0,
+            Some(version) => {
+                lines.next();
+                version.trim().parse().map_err(|_| {
+                    Error::new(ErrorCode::InvalidSession, format!("Invalid session header version '{}'.", version.trim()))
+                })?
+            }
+        };
+        match version {
+            0 => Self::migrate_inputs(lines),
+            // Version 1 has neither tags nor notes.
+            1 | VERSION => {
+                let mut session = Session::new();
+                for (idx, line) in lines.filter(|(_, line)| !line.is_empty()) {
+                    let mut fields = line.split('\t');
+                    let entry = match (fields.next(), fields.next(), fields.next(), fields.next(), fields.next()) {
+                        (Some(input), Some(value), None, None, None) => Some((input, value, "", None)),
+                        (Some(input), Some(value), Some(tags), note, None) if version == VERSION => {
+                            Some((input, value, tags, note))
+                        }
+                        _ => None,
+                    };
+                    let entry = entry.and_then(|(input, value, tags, note)| Some((input, value.parse().ok()?, tags, note)));
+                    let Some((input, value, tags, note)) = entry else {
+                        return Err(Error::new(
+                            ErrorCode::InvalidSession,
+                            format!("Expected '<input>\\t<result>[\\t<tags>[\\t<note>]]' on line {}.", idx + 1),
+                        ));
+                    };
+                    session.push(String::from(input), value);
+                    let entry = session.entries.last_mut().unwrap();
+                    entry.tags = tags.split(',').filter(|tag| !tag.is_empty()).map(String::from).collect();
+                    entry.note = note.map(unescape);
+                }
+                Ok(session)
+            }
+            _ => Err(Error::new(
+                ErrorCode::UnsupportedVersion,
+                format!("Session format version {} is newer than the supported version {}.", version, VERSION),
+            )),
+        }
+    }
+
+    // The binary format is the magic, the version and the entry count followed
+    // by every entry as a length-prefixed input, its result, the count of its
+    // tags followed by every length-prefixed tag, and its length-prefixed
+    // note, empty when there is none, all integers being little endian.
+    // Version 1 has neither tags nor notes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::from(*MAGIC);
+        bytes.extend(BINARY_VERSION.to_le_bytes());
+        bytes.extend((self.len() as u64).to_le_bytes());
+        for (entry, value) in self.entries.iter().zip(&self.history) {
+            bytes.extend((entry.input.len() as u32).to_le_bytes());
+            bytes.extend(entry.input.as_bytes());
+            bytes.extend((*value as i64).to_le_bytes());
+            bytes.extend((entry.tags.len() as u32).to_le_bytes());
+            for tag in &entry.tags {
+                bytes.extend((tag.len() as u32).to_le_bytes());
+                bytes.extend(tag.as_bytes());
+            }
+            let note = entry.note.as_deref().unwrap_or_default();
+            bytes.extend((note.len() as u32).to_le_bytes());
+            bytes.extend(note.as_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader { bytes, position: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(Error::new(ErrorCode::InvalidSession, "Missing binary session header."));
+        }
+        let version = u32::from_le_bytes(reader.array()?);
+        if version != 1 && version != BINARY_VERSION {
+            return Err(Error::new(
+                ErrorCode::UnsupportedVersion,
+                format!("Binary session version {} is not the supported version {}.", version, BINARY_VERSION),
+            ));
+        }
+        let count = u64::from_le_bytes(reader.array()?);
+        let mut session = Session::new();
+        for _ in 0..count {
+            let input = reader.string()?;
+            let value = isize::try_from(i64::from_le_bytes(reader.array()?))
+                .map_err(|_| Error::new(ErrorCode::InvalidSession, "Session result does not fit an integer."))?;
+            session.push(input, value);
+            if version == BINARY_VERSION {
+                for _ in 0..u32::from_le_bytes(reader.array()?) {
+                    let tag = reader.string()?;
+                    session.entries.last_mut().unwrap().tags.insert(tag);
+                }
+                let note = reader.string()?;
+                session.entries.last_mut().unwrap().note = Some(note).filter(|note| !note.is_empty());
+            }
+        }
+        if reader.position != bytes.len() {
+            return Err(Error::new(ErrorCode::InvalidSession, "Unexpected data after the last session entry."));
+        }
+        Ok(session)
+    }
+
+    // Version 0 files are plain lists of inputs, their results are recomputed.
+    fn migrate_inputs<'a>(lines: impl Iterator<Item = (usize, &'a str)>) -> Result<Self, Error> {
+        let mut session = Session::new();
+        for (idx, line) in lines.filter(|(_, line)| !line.trim().is_empty()) {
+            let failed = |err: Error| {
+                Error::new(ErrorCode::InvalidSession, format!("Cannot migrate line {}: {}", idx + 1, err))
+            };
+            let value = parse_line(line.split_whitespace()).map_err(failed)?;
+            let result = evaluate_value(&value, session.history()).map_err(failed)?;
+            session.push(value.to_string(), result);
+        }
+        Ok(session)
+    }
+
+    // Paths with the binary extension are written in the binary format.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let bytes = match path.extension() {
+            Some(extension) if extension == BINARY_EXTENSION => self.to_bytes(),
+            _ => self.to_text().into_bytes(),
+        };
+        fs::write(path, bytes).map_err(|err| {
+            Error::new(ErrorCode::WriteFailed, format!("Cannot write '{}': {}.", path.display(), err))
+        })
+    }
+
+    // The format is recognized by the content, whatever the extension.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let bytes = fs::read(path).map_err(|err| {
+            Error::new(ErrorCode::ReadFailed, format!("Cannot read '{}': {}.", path.display(), err))
+        })?;
+        if bytes.starts_with(MAGIC) {
+            return Session::from_bytes(&bytes);
+        }
+        let text = String::from_utf8(bytes)
+            .map_err(|_| Error::new(ErrorCode::InvalidSession, "Session file is not valid UTF-8."))?;
+        Session::from_text(&text)
+    }
+}
+
+// Notes are free text, their tabs, line breaks and backslashes are escaped
+// in the text format.
+fn escape(note: &str) -> String {
+    note.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+fn unescape(note: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = note.chars();
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => match chars.next() {
+                Some('t') => unescaped.push('\t'),
+                Some('n') => unescaped.push('\n'),
+                Some(other) => unescaped.push(other),
+                None => unescaped.push('\\'),
+            },
+            char => unescaped.push(char),
+        }
+    }
+    unescaped
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let bytes = self.bytes.get(self.position..self.position.saturating_add(len))
+            .ok_or_else(|| Error::new(ErrorCode::InvalidSession, "Binary session ends unexpectedly."))?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        let len = u32::from_le_bytes(self.array()?) as usize;
+        std::str::from_utf8(self.take(len)?)
+            .map(String::from)
+            .map_err(|_| Error::new(ErrorCode::InvalidSession, "Session string is not valid UTF-8."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::session::Session;
+    use crate::ErrorCode;
+
+    fn session() -> Session {
+        let mut session = Session::new();
+        session.push(String::from("+ 3 2"), 5);
+        session.push(String::from("* $0 2"), 10);
+        session
+    }
+
+    #[test]
+    fn round_trip() {
+        let text = session().to_text();
+        assert_eq!(text, "cwh-session 2\n+ 3 2\t5\n* $0 2\t10\n");
+        assert_eq!(Session::from_text(&text), Ok(session()));
+        assert_eq!(Session::from_text("cwh-session 1\n+ 3 2\t5\n* $0 2\t10\n"), Ok(session()));
+        assert_eq!(Session::from_text("cwh-session 1\n"), Ok(Session::new()));
+    }
+
+    #[test]
+    fn binary() {
+        let bytes = session().to_bytes();
+        assert_eq!(&bytes[..8], b"CWHS\x02\0\0\0");
+        assert_eq!(Session::from_bytes(&bytes), Ok(session()));
+        assert_eq!(Session::from_bytes(&Session::new().to_bytes()), Ok(Session::new()));
+
+        let code = |bytes: &[u8]| Session::from_bytes(bytes).unwrap_err().code;
+        assert_eq!(code(&bytes[..bytes.len() - 1]), ErrorCode::InvalidSession);
+        assert_eq!(code(&[&bytes[..], b"x"].concat()), ErrorCode::InvalidSession);
+        assert_eq!(code(b"CWHS\x03\0\0\0"), ErrorCode::UnsupportedVersion);
+        assert_eq!(code(b"cwh-session 1\n"), ErrorCode::InvalidSession);
+    }
+
+    #[test]
+    fn files() {
+        let dir = std::env::temp_dir();
+        for name in ["text", "binary.cwhs"] {
+            let path = dir.join(format!("cwh-session-{}-{}", std::process::id(), name));
+            session().save(&path).unwrap();
+            assert_eq!(std::fs::read(&path).unwrap().starts_with(b"CWHS"), name.ends_with(".cwhs"));
+            assert_eq!(Session::load(&path), Ok(session()));
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn tags() {
+        let mut session = session();
+        session.push(String::from("7"), 7);
+        session.push(String::from("- $2 $0"), 2);
+        session.entry_mut(3).unwrap().tags.extend([String::from("taxes"), String::from("q1")]);
+        session.entry_mut(0).unwrap().tags.insert(String::from("q1"));
+        assert_eq!(session.tagged("q1"), [0, 3]);
+
+        let text = session.to_text();
+        assert!(text.ends_with("- $2 $0\t2\tq1,taxes\n"));
+        assert_eq!(Session::from_text(&text).as_ref(), Ok(&session));
+        assert_eq!(Session::from_bytes(&session.to_bytes()).as_ref(), Ok(&session));
+
+        let selected = session.select(&session.tagged("taxes")).unwrap();
+        assert_eq!(selected.to_text(), "cwh-session 2\n+ 3 2\t5\tq1\n7\t7\n- $1 $0\t2\tq1,taxes\n");
+        let selected = session.select(&[2]).unwrap();
+        assert_eq!(selected.entry(0), Some(("7", 7)));
+        assert_eq!(session.select(&[1]).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn notes() {
+        let mut session = session();
+        session.entry_mut(1).unwrap().note = Some(String::from("before\tdiscount \\ 2\n"));
+        let text = session.to_text();
+        assert_eq!(text, "cwh-session 2\n+ 3 2\t5\n* $0 2\t10\t\tbefore\\tdiscount \\\\ 2\\n\n");
+        assert_eq!(Session::from_text(&text).as_ref(), Ok(&session));
+        assert_eq!(Session::from_bytes(&session.to_bytes()).as_ref(), Ok(&session));
+        assert_eq!(session.select(&[1]).unwrap().entries()[1].note, session.entries()[1].note);
+    }
+
+    #[test]
+    fn tests() {
+        let mut session = session();
+        session.push(String::from("- 0 $1"), -10);
+        session.entry_mut(1).unwrap().note = Some(String::from("doubled\nagain"));
+        assert_eq!(
+            session.to_tests(),
+            "+ 3 2\n@assert-eq $0 5\n* $0 2 ; doubled again\n@assert-eq $1 10\n- 0 $1\n@assert-eq $2 -10\n",
+        );
+    }
+
+    #[test]
+    fn divergence() {
+        let mut other = session().prefix(1);
+        assert_eq!(session().divergence(&session()), None);
+        assert_eq!(session().divergence(&other), Some(1));
+        other.push(String::from("* $0 2"), 10);
+        other.push(String::from("1"), 1);
+        assert_eq!(session().divergence(&other), Some(2));
+        assert_eq!(Session::new().divergence(&other), Some(0));
+    }
+
+    #[test]
+    fn recompute() {
+        assert_eq!(session().recompute(0, 7), Ok(vec![7, 14]));
+        assert_eq!(session().recompute(1, 7), Ok(vec![5, 7]));
+        let mut session = session();
+        session.push(String::from("/ 1 $0"), 0);
+        assert_eq!(session.recompute(0, 0).unwrap_err().code, ErrorCode::DivisionByZero);
+    }
+
+    #[test]
+    fn migration() {
+        assert_eq!(Session::from_text("+ 3   2\n\n*  $0 2\n"), Ok(session()));
+        let err = Session::from_text("+ 3 2\n/ 1 0\n").unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidSession);
+        assert!(err.message.starts_with("Cannot migrate line 2: Error[E0102]"));
+    }
+
+    #[test]
+    fn errors() {
+        let code = |text: &str| Session::from_text(text).unwrap_err().code;
+        assert_eq!(code("cwh-session 3\n+ 3 2\t5\n"), ErrorCode::UnsupportedVersion);
+        assert_eq!(code("cwh-session 1\n+ 3 2\t5\ttaxes\n"), ErrorCode::InvalidSession);
+        assert_eq!(code("cwh-session one\n"), ErrorCode::InvalidSession);
+        assert_eq!(code("cwh-session 1\n+ 3 2\n"), ErrorCode::InvalidSession);
+        assert_eq!(code("cwh-session 1\n+ 3 2\tfive\n"), ErrorCode::InvalidSession);
+    }
+}
@@ -0,0 +1,221 @@
+use std::time::Duration;
+
+use crate::notify::Notify;
+use crate::template::interpolate;
+use crate::{Error, ErrorCode};
+
+pub const SETTINGS: [(&str, &str); 7] = [
+    ("sigfigs <n | off>", "Rounds displayed results to n significant figures, history keeps exact values."),
+    ("notify-after <seconds | off>", "Notifies when an evaluation lasts at least that long."),
+    ("notify-command <command | off>", "Shell command notifying instead of notify-send."),
+    ("pager <on | off>", "Shows output taller than the terminal through $PAGER."),
+    ("grouping <, | _ | space | off>", "Groups digits of displayed results by thousands."),
+    ("on-result <'template' | off>", "Prints the template for every result, or runs it if it starts with '!'."),
+    ("editor <command | off>", "Editor of :edit-buffer, instead of $VISUAL or $EDITOR."),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    pub sigfigs: Option<u32>,
+    pub notify: Notify,
+    pub pager: bool,
+    pub grouping: Option<char>,
+    pub on_result: Option<String>,
+    pub editor: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            sigfigs: None,
+            notify: Notify::default(),
+            pager: true,
+            grouping: None,
+            on_result: None,
+            editor: None,
+        }
+    }
+}
+
+fn invalid(name: &str, value: &str) -> Error {
+    match SETTINGS.iter().find(|(usage, _)| usage.split_whitespace().next() == Some(name)) {
+        Some((usage, _)) => Error::new(
+            ErrorCode::InvalidSetting,
+            format!("Invalid value '{}', expected :set {}", value, usage),
+        ),
+        None => Error::new(ErrorCode::InvalidSetting, format!("Unknown setting '{}'.", name)),
+    }
+}
+
+fn off<T>(value: &str, parse: impl FnOnce(&str) -> Option<T>) -> Option<Option<T>> {
+    match value {
+        "off" => Some(None),
+        value => parse(value).map(Some),
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    ['\'', '"'].iter()
+        .find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote))
+        .unwrap_or(value)
+}
+
+fn on_off(value: &str) -> Option<bool> {
+    match value {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+impl Settings {
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        match name {
+            "sigfigs" => {
+                self.sigfigs = off(value, |value| value.parse().ok().filter(|n| *n > 0))
+                    .ok_or_else(|| invalid(name, value))?;
+            }
+            "notify-after" => {
+                self.notify.after = off(value, |value| value.parse().ok().map(Duration::from_secs))
+                    .ok_or_else(|| invalid(name, value))?;
+            }
+            "notify-command" => self.notify.command = off(value, |value| Some(String::from(value))).unwrap(),
+            "grouping" => {
+                self.grouping = match value {
+                    "," | "_" => value.chars().next(),
+                    "space" => Some(' '),
+                    "off" => None,
+                    _ => return Err(invalid(name, value)),
+                }
+            }
+            "on-result" => {
+                let template = unquote(value);
+                interpolate(template, |_| Ok(0)).map_err(|err| {
+                    Error::new(ErrorCode::InvalidSetting, format!("Invalid on-result template: {}", err.message))
+                })?;
+                self.on_result = off(template, |template| Some(String::from(template))).unwrap();
+            }
+            "pager" => self.pager = on_off(value).ok_or_else(|| invalid(name, value))?,
+            "editor" => self.editor = off(value, |value| Some(String::from(value))).unwrap(),
+            _ => return Err(invalid(name, value)),
+        }
+        Ok(())
+    }
+
+    pub fn describe(&self) -> Vec<String> {
+        let show = |value: Option<String>| value.unwrap_or_else(|| String::from("off"));
+        vec![
+            format!("sigfigs {}", show(self.sigfigs.map(|n| n.to_string()))),
+            format!("notify-after {}", show(self.notify.after.map(|after| after.as_secs().to_string()))),
+            format!("notify-command {}", show(self.notify.command.clone())),
+            format!("pager {}", if self.pager { "on" } else { "off" }),
+            format!("grouping {}", match self.grouping {
+                Some(' ') => String::from("space"),
+                grouping => show(grouping.map(String::from)),
+            }),
+            format!("on-result {}", show(self.on_result.as_ref().map(|template| format!("'{}'", template)))),
+            format!("editor {}", show(self.editor.clone())),
+        ]
+    }
+
+    pub fn display(&self, int: isize) -> String {
+        let text = match self.sigfigs {
+            Some(sigfigs) => round_sigfigs(int, sigfigs),
+            None => int.to_string(),
+        };
+        match self.grouping {
+            Some(separator) => group_digits(&text, separator),
+            None => text,
+        }
+    }
+}
+
+// Rounds half away from zero, computing in u128 so that rounding up the
+// largest magnitudes cannot overflow.
+pub fn round_sigfigs(int: isize, sigfigs: u32) -> String {
+    let magnitude = int.unsigned_abs() as u128;
+    let digits = magnitude.checked_ilog10().map_or(1, |log| log + 1);
+    let rounded = match digits.checked_sub(sigfigs) {
+        Some(dropped) if dropped > 0 => {
+            let factor = 10_u128.pow(dropped);
+            (magnitude / factor + u128::from(magnitude % factor * 2 >= factor)) * factor
+        }
+        _ => magnitude,
+    };
+    format!("{}{}", if int < 0 { "-" } else { "" }, rounded)
+}
+
+// Inserts the separator between every three digits of a decimal number.
+pub fn group_digits(number: &str, separator: char) -> String {
+    let (sign, digits) = number.split_at(number.len() - number.trim_start_matches('-').len());
+    let mut grouped = String::from(sign);
+    for (idx, digit) in digits.chars().enumerate() {
+        if idx > 0 && (digits.len() - idx) % 3 == 0 {
+            grouped.push(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::settings::{group_digits, round_sigfigs, Settings};
+    use crate::ErrorCode;
+
+    #[test]
+    fn rounding() {
+        assert_eq!(round_sigfigs(12345, 3), "12300");
+        assert_eq!(round_sigfigs(12350, 3), "12400");
+        assert_eq!(round_sigfigs(-99_960, 3), "-100000");
+        assert_eq!(round_sigfigs(42, 3), "42");
+        assert_eq!(round_sigfigs(0, 1), "0");
+        assert_eq!(round_sigfigs(isize::MAX, 2), "9200000000000000000");
+        assert_eq!(round_sigfigs(isize::MIN, 1), "-9000000000000000000");
+    }
+
+    #[test]
+    fn grouping() {
+        assert_eq!(group_digits("1234567", ','), "1,234,567");
+        assert_eq!(group_digits("-123456", ' '), "-123 456");
+        assert_eq!(group_digits("12", '_'), "12");
+        let settings = Settings { sigfigs: Some(2), grouping: Some(','), ..Settings::default() };
+        assert_eq!(settings.display(-1_234_567), "-1,200,000");
+    }
+
+    #[test]
+    fn set() {
+        let mut settings = Settings::default();
+        settings.set("sigfigs", "2").unwrap();
+        assert_eq!(settings.display(1234), "1200");
+        settings.set("notify-command", "say done").unwrap();
+        settings.set("pager", "off").unwrap();
+        settings.set("grouping", "space").unwrap();
+        settings.set("editor", "code --wait").unwrap();
+        assert_eq!(settings.describe(), [
+            "sigfigs 2",
+            "notify-after off",
+            "notify-command say done",
+            "pager off",
+            "grouping space",
+            "on-result off",
+            "editor code --wait",
+        ]);
+        settings.set("on-result", "'{result:x} !'").unwrap();
+        assert_eq!(settings.on_result.as_deref(), Some("{result:x} !"));
+        settings.set("sigfigs", "off").unwrap();
+        assert_eq!(settings.display(1234), "1 234");
+        let invalid = [
+            ("sigfigs", "0"),
+            ("sigfigs", "many"),
+            ("notify-after", "soon"),
+            ("pager", "yes"),
+            ("grouping", "."),
+            ("on-result", "'{result'"),
+            ("color", "on"),
+        ];
+        for (name, value) in invalid {
+            assert_eq!(settings.set(name, value).unwrap_err().code, ErrorCode::InvalidSetting);
+        }
+    }
+}
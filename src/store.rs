@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+
+use crate::{parse_line, Error, ErrorCode, Value};
+
+pub const MIN_REFERENCE: usize = 6;
+
+// FNV-1a of the canonical form, so that expressions differing only by
+// whitespace or operator aliases share a hash.
+pub fn hash(value: &Value) -> String {
+    let hash = value.to_string().bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+#[derive(Debug, Default)]
+pub struct Store {
+    expressions: BTreeMap<String, Value>,
+}
+
+impl Store {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.expressions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.expressions.is_empty()
+    }
+
+    // Returns the shortest unambiguous reference to the stored expression.
+    pub fn insert(&mut self, value: Value) -> String {
+        let hash = hash(&value);
+        self.expressions.insert(hash.clone(), value);
+        self.reference(&hash)
+    }
+
+    fn reference(&self, hash: &str) -> String {
+        let len = (MIN_REFERENCE..hash.len())
+            .find(|len| self.matching(&hash[..*len]).len() == 1)
+            .unwrap_or(hash.len());
+        format!("#{}", &hash[..len])
+    }
+
+    // Every stored expression with its shortest unambiguous reference.
+    pub fn references(&self) -> Vec<(String, &Value)> {
+        self.expressions.iter().map(|(hash, value)| (self.reference(hash), value)).collect()
+    }
+
+    fn matching<'a>(&'a self, prefix: &str) -> Vec<&'a Value> {
+        self.expressions.range(String::from(prefix)..)
+            .take_while(|(hash, _)| hash.starts_with(prefix))
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    // Resolves a reference, with or without its leading '#'.
+    pub fn get(&self, reference: &str) -> Result<&Value, Error> {
+        let prefix = reference.strip_prefix('#').unwrap_or(reference);
+        let unknown = |reason| Error::new(ErrorCode::UnknownReference, format!("Reference '#{}' {}.", prefix, reason));
+        if prefix.len() < MIN_REFERENCE {
+            return Err(unknown("is too short"));
+        }
+        match self.matching(prefix).as_slice() {
+            [value] => Ok(value),
+            [] => Err(unknown("does not match any stored expression")),
+            _ => Err(unknown("matches several stored expressions")),
+        }
+    }
+
+    // Replaces every `#hash` token of the line by the stored expression.
+    pub fn expand(&self, line: &str) -> Result<String, Error> {
+        let tokens = line.split_whitespace()
+            .map(|token| match token.starts_with('#') {
+                true => self.get(token).map(Value::to_string),
+                false => Ok(String::from(token)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tokens.join(" "))
+    }
+
+    pub fn store_line(&mut self, line: &str) -> Result<String, Error> {
+        let value = parse_line(self.expand(line)?.split_whitespace())?;
+        Ok(self.insert(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::store::{hash, Store};
+    use crate::{parse_line, ErrorCode};
+
+    #[test]
+    fn references() {
+        let mut store = Store::new();
+        let reference = store.store_line("+ $0 1").unwrap();
+        assert_eq!(reference.len(), 7);
+        assert_eq!(store.store_line("+   $0  1"), Ok(reference.clone()));
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.expand(&format!("* {} 2", reference)), Ok(String::from("* + $0 1 2")));
+        assert_eq!(store.get(&reference[1..]).unwrap().to_string(), "+ $0 1");
+
+        let nested = store.store_line(&format!("fact {}", reference)).unwrap();
+        assert_eq!(store.expand(&nested), Ok(String::from("fact + $0 1")));
+        let mut listed: Vec<_> = store.references().into_iter().map(|(reference, value)| (reference, value.to_string())).collect();
+        listed.sort();
+        let mut expected = vec![(reference, String::from("+ $0 1")), (nested, String::from("fact + $0 1"))];
+        expected.sort();
+        assert_eq!(listed, expected);
+    }
+
+    #[test]
+    fn canonical() {
+        let parse = |line: &str| parse_line(line.split_whitespace()).unwrap();
+        assert_eq!(hash(&parse("! 3")), hash(&parse("fact 3")));
+        assert_ne!(hash(&parse("- 3 2")), hash(&parse("- 2 3")));
+    }
+
+    #[test]
+    fn unknown() {
+        let mut store = Store::new();
+        store.store_line("42").unwrap();
+        let code = |reference: &str| store.get(reference).unwrap_err().code;
+        assert_eq!(code("#000000"), ErrorCode::UnknownReference);
+        assert_eq!(code(&format!("#{}", &hash(&parse_line("42".split_whitespace()).unwrap())[..5])), ErrorCode::UnknownReference);
+        assert_eq!(store.store_line("#000000").unwrap_err().code, ErrorCode::UnknownReference);
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use crate::{json, Error};
+
+// Health of a batch run, for scripts wrapping cwh to check without parsing
+// its standard error.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+    // Lines evaluated, the failing ones included.
+    pub lines: usize,
+    // Failed lines by error code.
+    pub errors: BTreeMap<&'static str, usize>,
+    pub elapsed: Duration,
+    // Length of history once the run is over.
+    pub history: usize,
+}
+
+impl Summary {
+    pub fn record(&mut self, error: Option<&Error>) {
+        self.lines += 1;
+        if let Some(err) = error {
+            self.failed(err);
+        }
+    }
+
+    // Counts an error without a line, for runs counting their lines apart.
+    pub fn failed(&mut self, error: &Error) {
+        *self.errors.entry(error.code.as_str()).or_default() += 1;
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.errors.values().sum()
+    }
+
+    pub fn to_json(&self) -> String {
+        json::object([
+            ("lines", self.lines.to_string()),
+            ("errors", self.error_count().to_string()),
+            ("errors_by_code", json::object(&self.errors)),
+            ("elapsed_us", self.elapsed.as_micros().to_string()),
+            ("history", self.history.to_string()),
+        ])
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Evaluated {} lines in {:?}, {} failed", self.lines, self.elapsed, self.error_count())?;
+        if !self.errors.is_empty() {
+            let errors: Vec<_> = self.errors.iter().map(|(code, count)| format!("{} {}", code, count)).collect();
+            write!(f, " ({})", errors.join(", "))?;
+        }
+        write!(f, ", {} results in history.", self.history)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::json::{self, Json};
+    use crate::summary::Summary;
+    use crate::{Error, ErrorCode};
+
+    #[test]
+    fn counts() {
+        let mut summary = Summary { elapsed: Duration::from_micros(1500), history: 2, ..Summary::default() };
+        let (division, unexpected) = (Error::new(ErrorCode::DivisionByZero, ""), Error::new(ErrorCode::UnexpectedInput, ""));
+        for error in [None, Some(&division), None, Some(&unexpected), Some(&division)] {
+            summary.record(error);
+        }
+        assert_eq!(summary.error_count(), 3);
+        assert_eq!(summary.to_string(), "Evaluated 5 lines in 1.5ms, 3 failed (E0001 1, E0102 2), 2 results in history.");
+        let parsed = json::parse(&summary.to_json()).unwrap();
+        assert_eq!(parsed.get("lines"), Some(&Json::Int(5)));
+        assert_eq!(parsed.get("errors_by_code").unwrap().get("E0102"), Some(&Json::Int(2)));
+        assert_eq!(parsed.get("elapsed_us"), Some(&Json::Int(1500)));
+        assert_eq!(Summary::default().to_string(), "Evaluated 0 lines in 0ns, 0 failed, 0 results in history.");
+    }
+}
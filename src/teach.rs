@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::registry::{self, Operator};
+use crate::script::{parse_string, strip_comment};
+use crate::template::interpolate;
+use crate::Value::{BinaryOperation, UnaryOperation};
+use crate::{evaluate_in, evaluate_observed, parse_line, Context, Error, ErrorCode, Observer, Value};
+
+// Templates explaining operators, every line of a rules file being an
+// operator symbol followed by a quoted template. Templates see the operands
+// as `a` and `b` and the result as `result`:
+//
+//     / "integer division truncates toward zero: {a}/{b} = {result}"
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Rules {
+    rules: Vec<(Operator, String)>,
+}
+
+fn operand_names(operator: Operator) -> &'static [&'static str] {
+    match operator {
+        Operator::Binary(_) => &["a", "b"],
+        Operator::Unary(_) => &["a"],
+    }
+}
+
+fn explain(template: &str, operator: Operator, operands: &[isize], result: isize) -> Result<String, Error> {
+    let mut names: HashMap<_, _> = operand_names(operator).iter()
+        .map(|name| String::from(*name))
+        .zip(operands.iter().copied())
+        .collect();
+    names.insert(String::from("result"), result);
+    let ctx = Context::new(&[], &names);
+    interpolate(template, |expr| evaluate_in(&parse_line(expr.split_whitespace())?, &ctx))
+}
+
+impl Rules {
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn parse(text: &str) -> Result<Rules, Error> {
+        let mut rules = Rules::default();
+        for (idx, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |message: String| {
+                Error::new(ErrorCode::InvalidConfig, format!("Line {}: {}", idx + 1, message))
+            };
+            let (symbol, template) = line.split_once(char::is_whitespace)
+                .and_then(|(symbol, rest)| Some((symbol, parse_string(rest.trim())?)))
+                .ok_or_else(|| invalid(String::from("Expected '<operator> \"<template>\"'.")))?;
+            let operator = registry::lookup(symbol)
+                .ok_or_else(|| invalid(format!("Unknown operator '{}'.", symbol)))?
+                .operator;
+            // Catches malformed templates and unknown names before any use.
+            explain(template, operator, &[1, 1], 1).map_err(|err| invalid(err.message))?;
+            rules.rules.push((operator, String::from(template)));
+        }
+        Ok(rules)
+    }
+
+    pub fn load(path: &Path) -> Result<Rules, Error> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| Error::new(ErrorCode::ReadFailed, format!("Cannot read '{}': {}.", path.display(), err)))?;
+        Rules::parse(&text).map_err(|err| Error::new(err.code, format!("{}: {}", path.display(), err.message)))
+    }
+
+    // Evaluates the expression, explaining every operation with a rule in
+    // the order they complete, the innermost first.
+    pub fn evaluate(&self, value: &Value, ctx: &Context) -> (Result<isize, Error>, Vec<String>) {
+        let mut explainer = Explainer { rules: self, operands: Vec::new(), lines: Vec::new() };
+        let result = evaluate_observed(value, ctx, &mut explainer);
+        (result, explainer.lines)
+    }
+}
+
+struct Explainer<'a> {
+    rules: &'a Rules,
+    // Results of the children of the nodes being evaluated.
+    operands: Vec<Vec<isize>>,
+    lines: Vec<String>,
+}
+
+impl Observer for Explainer<'_> {
+    fn enter(&mut self, _value: &Value) {
+        self.operands.push(Vec::new());
+    }
+
+    fn leave(&mut self, value: &Value, result: &Result<isize, Error>) {
+        let operands = self.operands.pop().expect("every node is entered before it is left");
+        let Ok(result) = result else { return };
+        if let Some(parent) = self.operands.last_mut() {
+            parent.push(*result);
+        }
+        let operator = match value {
+            BinaryOperation { operator, .. } => Operator::Binary(*operator),
+            UnaryOperation { operator, .. } => Operator::Unary(*operator),
+            _ => return,
+        };
+        for (_, template) in self.rules.rules.iter().filter(|(rule, _)| *rule == operator) {
+            // Templates were checked when parsed, only overflows remain.
+            if let Ok(line) = explain(template, operator, &operands, *result) {
+                self.lines.push(line);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::teach::Rules;
+    use crate::{parse_line, Context, ErrorCode};
+
+    #[test]
+    fn explanations() {
+        let rules = Rules::parse("; teaching\n/ \"integer division truncates toward zero: {a}/{b} = {result}\"\n\
+            ! \"{a}! = {result}\"\n").unwrap();
+        let names = HashMap::new();
+        let evaluate = |line: &str| rules.evaluate(&parse_line(line.split_whitespace()).unwrap(), &Context::new(&[7], &names));
+        assert_eq!(evaluate("+ / $0 2 fact 3"), (Ok(9), vec![
+            String::from("integer division truncates toward zero: 7/2 = 3"),
+            String::from("3! = 6"),
+        ]));
+        let (result, lines) = evaluate("fact / 6 0");
+        assert_eq!(result.unwrap_err().code, ErrorCode::DivisionByZero);
+        assert!(lines.is_empty());
+    }
+
+    #[test]
+    fn invalid() {
+        for text in ["/", "/ unquoted", "% \"{a}\"", "abs \"{b}\"", "+ \"{a\""] {
+            let err = Rules::parse(text).unwrap_err();
+            assert_eq!(err.code, ErrorCode::InvalidConfig, "{}", text);
+            assert!(err.message.starts_with("Line 1: "));
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::str::FromStr;
+
+use crate::{Error, ErrorCode};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    #[default]
+    Right,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Spec {
+    pub align: Align,
+    pub zero: bool,
+    pub width: usize,
+    pub separator: Option<char>,
+    pub radix: u32,
+    pub upper: bool,
+}
+
+impl FromStr for Spec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::new(ErrorCode::InvalidTemplate, format!("Invalid format specification '{}'.", s));
+        let mut spec = Spec { radix: 10, ..Spec::default() };
+        let mut rest = s;
+        if let Some(align) = rest.chars().next().and_then(|char| match char {
+            '<' => Some(Align::Left),
+            '^' => Some(Align::Center),
+            '>' => Some(Align::Right),
+            _ => None,
+        }) {
+            spec.align = align;
+            rest = &rest[1..];
+        }
+        if let Some(stripped) = rest.strip_prefix('0') {
+            spec.zero = true;
+            rest = stripped;
+        }
+        let digits = rest.find(|char: char| !char.is_ascii_digit()).unwrap_or(rest.len());
+        if digits > 0 {
+            spec.width = rest[..digits].parse().map_err(|_| invalid())?;
+            rest = &rest[digits..];
+        }
+        if let Some(separator) = rest.chars().next().filter(|char| matches!(char, ',' | '_')) {
+            spec.separator = Some(separator);
+            rest = &rest[1..];
+        }
+        match rest {
+            "" | "d" => {}
+            "b" => spec.radix = 2,
+            "o" => spec.radix = 8,
+            "x" => spec.radix = 16,
+            "X" => (spec.radix, spec.upper) = (16, true),
+            _ => return Err(invalid()),
+        }
+        Ok(spec)
+    }
+}
+
+impl Spec {
+    pub fn format(&self, int: isize) -> String {
+        let mut digits = Vec::new();
+        let mut magnitude = int.unsigned_abs();
+        loop {
+            let digit = char::from_digit((magnitude % self.radix as usize) as u32, self.radix).unwrap();
+            digits.push(if self.upper { digit.to_ascii_uppercase() } else { digit });
+            magnitude /= self.radix as usize;
+            if magnitude == 0 {
+                break;
+            }
+        }
+        let sign = if int < 0 { "-" } else { "" };
+        if self.zero {
+            while sign.len() + digits.len() < self.width {
+                digits.push('0');
+            }
+        }
+
+        let group = if self.radix == 10 { 3 } else { 4 };
+        let mut body = String::from(sign);
+        for (idx, digit) in digits.iter().enumerate().rev() {
+            body.push(*digit);
+            if let Some(separator) = self.separator.filter(|_| idx > 0 && idx % group == 0) {
+                body.push(separator);
+            }
+        }
+
+        let padding = self.width.saturating_sub(body.chars().count());
+        match self.align {
+            Align::Left => format!("{}{}", body, " ".repeat(padding)),
+            Align::Center => format!("{}{}{}", " ".repeat(padding / 2), body, " ".repeat(padding - padding / 2)),
+            Align::Right => format!("{}{}", " ".repeat(padding), body),
+        }
+    }
+}
+
+// Replaces every `{expr}` or `{expr:spec}` of the template by the formatted
+// result of `evaluate`.
+pub fn interpolate(
+    template: &str,
+    mut evaluate: impl FnMut(&str) -> Result<isize, Error>,
+) -> Result<String, Error> {
+    let mut out = String::new();
+    let mut chars = template.char_indices().peekable();
+    while let Some((idx, char)) = chars.next() {
+        match char {
+            '{' if chars.next_if(|(_, char)| *char == '{').is_some() => out.push('{'),
+            '}' if chars.next_if(|(_, char)| *char == '}').is_some() => out.push('}'),
+            '{' => {
+                let Some(end) = template[idx..].find('}') else {
+                    return Err(Error::new(ErrorCode::InvalidTemplate, "Expected '}' to close '{'."));
+                };
+                let field = &template[idx + 1..idx + end];
+                let (expr, spec) = match field.split_once(':') {
+                    None => (field, Spec::from_str("")?),
+                    Some((expr, spec)) => (expr, Spec::from_str(spec)?),
+                };
+                out.push_str(&spec.format(evaluate(expr)?));
+                while chars.next_if(|(next, _)| *next <= idx + end).is_some() {}
+            }
+            '}' => return Err(Error::new(ErrorCode::InvalidTemplate, "Unexpected '}', write '}}' for a brace.")),
+            char => out.push(char),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::template::{interpolate, Spec};
+    use crate::{process_line, ErrorCode};
+
+    fn format(spec: &str, int: isize) -> String {
+        Spec::from_str(spec).unwrap().format(int)
+    }
+
+    #[test]
+    fn specs() {
+        assert_eq!(format("", 42), "42");
+        assert_eq!(format("5", 42), "   42");
+        assert_eq!(format("<5", 42), "42   ");
+        assert_eq!(format("^6", -42), " -42  ");
+        assert_eq!(format("05", -42), "-0042");
+        assert_eq!(format("x", 255), "ff");
+        assert_eq!(format("X", -255), "-FF");
+        assert_eq!(format("b", 5), "101");
+        assert_eq!(format("o", 8), "10");
+        assert_eq!(format(",", 1234567), "1,234,567");
+        assert_eq!(format(",", -123456), "-123,456");
+        assert_eq!(format(">12_b", 37), "     10_0101");
+        assert_eq!(format("d", isize::MIN), isize::MIN.to_string());
+        for spec in ["x5", "<>", "5,,", "q"] {
+            assert_eq!(Spec::from_str(spec).unwrap_err().code, ErrorCode::InvalidTemplate);
+        }
+    }
+
+    #[test]
+    fn interpolation() {
+        let evaluate = |expr: &str| process_line(String::from(expr), &[]);
+        assert_eq!(interpolate("Total: {+ 3 2} EUR", evaluate), Ok(String::from("Total: 5 EUR")));
+        assert_eq!(interpolate("{* 16 16:x}|{1:>3}|{{}}", evaluate), Ok(String::from("100|  1|{}")));
+        assert_eq!(interpolate("{/ 1 0}", evaluate).unwrap_err().code, ErrorCode::DivisionByZero);
+        assert_eq!(interpolate("{+ 3 2", evaluate).unwrap_err().code, ErrorCode::InvalidTemplate);
+        assert_eq!(interpolate("3 }", evaluate).unwrap_err().code, ErrorCode::InvalidTemplate);
+    }
+}
@@ -0,0 +1,129 @@
+use std::time::{Duration, Instant};
+
+use crate::Value::{BinaryOperation, UnaryOperation};
+use crate::{evaluate_observed, Context, Error, ErrorCode, Observer, Value};
+
+pub const BAR_WIDTH: usize = 10;
+
+// Evaluation of one node, its time including the time of its children.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trace {
+    pub label: String,
+    pub result: Result<isize, ErrorCode>,
+    pub elapsed: Duration,
+    pub children: Vec<Trace>,
+}
+
+#[derive(Default)]
+struct Tracer {
+    // Nodes being evaluated, with the traces of their finished children.
+    stack: Vec<(Instant, Vec<Trace>)>,
+    root: Option<Trace>,
+}
+
+fn label(value: &Value) -> String {
+    match value {
+        BinaryOperation { operator, .. } => operator.to_string(),
+        UnaryOperation { operator, .. } => operator.to_string(),
+        leaf => leaf.to_string(),
+    }
+}
+
+impl Observer for Tracer {
+    fn enter(&mut self, _value: &Value) {
+        self.stack.push((Instant::now(), Vec::new()));
+    }
+
+    fn leave(&mut self, value: &Value, result: &Result<isize, Error>) {
+        let (started, children) = self.stack.pop().expect("every node is entered before it is left");
+        let trace = Trace {
+            label: label(value),
+            result: result.as_ref().map(|int| *int).map_err(|err| err.code),
+            elapsed: started.elapsed(),
+            children,
+        };
+        match self.stack.last_mut() {
+            Some((_, siblings)) => siblings.push(trace),
+            None => self.root = Some(trace),
+        }
+    }
+}
+
+pub fn trace(value: &Value, ctx: &Context) -> Trace {
+    let mut tracer = Tracer::default();
+    let _ = evaluate_observed(value, ctx, &mut tracer);
+    tracer.root.expect("the root is left last")
+}
+
+// Renders the tree of nodes with their results and times, the bar showing
+// the share of the total time spent in each subtree.
+pub fn render(trace: &Trace) -> Vec<String> {
+    let mut lines = Vec::new();
+    render_into(trace, 0, trace.elapsed, &mut lines);
+    lines
+}
+
+fn render_into(trace: &Trace, depth: usize, total: Duration, lines: &mut Vec<String>) {
+    let result = match &trace.result {
+        Ok(int) => int.to_string(),
+        Err(code) => format!("Error[{}]", code),
+    };
+    let share = match total.is_zero() {
+        true => 1.0,
+        false => trace.elapsed.as_secs_f64() / total.as_secs_f64(),
+    };
+    let bar = "#".repeat((share * BAR_WIDTH as f64).round() as usize);
+    let line = format!(
+        "{}{} => {}  {:?} {:>3.0}% {}",
+        "  ".repeat(depth), trace.label, result, trace.elapsed, share * 100.0, bar,
+    );
+    lines.push(String::from(line.trim_end()));
+    for child in &trace.children {
+        render_into(child, depth + 1, total, lines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use crate::trace::{render, trace, Trace};
+    use crate::{parse_line, Context, ErrorCode};
+
+    fn strip_times(trace: &mut Trace) {
+        trace.elapsed = Duration::ZERO;
+        trace.children.iter_mut().for_each(strip_times);
+    }
+
+    fn leaf(label: &str, int: isize, micros: u64) -> Trace {
+        Trace { label: String::from(label), result: Ok(int), elapsed: Duration::from_micros(micros), children: vec![] }
+    }
+
+    #[test]
+    fn tree() {
+        let value = parse_line("/ fact 5 - $0 2".split_whitespace()).unwrap();
+        let mut traced = trace(&value, &Context::new(&[2], &HashMap::new()));
+        strip_times(&mut traced);
+        assert_eq!(traced.label, "/");
+        assert_eq!(traced.result, Err(ErrorCode::DivisionByZero));
+        assert_eq!(traced.children[0].children, [leaf("5", 5, 0)]);
+        assert_eq!(traced.children[1].result, Ok(0));
+        assert_eq!(traced.children[1].children, [leaf("$0", 2, 0), leaf("2", 2, 0)]);
+    }
+
+    #[test]
+    fn rendering() {
+        let trace = Trace {
+            label: String::from("+"),
+            result: Ok(7),
+            elapsed: Duration::from_micros(10),
+            children: vec![leaf("3", 3, 2), leaf("4", 4, 5)],
+        };
+        assert_eq!(render(&trace), [
+            "+ => 7  10µs 100% ##########",
+            "  3 => 3  2µs  20% ##",
+            "  4 => 4  5µs  50% #####",
+        ]);
+    }
+}
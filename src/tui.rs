@@ -0,0 +1,507 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+
+use crate::grid::{self, Cell};
+use crate::lexer::IncrementalLexer;
+use crate::repl::Repl;
+use crate::{evaluate_in, pager, Context, Error, ErrorCode, Value};
+
+const SIDE_WIDTH: usize = 30;
+const CELL_WIDTH: usize = 10;
+const RED: &str = "\x1b[31m";
+const BOLD: &str = "\x1b[1m";
+const REVERSE: &str = "\x1b[7m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+    Char(char),
+    Backspace,
+    Enter,
+    Up,
+    Down,
+    Left,
+    Right,
+    // Switches between the transcript and the grid.
+    ToggleGrid,
+    PageUp,
+    PageDown,
+    Quit,
+}
+
+// Decodes the keys read from a terminal in raw mode, ignoring unknown
+// escape sequences.
+pub fn parse_keys(text: &str) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(char) = chars.next() {
+        let key = match char {
+            '\x03' | '\x04' => Key::Quit,
+            '\x07' => Key::ToggleGrid,
+            '\x7f' | '\x08' => Key::Backspace,
+            '\r' | '\n' => Key::Enter,
+            '\x1b' => {
+                if chars.next_if_eq(&'[').is_none() {
+                    continue;
+                }
+                let mut sequence = String::new();
+                while let Some(char) =
+                    chars.next_if(|char| !char.is_ascii_alphabetic() && *char != '~')
+                {
+                    sequence.push(char);
+                }
+                sequence.extend(chars.next());
+                match sequence.as_str() {
+                    "A" => Key::Up,
+                    "B" => Key::Down,
+                    "C" => Key::Right,
+                    "D" => Key::Left,
+                    "5~" => Key::PageUp,
+                    "6~" => Key::PageDown,
+                    _ => continue,
+                }
+            }
+            char if char.is_control() => continue,
+            char => Key::Char(char),
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Entry {
+    Input(String),
+    Result(usize, String),
+    Output(String),
+    Error(String),
+}
+
+// State of the full-screen interface, kept apart from the terminal so that
+// it can be rendered to lines.
+pub struct Tui {
+    pub repl: Repl,
+    pub input: String,
+    // Tokens of the input, re-lexed from the first change on every key to
+    // preview its result.
+    lexer: IncrementalLexer,
+    transcript: Vec<Entry>,
+    // Lines scrolled back from the bottom of the transcript.
+    scroll: usize,
+    // The last input when it failed, highlighted above the prompt.
+    failed: Option<(String, String)>,
+    // The selected cell while the grid is shown.
+    selected: Option<Cell>,
+}
+
+impl Tui {
+    pub fn new(repl: Repl) -> Self {
+        Tui {
+            repl,
+            input: String::new(),
+            lexer: IncrementalLexer::new(),
+            transcript: Vec::new(),
+            scroll: 0,
+            failed: None,
+            selected: None,
+        }
+    }
+
+    // Returns false once the interface should close.
+    pub fn key(&mut self, key: Key, page: usize) -> bool {
+        if let Some(cell) = self.selected {
+            return self.grid_key(cell, key);
+        }
+        match key {
+            Key::Char(char) => self.input.push(char),
+            Key::Backspace => {
+                self.input.pop();
+            }
+            Key::Enter => self.submit(),
+            Key::Up => self.scroll = (self.scroll + 1).min(self.transcript.len()),
+            Key::Down => self.scroll = self.scroll.saturating_sub(1),
+            Key::PageUp => self.scroll = (self.scroll + page).min(self.transcript.len()),
+            Key::PageDown => self.scroll = self.scroll.saturating_sub(page),
+            Key::Left | Key::Right => {}
+            Key::ToggleGrid => self.select(Cell { row: 0, column: 0 }),
+            Key::Quit => return false,
+        }
+        self.lexer.update(&self.input);
+        true
+    }
+
+    // Result of the operation being typed, before it is submitted. Commands
+    // and lone values have none.
+    fn preview(&self) -> Option<String> {
+        if self.selected.is_some() || self.input.trim_start().starts_with(':') {
+            return None;
+        }
+        let value = self.lexer.parse().ok()?;
+        if !matches!(
+            value,
+            Value::BinaryOperation { .. } | Value::UnaryOperation { .. }
+        ) {
+            return None;
+        }
+        let int = evaluate_in(
+            &value,
+            &Context::new(self.repl.session.history(), &HashMap::new()),
+        )
+        .ok()?;
+        Some(self.repl.settings.display(int))
+    }
+
+    // In the grid, the arrows select a cell, loading its expression for
+    // editing, and enter sets it.
+    fn grid_key(&mut self, cell: Cell, key: Key) -> bool {
+        let Cell { row, column } = cell;
+        match key {
+            Key::Char(char) => self.input.push(char),
+            Key::Backspace => {
+                self.input.pop();
+            }
+            Key::Enter => {
+                self.failed = self
+                    .repl
+                    .grid
+                    .set(cell, &self.input)
+                    .err()
+                    .map(|err| (self.input.clone(), err.to_string()));
+            }
+            Key::Up => self.select(Cell {
+                row: row.saturating_sub(1),
+                column,
+            }),
+            Key::Down => self.select(Cell {
+                row: (row + 1).min(grid::ROWS - 1),
+                column,
+            }),
+            Key::Left => self.select(Cell {
+                row,
+                column: column.saturating_sub(1),
+            }),
+            Key::Right => self.select(Cell {
+                row,
+                column: (column + 1).min(grid::COLUMNS - 1),
+            }),
+            Key::PageUp | Key::PageDown => {}
+            Key::ToggleGrid => {
+                self.selected = None;
+                self.input.clear();
+            }
+            Key::Quit => return false,
+        }
+        self.lexer.update(&self.input);
+        true
+    }
+
+    fn select(&mut self, cell: Cell) {
+        self.selected = Some(cell);
+        self.input = String::from(self.repl.grid.input(cell).unwrap_or_default());
+        self.lexer.update(&self.input);
+    }
+
+    fn submit(&mut self) {
+        let line = std::mem::take(&mut self.input);
+        if line.trim().is_empty() {
+            return;
+        }
+        let response = self.repl.handle(&line);
+        self.transcript.push(Entry::Input(line.clone()));
+        let mut results = response.results.into_iter().peekable();
+        for (idx, line) in response.lines.into_iter().enumerate() {
+            self.transcript
+                .push(match results.next_if(|pushed| pushed.line == idx) {
+                    Some(pushed) => Entry::Result(pushed.index, line),
+                    None => Entry::Output(line),
+                });
+        }
+        self.failed = response.error.map(|err| (line, err.to_string()));
+        if let Some((_, err)) = &self.failed {
+            self.transcript.push(Entry::Error(err.clone()));
+        }
+        self.scroll = 0;
+    }
+
+    fn side_panel(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("Session {}", self.repl.name),
+            String::new(),
+            String::from("Stack"),
+        ];
+        lines.extend(
+            self.repl
+                .stack
+                .iter()
+                .rev()
+                .map(|int| format!("  {}", self.repl.settings.display(*int))),
+        );
+        lines.push(String::new());
+        lines.push(String::from("Stored"));
+        lines.extend(
+            self.repl
+                .store
+                .references()
+                .into_iter()
+                .map(|(reference, value)| format!("  {} {}", reference, value)),
+        );
+        lines
+    }
+
+    // The transcript on the left with the bindings on the right, or the grid,
+    // the failed input and its error in red and the prompt on the last line.
+    pub fn render(&self, rows: usize, columns: usize) -> Vec<String> {
+        let height = rows.saturating_sub(if self.failed.is_some() { 4 } else { 2 });
+        let (mut lines, prompt) = match self.selected {
+            None => (self.render_transcript(height, columns), String::from("#")),
+            Some(cell) => (
+                self.render_grid(cell, height, columns),
+                format!("{}>", cell),
+            ),
+        };
+        lines.push("-".repeat(columns));
+        if let Some((input, err)) = &self.failed {
+            lines.push(format!(
+                "{}{}{}",
+                RED,
+                fit(&format!("! {}", input), columns),
+                RESET
+            ));
+            lines.push(format!("{}{}{}", RED, fit(err, columns), RESET));
+        }
+        let prompt = match self.preview() {
+            Some(preview) => format!("{} {}  = {}", prompt, self.input, preview),
+            None => format!("{} {}", prompt, self.input),
+        };
+        lines.push(fit(&prompt, columns));
+        lines
+    }
+
+    // Scrolls the grid so that the selected cell is visible.
+    fn render_grid(&self, selected: Cell, height: usize, columns: usize) -> Vec<String> {
+        let visible_columns = (columns.saturating_sub(3) / (CELL_WIDTH + 1)).max(1);
+        let visible_rows = height.saturating_sub(1).max(1);
+        let first = Cell {
+            row: selected.row.saturating_sub(visible_rows - 1),
+            column: selected.column.saturating_sub(visible_columns - 1),
+        };
+        let last_column = (first.column + visible_columns).min(grid::COLUMNS);
+        let mut header = String::from("   ");
+        for column in first.column..last_column {
+            header.push_str(&format!(" {:>CELL_WIDTH$}", Cell::column_name(column)));
+        }
+        let mut lines = vec![fit(&header, columns)];
+        for row in (first.row..grid::ROWS).take(visible_rows) {
+            let mut line = format!("{:>3}", row + 1);
+            for column in first.column..last_column {
+                let cell = Cell { row, column };
+                let text = format!(
+                    "{:>CELL_WIDTH$}",
+                    fit(&self.repl.show_cell(cell), CELL_WIDTH)
+                );
+                match cell == selected {
+                    true => line.push_str(&format!(" {}{}{}", REVERSE, text, RESET)),
+                    false => line.push_str(&format!(" {}", text)),
+                }
+            }
+            lines.push(line);
+        }
+        lines.resize(height, String::new());
+        lines
+    }
+
+    fn render_transcript(&self, height: usize, columns: usize) -> Vec<String> {
+        let side = SIDE_WIDTH.min(columns / 3);
+        let main = columns.saturating_sub(side + 3);
+        let end = self.transcript.len() - self.scroll.min(self.transcript.len());
+        let visible = &self.transcript[end.saturating_sub(height)..end];
+        let side_lines = self.side_panel();
+        let mut lines = Vec::new();
+        for row in 0..height {
+            let (text, color) = match visible.get(row) {
+                None => (String::new(), ""),
+                Some(Entry::Input(input)) => (format!("# {}", input), BOLD),
+                Some(Entry::Result(idx, display)) => (format!("${} = {}", idx, display), ""),
+                Some(Entry::Output(line)) => (line.clone(), ""),
+                Some(Entry::Error(err)) => (err.clone(), RED),
+            };
+            let text = fit(&text, main);
+            let side_text = fit(side_lines.get(row).map_or("", String::as_str), side);
+            let padding = " ".repeat(main - text.chars().count());
+            let text = match color {
+                "" => text,
+                color => format!("{}{}{}", color, text, RESET),
+            };
+            lines.push(format!("{}{} | {}", text, padding, side_text));
+        }
+        lines
+    }
+}
+
+fn fit(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+fn stty(args: &[&str]) -> Result<String, Error> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .map_err(|err| Error::new(ErrorCode::Unavailable, format!("Cannot run stty: {}.", err)))?;
+    if !output.status.success() {
+        return Err(Error::new(
+            ErrorCode::Unavailable,
+            "The terminal does not support raw mode.",
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// Runs the interface in the alternate screen of the terminal, restoring its
+// mode when leaving.
+pub fn run(repl: Repl) -> Result<(), Error> {
+    let saved = stty(&["-g"])?;
+    stty(&["raw", "-echo"])?;
+    let mut tui = Tui::new(repl);
+    let mut stdout = io::stdout();
+    let mut buffer = [0; 64];
+    print!("\x1b[?1049h");
+    loop {
+        let (rows, columns) = pager::terminal_size();
+        let screen = tui.render(rows, columns).join("\r\n");
+        print!("\x1b[2J\x1b[H{}", screen);
+        stdout.flush().unwrap();
+        let read = match io::stdin().read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => read,
+        };
+        let keys = parse_keys(&String::from_utf8_lossy(&buffer[..read]));
+        if !keys.into_iter().all(|key| tui.key(key, rows / 2)) {
+            break;
+        }
+    }
+    print!("\x1b[?1049l");
+    stdout.flush().unwrap();
+    stty(&[&saved]).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::repl::Repl;
+    use crate::teach::Rules;
+    use crate::tui::{parse_keys, Key, Tui};
+    use crate::TrailingPolicy;
+
+    #[test]
+    fn keys() {
+        assert_eq!(
+            parse_keys("a\x7f\r\x1b[A\x1b[D\x1b[6~\x1b[1;5C\x07\x04"),
+            [
+                Key::Char('a'),
+                Key::Backspace,
+                Key::Enter,
+                Key::Up,
+                Key::Left,
+                Key::PageDown,
+                Key::ToggleGrid,
+                Key::Quit,
+            ]
+        );
+    }
+
+    #[test]
+    fn render() {
+        let mut tui = Tui::new(Repl::new(TrailingPolicy::Reject));
+        for key in parse_keys("+ 3 2\r:push 7\r/ $0 0\r") {
+            assert!(tui.key(key, 2));
+        }
+        let lines = tui.render(10, 40);
+        let pane = |text: &str, side: &str| format!("{:<24} | {}", text, side);
+        assert_eq!(lines.len(), 10);
+        assert_eq!(
+            lines[0],
+            format!("\x1b[1m# + 3 2\x1b[0m{} | Session main", " ".repeat(17))
+        );
+        assert_eq!(lines[1], pane("$0 = 5", ""));
+        assert_eq!(lines[3], pane("Stack: [7]", "  7"));
+        assert_eq!(
+            lines[5],
+            format!("\x1b[31mError[E0102]: Division b\x1b[0m | Stored")
+        );
+        assert_eq!(lines[6], "-".repeat(40));
+        assert_eq!(lines[7], "\x1b[31m! / $0 0\x1b[0m");
+        assert_eq!(lines[9], "# ");
+
+        tui.key(Key::Up, 2);
+        tui.key(Key::Char('1'), 2);
+        let lines = tui.render(10, 40);
+        assert_eq!(lines[5], pane("", "Stored"));
+        assert_eq!(lines[9], "# 1");
+    }
+
+    #[test]
+    fn shrinking_sessions() {
+        let mut tui = Tui::new(Repl::new(TrailingPolicy::Reject));
+        tui.repl.rules = Rules::parse("+ \"{a} plus {b}\"").unwrap();
+        for key in parse_keys("1\r2\r+ $0 $1\r:branch 0 first\r") {
+            assert!(tui.key(key, 2));
+        }
+        let lines = tui.render(10, 60);
+        let pane = |text: &str, side: &str| format!("{:<37} | {}", text, side);
+        assert_eq!(lines[0], pane("$0 = 1", "Session first"));
+        assert_eq!(lines[4], pane("1 plus 2", "Stored"));
+        assert_eq!(lines[5], pane("$2 = 3", ""));
+        assert_eq!(lines[7], pane("Switched to new session 'first' with", ""));
+    }
+
+    #[test]
+    fn preview() {
+        let mut tui = Tui::new(Repl::new(TrailingPolicy::Reject));
+        for key in parse_keys("+ 3 2\r* $0 ") {
+            tui.key(key, 2);
+        }
+        assert_eq!(tui.render(4, 40)[3], "# * $0 ");
+        tui.key(Key::Char('2'), 2);
+        assert_eq!(tui.render(4, 40)[3], "# * $0 2  = 10");
+        tui.key(Key::Char('0'), 2);
+        assert_eq!(tui.render(4, 40)[3], "# * $0 20  = 100");
+        for key in parse_keys("\x7f\x7f0") {
+            tui.key(key, 2);
+        }
+        assert_eq!(tui.render(4, 40)[3], "# * $0 0  = 0");
+        tui.key(Key::Char(' '), 2);
+        tui.key(Key::Char('1'), 2);
+        assert_eq!(tui.render(4, 40)[3], "# * $0 0 1");
+
+        // The grid replaces the input, which leaves nothing to preview.
+        for key in parse_keys("\x07* 2 3\r\x07\x07\x07") {
+            tui.key(key, 2);
+        }
+        assert_eq!(tui.render(4, 40)[3], "# ");
+    }
+
+    #[test]
+    fn grid() {
+        let mut tui = Tui::new(Repl::new(TrailingPolicy::Reject));
+        for key in parse_keys("\x077\r\x1b[B* A1 2\r\x1b[C") {
+            assert!(tui.key(key, 2));
+        }
+        let lines = tui.render(6, 30);
+        assert_eq!(
+            lines,
+            [
+                String::from("             A          B"),
+                format!("  1 {:>10} {:>10}", 7, ""),
+                format!("  2 {:>10} \x1b[7m{:>10}\x1b[0m", 14, ""),
+                format!("  3 {:>10} {:>10}", "", ""),
+                "-".repeat(30),
+                String::from("B2> "),
+            ]
+        );
+        tui.key(Key::Left, 2);
+        assert_eq!(tui.input, "* A1 2");
+        tui.key(Key::ToggleGrid, 2);
+        assert_eq!(tui.render(6, 30)[5], "# ");
+    }
+}